@@ -0,0 +1,182 @@
+use crate::cache::SummaryTree;
+use crate::git::RepositoryContent;
+use crate::gpt::{ChatMessage, ChatSettings};
+use crate::prompts::{AskPrompt, AskResponse};
+use eyre::{eyre, Error};
+use std::collections::HashMap;
+
+/// Words that are too common to say anything about where an answer lives.
+const STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "how", "what", "where", "which", "who", "why", "does", "this", "that",
+    "with", "from", "are", "was", "its", "into", "there", "their", "handled", "used",
+];
+
+/// The number of lines included in each raw file snippet.
+const SNIPPET_LINES: usize = 40;
+
+#[derive(Debug)]
+pub struct ContextItem {
+    pub path: String,
+
+    pub text: String,
+
+    pub score: usize,
+}
+
+/// Splits a question into lowercase search terms, dropping short and common words.
+pub fn terms(question: &str) -> Vec<String> {
+    question
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .map(|t| t.to_lowercase())
+        .filter(|t| t.len() > 2 && !STOP_WORDS.contains(&t.as_str()))
+        .collect()
+}
+
+fn hits(text: &str, terms: &[String]) -> usize {
+    let text = text.to_lowercase();
+
+    terms.iter().map(|t| text.matches(t.as_str()).count()).sum()
+}
+
+/// Returns the window of `SNIPPET_LINES` lines containing the most terms,
+/// along with its 1-based starting line.
+fn snippet(content: &str, terms: &[String]) -> (usize, String) {
+    let lines = content.lines().collect::<Vec<&str>>();
+    let line_hits = lines.iter().map(|l| hits(l, terms)).collect::<Vec<usize>>();
+
+    let mut best = (0, 0);
+    for start in 0..lines.len().saturating_sub(SNIPPET_LINES) + 1 {
+        let end = (start + SNIPPET_LINES).min(lines.len());
+        let score = line_hits[start..end].iter().sum::<usize>();
+
+        if score > best.1 {
+            best = (start, score);
+        }
+    }
+
+    let end = (best.0 + SNIPPET_LINES).min(lines.len());
+
+    (best.0 + 1, lines[best.0..end].join("\n"))
+}
+
+/// Ranks the folder summaries, file summaries and file contents against the
+/// question, returning at most `limit` context items.
+pub fn retrieve(
+    tree: &SummaryTree,
+    contents: &RepositoryContent,
+    question: &str,
+    limit: usize,
+) -> Vec<ContextItem> {
+    let terms = terms(question);
+    let mut items = vec![];
+
+    let mut file_summaries = HashMap::new();
+    for (name, folder) in &tree.folders {
+        let score = hits(name, &terms) * 3 + hits(&folder.summary, &terms);
+
+        if score > 0 {
+            items.push(ContextItem {
+                path: name.clone(),
+                text: format!("### folder: {}\n{}\n", name, folder.summary),
+                score,
+            });
+        }
+
+        for file in &folder.files {
+            file_summaries.insert(file.name.as_str(), file.summary.as_str());
+        }
+    }
+
+    for folder in contents.folders.values() {
+        for (name, file) in &folder.files {
+            let summary = file_summaries
+                .get(name.as_str())
+                .copied()
+                .unwrap_or_default();
+            let score = hits(name, &terms) * 3
+                + hits(summary, &terms) * 2
+                + hits(&file.content, &terms).min(10);
+
+            if score == 0 {
+                continue;
+            }
+
+            let (start, lines) = snippet(&file.content, &terms);
+            let end = start + lines.lines().count().saturating_sub(1);
+
+            items.push(ContextItem {
+                path: name.clone(),
+                text: format!(
+                    "### file: {}\nsummary: {}\nlines {}-{}:\n```\n{}\n```\n",
+                    name, summary, start, end, lines
+                ),
+                score,
+            });
+        }
+    }
+
+    items.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.path.cmp(&b.path)));
+    items.truncate(limit);
+
+    items
+}
+
+/// Answers questions about a summarized repository, keeping the previous
+/// turns so that follow-up questions have context.
+#[derive(Debug)]
+pub struct Conversation {
+    pub open_ai_key: String,
+
+    pub settings: ChatSettings,
+
+    pub summaries: SummaryTree,
+
+    pub contents: RepositoryContent,
+
+    pub history: Vec<ChatMessage>,
+
+    pub limit: usize,
+}
+
+impl Conversation {
+    pub fn new(
+        open_ai_key: &str,
+        settings: ChatSettings,
+        summaries: SummaryTree,
+        contents: RepositoryContent,
+    ) -> Self {
+        Conversation {
+            open_ai_key: open_ai_key.to_string(),
+            settings,
+            summaries,
+            contents,
+            history: vec![],
+            limit: 8,
+        }
+    }
+
+    pub async fn ask(&mut self, question: &str) -> Result<AskResponse, Error> {
+        let items = retrieve(&self.summaries, &self.contents, question, self.limit);
+
+        let mut context = format!("### repository\n{}\n", self.summaries.summary);
+        for item in &items {
+            context.push_str(&item.text);
+        }
+
+        let prompt = AskPrompt::new(question, &context);
+
+        let answer = prompt
+            .send(&self.open_ai_key, &self.settings, &self.history)
+            .await
+            .map_err(|e| eyre!("failed to answer question: {}", e))?;
+
+        self.history.push(ChatMessage::user(question));
+        self.history.push(ChatMessage::assistant(&format!(
+            "{}\nSources: {}",
+            answer.answer,
+            answer.sources.join(", ")
+        )));
+
+        Ok(answer)
+    }
+}
@@ -0,0 +1,368 @@
+use crate::git::RepositoryContent;
+use crate::index::Index;
+use crate::manifests::Dependency;
+use crate::prompts::{FileSummaryResponse, FolderWideSummaryResponse};
+use eyre::{eyre, Error, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CONTENTS_FILE: &str = "contents.json";
+
+const SUMMARIES_FILE: &str = "summaries.json";
+
+const INDEX_FILE: &str = "index.json";
+
+const JOURNAL_FILE: &str = "journal.jsonl";
+
+/// The maximum length of the error of a failure.
+const MAX_FAILURE_ERROR: usize = 300;
+
+/// The maximum length of a one line summary.
+const MAX_ONE_LINE: usize = 80;
+
+/// Shortens a summary to its first sentence, cut to `MAX_ONE_LINE` characters.
+pub fn one_line(summary: &str) -> String {
+    let sentence = summary.split(". ").next().unwrap_or_default().trim();
+
+    if sentence.chars().count() > MAX_ONE_LINE {
+        format!(
+            "{}...",
+            sentence
+                .chars()
+                .take(MAX_ONE_LINE)
+                .collect::<String>()
+                .trim_end()
+        )
+    } else {
+        sentence.to_string()
+    }
+}
+
+/// Normalizes generated markdown so the same summaries always render to
+/// the same bytes: Unix line endings, no trailing whitespace, no runs of
+/// blank lines and a single newline at the end.
+pub fn normalize(text: &str) -> String {
+    let mut out = String::new();
+    let mut blank = false;
+
+    for line in text.replace("\r\n", "\n").lines() {
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            blank = !out.is_empty();
+            continue;
+        }
+
+        if blank {
+            out.push('\n');
+            blank = false;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+
+    out
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct FolderSummary {
+    pub summary: String,
+
+    pub files: Vec<FileSummaryResponse>,
+}
+
+/// What a failed summary was of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureKind {
+    File,
+
+    Folder,
+
+    Dependencies,
+
+    Repository,
+}
+
+/// A summary that failed, recorded instead of stopping the run when keeping going.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Failure {
+    pub kind: FailureKind,
+
+    /// The path of the file or folder, empty for the whole repository.
+    pub path: String,
+
+    /// The error, on one line and cut to `MAX_FAILURE_ERROR` characters.
+    pub error: String,
+}
+
+impl Failure {
+    pub fn new(kind: FailureKind, path: &str, error: &Error) -> Failure {
+        let error = error
+            .to_string()
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        Failure {
+            kind,
+            path: path.to_string(),
+            error: if error.chars().count() > MAX_FAILURE_ERROR {
+                format!(
+                    "{}...",
+                    error.chars().take(MAX_FAILURE_ERROR).collect::<String>()
+                )
+            } else {
+                error
+            },
+        }
+    }
+}
+
+/// The summaries produced by a `sum` run, keyed by folder path.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct SummaryTree {
+    pub summary: String,
+
+    pub folders: BTreeMap<String, FolderSummary>,
+
+    /// The major dependencies of the repository and what they are used for.
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+
+    /// What failed to be summarized when keeping going.
+    #[serde(default)]
+    pub failures: Vec<Failure>,
+}
+
+impl SummaryTree {
+    pub fn insert_folder(
+        &mut self,
+        name: &str,
+        folder_summary: FolderWideSummaryResponse,
+        file_summaries: Vec<FileSummaryResponse>,
+    ) {
+        self.folders.insert(
+            name.to_string(),
+            FolderSummary {
+                summary: folder_summary.summary,
+                files: file_summaries,
+            },
+        );
+    }
+}
+
+/// On-disk storage for fetched repository contents and their summaries,
+/// located at `<cache dir>/<username>/<repo>/<branch>`, where the cache
+/// dir defaults to `~/.gitsum`.
+#[derive(Debug)]
+pub struct Cache {
+    pub path: PathBuf,
+}
+
+/// A repository with cached summaries.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct CachedRepository {
+    pub username: String,
+
+    pub repo: String,
+
+    pub branch: String,
+}
+
+/// Checks that a repository and reference are named the way GitHub allows
+/// before they are used in a cache path: usernames of letters, digits and
+/// hyphens, repositories of letters, digits, `.`, `-` and `_`, and references
+/// following `git check-ref-format`.
+pub fn validate(username: &str, repo: &str, reference: &str) -> Result<(), Error> {
+    let valid_username = !username.is_empty()
+        && username.len() <= 39
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if !valid_username {
+        return Err(eyre!("{} is not a valid GitHub username", username));
+    }
+
+    let valid_repo = !repo.is_empty()
+        && repo.len() <= 100
+        && repo != "."
+        && repo != ".."
+        && repo
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+    if !valid_repo {
+        return Err(eyre!("{} is not a valid GitHub repository name", repo));
+    }
+
+    let valid_reference = !reference.is_empty()
+        && !reference.contains("..")
+        && !reference.contains("@{")
+        && !reference.ends_with(".lock")
+        && !reference.ends_with('.')
+        && reference
+            .split('/')
+            .all(|part| !part.is_empty() && !part.starts_with('.'))
+        && !reference
+            .chars()
+            .any(|c| c.is_control() || matches!(c, ' ' | '~' | '^' | ':' | '?' | '*' | '[' | '\\'));
+    if !valid_reference {
+        return Err(eyre!("{} is not a valid branch, tag or commit", reference));
+    }
+
+    Ok(())
+}
+
+/// Encodes one part of a cache path as a single directory name, so that
+/// branches such as `feature/x` aren't nested and no part can name a
+/// directory outside the cache.
+fn encode(part: &str) -> String {
+    let mut out = String::new();
+
+    for (i, c) in part.chars().enumerate() {
+        match c {
+            '%' | '/' | '\\' | ':' => out.push_str(&format!("%{:02X}", c as u32)),
+            '.' if i == 0 => out.push_str("%2E"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Reverses `encode`.
+fn decode(name: &str) -> String {
+    let mut out = String::new();
+    let mut rest = name;
+
+    while let Some(i) = rest.find('%') {
+        out.push_str(&rest[..i]);
+
+        let byte = rest
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match byte {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[i + 3..];
+            }
+            None => {
+                out.push('%');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Returns the names of the directories in `dir`, or nothing if it can't be read.
+fn subdirectories(dir: &Path) -> Vec<String> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl Cache {
+    /// Lists the repositories in `cache_dir` that have been summarized, in order.
+    pub fn list(cache_dir: &Path) -> Vec<CachedRepository> {
+        let mut repositories = vec![];
+
+        for username in subdirectories(cache_dir) {
+            for repo in subdirectories(&cache_dir.join(&username)) {
+                for branch in subdirectories(&cache_dir.join(&username).join(&repo)) {
+                    let (username, repo, branch) =
+                        (decode(&username), decode(&repo), decode(&branch));
+                    let cache = Cache::new(cache_dir, &username, &repo, &branch);
+
+                    if cache.path.join(SUMMARIES_FILE).is_file() {
+                        repositories.push(CachedRepository {
+                            username,
+                            repo,
+                            branch,
+                        });
+                    }
+                }
+            }
+        }
+        repositories.sort();
+
+        repositories
+    }
+
+    /// The cache of a branch, in `cache_dir/username/repo/branch` with each
+    /// part encoded as a single directory name.
+    pub fn new(cache_dir: &Path, username: &str, repo: &str, branch: &str) -> Cache {
+        Cache {
+            path: cache_dir
+                .join(encode(username))
+                .join(encode(repo))
+                .join(encode(branch)),
+        }
+    }
+
+    pub fn save_contents(&self, contents: &RepositoryContent) -> Result<(), Error> {
+        self.write(CONTENTS_FILE, contents)
+    }
+
+    pub fn load_contents(&self) -> Result<RepositoryContent, Error> {
+        self.read(CONTENTS_FILE)
+    }
+
+    pub fn save_summaries(&self, summaries: &SummaryTree) -> Result<(), Error> {
+        self.write(SUMMARIES_FILE, summaries)
+    }
+
+    pub fn load_summaries(&self) -> Result<SummaryTree, Error> {
+        self.read(SUMMARIES_FILE)
+    }
+
+    /// Returns the cached summaries, or an empty tree if none have been saved yet.
+    pub fn load_summaries_or_default(&self) -> SummaryTree {
+        self.load_summaries().unwrap_or_default()
+    }
+
+    /// Where the summaries of an unfinished `sum` run are checkpointed.
+    pub fn journal_path(&self) -> PathBuf {
+        self.path.join(JOURNAL_FILE)
+    }
+
+    pub fn save_index(&self, index: &Index) -> Result<(), Error> {
+        self.write(INDEX_FILE, index)
+    }
+
+    pub fn load_index(&self) -> Result<Index, Error> {
+        self.read(INDEX_FILE)
+    }
+
+    fn write<T: Serialize>(&self, name: &str, value: &T) -> Result<(), Error> {
+        fs::create_dir_all(&self.path)?;
+        fs::write(self.path.join(name), serde_json::to_string(value)?)?;
+
+        Ok(())
+    }
+
+    fn read<T: DeserializeOwned>(&self, name: &str) -> Result<T, Error> {
+        let path = self.path.join(name);
+
+        let contents = fs::read_to_string(&path).map_err(|e| {
+            eyre!(
+                "failed to read {}, run `gitsum sum` first: {}",
+                path.display(),
+                e
+            )
+        })?;
+
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
@@ -0,0 +1,469 @@
+use crate::cache::{normalize, one_line, Cache, SummaryTree};
+use crate::config::{Config, OutputFormat};
+use crate::git::{request, Git, RepositoryContent};
+use crate::http;
+use crate::report::Report;
+use crate::stats::RepositoryStats;
+use colored::*;
+use eyre::{eyre, Error, Result};
+use futures::stream::{self, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The repositories listed per page of the GitHub API.
+const PER_PAGE: usize = 100;
+
+/// The languages listed for each repository in the index.
+const MAX_INDEX_LANGUAGES: usize = 3;
+
+/// A repository as listed by the GitHub API.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepositoryInfo {
+    /// The repository, as `owner/repo`.
+    pub full_name: String,
+
+    #[serde(default)]
+    pub description: Option<String>,
+
+    #[serde(default)]
+    pub default_branch: String,
+
+    #[serde(default)]
+    pub archived: bool,
+
+    #[serde(default)]
+    pub fork: bool,
+
+    #[serde(default)]
+    pub topics: Vec<String>,
+}
+
+/// A repository to summarize, at a branch.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub repository: RepositoryInfo,
+
+    pub branch: String,
+}
+
+/// Decides which listed repositories are summarized. Archived repositories
+/// and forks are skipped unless included, and if topics are given a
+/// repository must have one of them.
+#[derive(Debug, Clone, Default)]
+pub struct RepositoryFilter {
+    pub topics: Vec<String>,
+
+    pub include_archived: bool,
+
+    pub include_forks: bool,
+}
+
+impl RepositoryFilter {
+    pub fn matches(&self, repository: &RepositoryInfo) -> bool {
+        (self.include_archived || !repository.archived)
+            && (self.include_forks || !repository.fork)
+            && (self.topics.is_empty() || self.topics.iter().any(|t| repository.topics.contains(t)))
+    }
+}
+
+/// A summarized repository, as listed in the index.
+#[derive(Debug, Clone, Serialize)]
+pub struct CatalogEntry {
+    pub repository: RepositoryInfo,
+
+    pub branch: String,
+
+    /// The first paragraph of the repository summary.
+    pub summary: String,
+
+    pub stats: RepositoryStats,
+
+    /// The report of the repository, relative to the index.
+    pub report: String,
+
+    /// The number of summaries that failed with `--keep-going`.
+    pub failures: usize,
+}
+
+/// A repository that couldn't be summarized.
+#[derive(Debug, Clone, Serialize)]
+pub struct CatalogFailure {
+    pub repository: String,
+
+    pub error: String,
+}
+
+/// An index of summarized repositories.
+#[derive(Debug, Default, Serialize)]
+pub struct Catalog {
+    pub entries: Vec<CatalogEntry>,
+
+    pub failures: Vec<CatalogFailure>,
+}
+
+/// Returns the first paragraph of a summary, on one line.
+fn first_paragraph(summary: &str) -> String {
+    summary
+        .trim()
+        .split("\n\n")
+        .next()
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+impl Catalog {
+    /// Renders the index as markdown: a table of every repository, then a
+    /// section for each with its summary, stats and a link to its report.
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "# Repositories\n\n{} repositories summarized.\n\n| Repository | Languages | Summary |\n| --- | --- | --- |\n",
+            self.entries.len()
+        );
+
+        for entry in &self.entries {
+            out.push_str(&format!(
+                "| [{}]({}) | {} | {} |\n",
+                entry.repository.full_name,
+                entry.report,
+                entry.stats.main_languages(MAX_INDEX_LANGUAGES),
+                one_line(&entry.summary).replace('|', "\\|")
+            ));
+        }
+
+        for entry in &self.entries {
+            out.push_str(&format!("\n## {}\n\n", entry.repository.full_name));
+
+            if let Some(description) = entry
+                .repository
+                .description
+                .as_deref()
+                .filter(|d| !d.is_empty())
+            {
+                out.push_str(&format!("_{}_\n\n", description.trim()));
+            }
+
+            out.push_str(&format!("{}\n\n", entry.summary));
+            out.push_str(&format!(
+                "- Branch: `{}`\n- Languages: {}\n",
+                entry.branch,
+                entry.stats.main_languages(MAX_INDEX_LANGUAGES)
+            ));
+
+            let stack = entry.stats.stack();
+            if !stack.is_empty() {
+                out.push_str(&format!("- Tech stack: {}\n", stack.join(", ")));
+            }
+
+            if !entry.repository.topics.is_empty() {
+                out.push_str(&format!(
+                    "- Topics: {}\n",
+                    entry.repository.topics.join(", ")
+                ));
+            }
+
+            if entry.failures > 0 {
+                out.push_str(&format!(
+                    "- {} summaries failed, see the report\n",
+                    entry.failures
+                ));
+            }
+
+            out.push_str(&format!("\n[Full report]({})\n", entry.report));
+        }
+
+        if !self.failures.is_empty() {
+            out.push_str("\n## Failed\n\n| Repository | Error |\n| --- | --- |\n");
+            for failure in &self.failures {
+                out.push_str(&format!(
+                    "| {} | {} |\n",
+                    failure.repository,
+                    failure.error.replace('|', "\\|").replace('\n', " ")
+                ));
+            }
+        }
+
+        normalize(&out)
+    }
+}
+
+/// Looks up a repository given as `owner/repo`, optionally followed by
+/// `@branch`. Without a branch, its default branch is used.
+pub async fn lookup(git_key: &str, spec: &str) -> Result<Target, Error> {
+    let (full_name, branch) = match spec.split_once('@') {
+        Some((full_name, branch)) => (full_name.trim(), Some(branch.trim().to_string())),
+        None => (spec.trim(), None),
+    };
+
+    if !full_name.contains('/') {
+        return Err(eyre!("{} must be of the form owner/repo", full_name));
+    }
+
+    let url = format!("https://api.github.com/repos/{}", full_name);
+    let response = http::send(request(&Client::new(), git_key, &url)).await?;
+    let repository = serde_json::from_str::<RepositoryInfo>(&response).map_err(|e| {
+        eyre!(
+            "failed to look up {}, check it exists and can be read with your GitHub key: {}",
+            full_name,
+            e
+        )
+    })?;
+
+    Ok(Target {
+        branch: branch.unwrap_or_else(|| repository.default_branch.clone()),
+        repository,
+    })
+}
+
+/// Returns the cached contents and summaries of a repository, fetching and
+/// summarizing it first if they aren't cached or `refresh` is set.
+pub async fn summarize_cached(
+    config: &Config,
+    target: &Target,
+    refresh: bool,
+) -> Result<(RepositoryContent, SummaryTree), Error> {
+    let full_name = &target.repository.full_name;
+    let (username, repo) = full_name
+        .split_once('/')
+        .ok_or_else(|| eyre!("{} must be of the form owner/repo", full_name))?;
+
+    let cache = Cache::new(&config.cache_dir, username, repo, &target.branch);
+
+    match (cache.load_contents(), cache.load_summaries()) {
+        (Ok(contents), Ok(summaries)) if !refresh => Ok((contents, summaries)),
+        _ => {
+            let mut git_config = config.clone();
+            git_config.output = OutputFormat::Json;
+
+            let mut git = Git::new(
+                &config.github_key().unwrap_or_default(),
+                &config.open_ai_key()?,
+                username,
+                repo,
+                &target.branch,
+            )
+            .with_config(git_config);
+
+            git.get_contents(Client::new()).await?;
+            cache.save_contents(&git.repository_content)?;
+
+            let summaries = git.summarize_repository().await?;
+            cache.save_summaries(&summaries)?;
+
+            Ok((git.repository_content, summaries))
+        }
+    }
+}
+
+/// Summarizes many repositories, listed from an organization or user or
+/// from a file, and writes a report for each along with an index of them.
+#[derive(Debug)]
+pub struct Batch {
+    pub config: Config,
+
+    pub filter: RepositoryFilter,
+
+    /// Where the index and reports are written.
+    pub output_dir: PathBuf,
+
+    /// The number of repositories summarized at once, sharing
+    /// `config.concurrency` between them.
+    pub jobs: usize,
+
+    /// Whether repositories with cached summaries are summarized again.
+    pub refresh: bool,
+}
+
+impl Batch {
+    /// Creates the batch, splitting `config.concurrency` across the `jobs`
+    /// so that no more requests are in flight than a single run would send.
+    /// There are never more jobs than `config.concurrency`.
+    pub fn new(
+        mut config: Config,
+        filter: RepositoryFilter,
+        output_dir: &Path,
+        jobs: usize,
+        refresh: bool,
+    ) -> Batch {
+        let jobs = jobs.clamp(1, config.concurrency.max(1));
+        config.concurrency = (config.concurrency / jobs).max(1);
+
+        Batch {
+            config,
+            filter,
+            output_dir: output_dir.to_path_buf(),
+            jobs,
+            refresh,
+        }
+    }
+
+    fn git_key(&self) -> String {
+        self.config.github_key().unwrap_or_default()
+    }
+
+    /// Lists the repositories of an organization, or of a user if there is
+    /// no organization by that name, at their default branches.
+    pub async fn org(&self, name: &str) -> Result<Vec<Target>, Error> {
+        let client = Client::new();
+        let mut repositories = vec![];
+
+        for kind in ["orgs", "users"] {
+            for page in 1.. {
+                let url = format!(
+                    "https://api.github.com/{}/{}/repos?per_page={}&page={}",
+                    kind, name, PER_PAGE, page
+                );
+
+                let (status, response) =
+                    http::send_with_status(request(&client, &self.git_key(), &url)).await?;
+                match status {
+                    // Not an organization, so the user is tried next.
+                    404 if page == 1 => break,
+                    200..=299 => {}
+                    _ => {
+                        return Err(eyre!(
+                            "failed to list the repositories of {}: GitHub responded {}: {}",
+                            name,
+                            status,
+                            response.trim()
+                        ))
+                    }
+                }
+
+                let listed = serde_json::from_str::<Vec<RepositoryInfo>>(&response)
+                    .map_err(|e| eyre!("failed to list the repositories of {}: {}", name, e))?;
+
+                let last = listed.len() < PER_PAGE;
+                repositories.extend(listed);
+
+                if last {
+                    break;
+                }
+            }
+
+            if !repositories.is_empty() {
+                break;
+            }
+        }
+
+        if repositories.is_empty() {
+            return Err(eyre!("{} has no repositories, or doesn't exist", name));
+        }
+
+        Ok(self.targets(repositories.into_iter().map(|r| (r, None)).collect()))
+    }
+
+    /// Reads the repositories listed in a file, one `owner/repo` per line,
+    /// optionally followed by `@branch`. Blank lines and `#` comments are
+    /// skipped. Repositories without a branch are summarized at their default branch.
+    pub async fn from_file(&self, path: &Path) -> Result<Vec<Target>, Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| eyre!("failed to read {}: {}", path.display(), e))?;
+
+        let mut repositories = vec![];
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let target = lookup(&self.git_key(), line)
+                .await
+                .map_err(|e| eyre!("{}: {}", path.display(), e))?;
+
+            repositories.push((target.repository, Some(target.branch)));
+        }
+
+        Ok(self.targets(repositories))
+    }
+
+    /// Filters the repositories, sorted by name.
+    fn targets(&self, repositories: Vec<(RepositoryInfo, Option<String>)>) -> Vec<Target> {
+        let mut targets = repositories
+            .into_iter()
+            .filter(|(repository, _)| self.filter.matches(repository))
+            .map(|(repository, branch)| Target {
+                branch: branch.unwrap_or_else(|| repository.default_branch.clone()),
+                repository,
+            })
+            .collect::<Vec<Target>>();
+        targets.sort_by(|a, b| a.repository.full_name.cmp(&b.repository.full_name));
+
+        targets
+    }
+
+    /// Summarizes every target, `jobs` at once, writes their reports and
+    /// the index, and returns the index. A repository that fails is listed
+    /// as failed rather than stopping the others.
+    pub async fn run(&self, targets: Vec<Target>) -> Result<Catalog, Error> {
+        fs::create_dir_all(&self.output_dir)?;
+
+        let results = stream::iter(targets)
+            .map(|target| async move {
+                let result = self.summarize(&target).await;
+
+                (target, result)
+            })
+            .buffered(self.jobs)
+            .collect::<Vec<(Target, Result<CatalogEntry, Error>)>>()
+            .await;
+
+        let mut catalog = Catalog::default();
+        for (target, result) in results {
+            match result {
+                Ok(entry) => catalog.entries.push(entry),
+                Err(e) => {
+                    if self.config.output == OutputFormat::Text {
+                        eprintln!(
+                            "{} {}: {}",
+                            "Failed".red().bold(),
+                            target.repository.full_name,
+                            e
+                        );
+                    }
+
+                    catalog.failures.push(CatalogFailure {
+                        repository: target.repository.full_name,
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+
+        fs::write(self.output_dir.join("index.md"), catalog.render())?;
+
+        Ok(catalog)
+    }
+
+    /// Summarizes a repository, unless it has cached summaries, and writes its report.
+    async fn summarize(&self, target: &Target) -> Result<CatalogEntry, Error> {
+        let full_name = &target.repository.full_name;
+        let (contents, summaries) = summarize_cached(&self.config, target, self.refresh).await?;
+
+        let report = format!("{}.md", full_name);
+        let path = self.output_dir.join(&report);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(
+            &path,
+            Report::new(full_name, &contents, &summaries).render(),
+        )?;
+
+        if self.config.output == OutputFormat::Text && !self.config.quiet {
+            println!("{} {}", "Summarized".green().bold(), full_name);
+        }
+
+        Ok(CatalogEntry {
+            repository: target.repository.clone(),
+            branch: target.branch.clone(),
+            summary: first_paragraph(&summaries.summary),
+            stats: RepositoryStats::new(&contents),
+            report,
+            failures: summaries.failures.len(),
+        })
+    }
+}
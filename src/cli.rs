@@ -1,7 +1,35 @@
-use crate::git::Git;
-use clap::{Args, Parser, Subcommand};
+use crate::ask::Conversation;
+use crate::cache::Cache;
+use crate::catalog::{self, Batch, Catalog, RepositoryFilter};
+use crate::compare::Comparison;
+use crate::config::{Config, ConfigLayer, GenerationLayer, OutputFormat};
+use crate::credentials::{self, Service};
+use crate::diagram::{DiagramFormat, DiagramKind, Graph};
+use crate::docs::Docs;
+use crate::git::{Git, RepositoryContent};
+use crate::history::History;
+use crate::http;
+use crate::index::{EmbeddingProvider, Index, DEFAULT_EMBEDDINGS_MODEL};
+use crate::issues::{fetch_issues, parse_since, IssueDigest};
+use crate::journal::Journal;
+use crate::logging::{self, LogFormat};
+use crate::mcp::McpServer;
+use crate::progress::Progress;
+use crate::prompts::{Length, Style};
+use crate::report::Report;
+use crate::serve::Server;
+use crate::summary_files::{SummaryFiles, DEFAULT_SUMMARY_FILE};
+use chrono::{DateTime, Duration, Utc};
+use clap::{ArgAction, Args, Parser, Subcommand};
+use colored::*;
 use eyre::Error;
 use reqwest::Client;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tracing::{info, warn};
 
 #[derive(Debug, Parser)]
 #[command(
@@ -16,6 +44,70 @@ pub struct Cli {
     /// The command to run
     #[clap(subcommand)]
     pub command: Commands,
+
+    /// The config profile to use, from gitsum.toml or ~/.config/gitsum/config.toml
+    #[clap(long, global = true)]
+    pub profile: Option<String>,
+
+    /// How summaries are printed
+    #[clap(long, global = true, value_enum)]
+    pub output_format: Option<OutputFormat>,
+
+    /// The number of files summarized at once
+    #[clap(long, global = true)]
+    pub concurrency: Option<usize>,
+
+    /// The most requests sent to GitHub and the model each minute
+    #[clap(long, global = true)]
+    pub rate_limit: Option<u32>,
+
+    /// Where fetched contents and summaries are cached, defaults to ~/.gitsum
+    #[clap(long, global = true)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// A directory of file.jinja, folder.jinja and repository.jinja prompt templates
+    #[clap(long, global = true)]
+    pub prompts: Option<PathBuf>,
+
+    /// Who summaries are written for
+    #[clap(long, global = true, value_enum)]
+    pub style: Option<Style>,
+
+    /// How long summaries should be
+    #[clap(long, global = true, value_enum)]
+    pub length: Option<Length>,
+
+    /// Save every GitHub and model response as a fixture in this directory
+    #[clap(long, global = true, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Answer GitHub and model requests from the fixtures in this directory, offline
+    #[clap(long, global = true)]
+    pub replay: Option<PathBuf>,
+
+    /// The seed sent with every chat completion, for models that support sampling deterministically
+    #[clap(long, global = true)]
+    pub seed: Option<i64>,
+
+    /// Summarize at temperature 0 with a fixed seed, so runs on the same commit give the same reports
+    #[clap(long, global = true)]
+    pub reproducible: bool,
+
+    /// Fetch recent commits and CODEOWNERS, to summarize how code is evolving and who owns it
+    #[clap(long, global = true)]
+    pub history: bool,
+
+    /// Log more, -v for info and -vv for debug with the time taken by each request
+    #[clap(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Print no progress bars or summaries as they are generated, and only log errors
+    #[clap(long, global = true)]
+    pub quiet: bool,
+
+    /// How logs are written to stderr
+    #[clap(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
 }
 
 #[derive(Debug, Subcommand)]
@@ -23,10 +115,174 @@ pub enum Commands {
     /// Summarize a github repository
     #[command(name = "sum")]
     Sum(SumArgs),
+
+    /// Answer a question about a summarized repository
+    #[command(name = "ask")]
+    Ask(AskArgs),
+
+    /// Ask questions about a summarized repository interactively
+    #[command(name = "chat")]
+    Chat(ChatArgs),
+
+    /// Search a summarized repository for the files most related to a query
+    #[command(name = "search")]
+    Search(SearchArgs),
+
+    /// Generate a README and ARCHITECTURE.md for a repository
+    #[command(name = "docs")]
+    Docs(DocsArgs),
+
+    /// Draw the folder tree or dependency graph of a summarized repository
+    #[command(name = "diagram")]
+    Diagram(DiagramArgs),
+
+    /// Write a summary file into every folder of a local checkout
+    #[command(name = "summaries")]
+    Summaries(SummariesArgs),
+
+    /// Write a markdown report of a summarized repository
+    #[command(name = "report")]
+    Report(ReportArgs),
+
+    /// Manage the keys gitsum uses
+    #[command(name = "auth")]
+    Auth(AuthArgs),
+
+    /// Summarize every repository of a GitHub organization or user and write an index of them
+    #[command(name = "org")]
+    Org(OrgArgs),
+
+    /// Summarize the repositories listed in a file and write an index of them
+    #[command(name = "batch")]
+    Batch(BatchArgs),
+
+    /// Summarize two repositories and write a side by side comparison of them
+    #[command(name = "compare")]
+    Compare(CompareArgs),
+
+    /// Digest the open and recently closed issues of a repository into themes and pain points
+    #[command(name = "issues")]
+    Issues(IssuesArgs),
+
+    /// Serve a REST API for summarizing repositories
+    #[command(name = "serve")]
+    Serve(ServeArgs),
+
+    /// Serve summaries to AI assistants as a Model Context Protocol server on stdio
+    #[command(name = "mcp")]
+    Mcp,
 }
 
 #[derive(Debug, Args)]
-pub struct SumArgs {
+pub struct BatchOptions {
+    /// Where the index and the report of each repository are written
+    #[clap(long, default_value = "gitsum-index")]
+    pub output_dir: PathBuf,
+
+    /// The number of repositories summarized at once, sharing --concurrency between them, at most --concurrency
+    #[clap(long, default_value_t = 2)]
+    pub jobs: usize,
+
+    /// Only summarize repositories with one of these topics
+    #[clap(long)]
+    pub topic: Vec<String>,
+
+    /// Also summarize archived repositories
+    #[clap(long)]
+    pub include_archived: bool,
+
+    /// Also summarize forks
+    #[clap(long)]
+    pub include_forks: bool,
+
+    /// Summarize repositories again even if they have cached summaries
+    #[clap(long)]
+    pub refresh: bool,
+}
+
+impl BatchOptions {
+    fn batch(&self, config: &Config) -> Batch {
+        Batch::new(
+            config.clone(),
+            RepositoryFilter {
+                topics: self.topic.clone(),
+                include_archived: self.include_archived,
+                include_forks: self.include_forks,
+            },
+            &self.output_dir,
+            self.jobs,
+            self.refresh,
+        )
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct OrgArgs {
+    /// The organization or user whose repositories are summarized
+    pub org: String,
+
+    #[command(flatten)]
+    pub options: BatchOptions,
+}
+
+#[derive(Debug, Args)]
+pub struct BatchArgs {
+    /// A file listing one owner/repo per line, optionally followed by @branch
+    pub file: PathBuf,
+
+    #[command(flatten)]
+    pub options: BatchOptions,
+}
+
+#[derive(Debug, Args)]
+pub struct CompareArgs {
+    /// The first repository, as owner/repo, optionally followed by @branch
+    pub first: String,
+
+    /// The second repository, as owner/repo, optionally followed by @branch
+    pub second: String,
+
+    /// The file to write the comparison to, instead of printing it
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Summarize the repositories again even if they have cached summaries
+    #[clap(long)]
+    pub refresh: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct IssuesArgs {
+    /// The repository, as owner/repo, optionally followed by the @branch its cached summaries are of
+    #[clap(long)]
+    pub repo: String,
+
+    /// How far back closed issues are included, and open ones are considered stale, such as 30d, 2w or 12h
+    #[clap(long, default_value = "30d", value_parser = parse_since)]
+    pub since: Duration,
+
+    /// The file to write the digest to, instead of printing it
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// The address to listen on
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    pub bind: String,
+
+    /// The number of repositories summarized at once
+    #[clap(long, default_value_t = 2)]
+    pub jobs: usize,
+
+    /// The number of jobs that can wait to be run before new ones are rejected
+    #[clap(long, default_value_t = 64)]
+    pub queue: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct RepoArgs {
     /// The username of the repository owner
     #[clap(short, long)]
     pub username: String,
@@ -38,6 +294,12 @@ pub struct SumArgs {
     /// The branch of the repository
     #[clap(short, long)]
     pub branch: String,
+}
+
+#[derive(Debug, Args)]
+pub struct SumArgs {
+    #[command(flatten)]
+    pub repo: RepoArgs,
 
     /// Your github api key
     #[clap(short, long)]
@@ -55,106 +317,767 @@ pub struct SumArgs {
     #[clap(short = 's', long)]
     pub file: Option<String>,
 
-    /// The maximum number of tokens to generate in the chat completion.
-    #[clap(short, long, default_value = "4096")]
+    /// The model to use for chat completions, defaults to gpt-3.5-turbo
+    #[clap(long)]
+    pub model: Option<String>,
+
+    /// The maximum number of tokens to generate in the chat completion, defaults to 2000
+    #[clap(short, long)]
     pub max_tokens: Option<i64>,
 
-    /// What sampling temperature to use, between 0 and 2
-    #[clap(short = 'x', long, default_value = "0.7")]
+    /// What sampling temperature to use, between 0 and 2, defaults to 0.7
+    #[clap(short = 'x', long)]
     pub temperature: Option<f64>,
 
-    /// An alternative to sampling with temperature, called nucleus sampling,
+    /// An alternative to sampling with temperature, called nucleus sampling, defaults to 1.0
     ///
     /// The model considers the results of the tokens with top_p probability mass.
     /// It it recommended to alter this or temperature but not both.
-    #[clap(short, long, default_value = "1.0")]
+    #[clap(short, long)]
     pub top_p: Option<f64>,
 
-    /// Number between -2.0 and 2.0. Positive values penalize new tokens based on whether they appear in the text so far.
-    #[clap(short, long, default_value = "0.0")]
+    /// Number between -2.0 and 2.0. Positive values penalize new tokens based on whether they appear in the text so far. Defaults to 0.0
+    #[clap(short, long)]
     pub presence_penalty: Option<f64>,
 
-    /// Number between -2.0 and 2.0. Positive values penalize new tokens based on their existing frequency in the text so far.
-    #[clap(short = 'q', long, default_value = "0.0")]
+    /// Number between -2.0 and 2.0. Positive values penalize new tokens based on their existing frequency in the text so far. Defaults to 0.0
+    #[clap(short = 'q', long)]
     pub frequency_penalty: Option<f64>,
+
+    /// Continue an interrupted run, reusing its fetched contents and completed summaries
+    #[clap(long)]
+    pub resume: bool,
+
+    /// Record failed summaries in the report and keep summarizing everything else
+    #[clap(long)]
+    pub keep_going: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct AskArgs {
+    #[command(flatten)]
+    pub repo: RepoArgs,
+
+    /// Your openai api key
+    #[clap(short, long)]
+    pub open_ai_key: Option<String>,
+
+    /// The question to answer
+    pub question: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ChatArgs {
+    #[command(flatten)]
+    pub repo: RepoArgs,
+
+    /// Your openai api key
+    #[clap(short, long)]
+    pub open_ai_key: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct SearchArgs {
+    #[command(flatten)]
+    pub repo: RepoArgs,
+
+    /// The model used to embed files and the query
+    #[clap(short, long, default_value = DEFAULT_EMBEDDINGS_MODEL)]
+    pub model: String,
+
+    /// The url of a local, OpenAI compatible embeddings endpoint to use instead of OpenAI
+    #[clap(short, long)]
+    pub embeddings_url: Option<String>,
+
+    /// The maximum number of results to return
+    #[clap(short, long, default_value = "10")]
+    pub limit: usize,
+
+    /// Rebuild the index even if one is cached
+    #[clap(long)]
+    pub reindex: bool,
+
+    /// What to search for
+    pub query: String,
+}
+
+#[derive(Debug, Args)]
+pub struct DocsArgs {
+    #[command(flatten)]
+    pub repo: RepoArgs,
+
+    /// Your github api key
+    #[clap(short, long)]
+    pub git_key: Option<String>,
+
+    /// Where to write the README
+    #[clap(short, long, default_value = "README.md")]
+    pub write: PathBuf,
+
+    /// Where to write the architecture overview
+    #[clap(short, long, default_value = "ARCHITECTURE.md")]
+    pub architecture: PathBuf,
+
+    /// Write the generated documents merged with existing ones, rather than as generated
+    #[clap(short, long)]
+    pub merge: bool,
+
+    /// Summarize the repository again even if summaries are cached
+    #[clap(long)]
+    pub refresh: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DiagramArgs {
+    #[command(flatten)]
+    pub repo: RepoArgs,
+
+    /// The diagram to draw
+    #[clap(short, long, value_enum, default_value = "tree")]
+    pub kind: DiagramKind,
+
+    /// The format to output the diagram in
+    #[clap(short, long, value_enum, default_value = "mermaid")]
+    pub format: DiagramFormat,
+
+    /// The file to write the diagram to, instead of printing it
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    #[command(flatten)]
+    pub repo: RepoArgs,
+
+    /// The file to write the report to, instead of printing it
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct SummariesArgs {
+    /// The local checkout to summarize
+    #[clap(default_value = ".")]
+    pub path: PathBuf,
+
+    /// The name of the summary file written into each folder
+    #[clap(short, long, default_value = DEFAULT_SUMMARY_FILE)]
+    pub name: String,
+
+    /// List the folders with missing or outdated summary files and fail if
+    /// there are any, without writing anything
+    #[clap(short, long)]
+    pub check: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct AuthArgs {
+    #[clap(subcommand)]
+    pub command: AuthCommands,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuthCommands {
+    /// Show where the key of each service is found, without printing it
+    #[command(name = "status")]
+    Status,
+
+    /// Store a key in the OS keyring, read from stdin
+    #[command(name = "set")]
+    Set {
+        /// The service the key is for
+        #[clap(value_enum)]
+        service: Service,
+    },
+
+    /// Remove a key from the OS keyring
+    #[command(name = "delete")]
+    Delete {
+        /// The service the key is for
+        #[clap(value_enum)]
+        service: Service,
+    },
+}
+
+/// Loads the cached summaries and contents of a repository into a new conversation.
+fn conversation(repo: &RepoArgs, config: &Config) -> Result<Conversation, Error> {
+    let cache = Cache::new(&config.cache_dir, &repo.username, &repo.repo, &repo.branch);
+
+    Ok(Conversation::new(
+        &config.open_ai_key()?,
+        config.repository.clone(),
+        cache.load_summaries()?,
+        cache.load_contents()?,
+    ))
+}
+
+fn print_answer(answer: &crate::prompts::AskResponse) {
+    println!("{}", answer.answer);
+
+    if !answer.sources.is_empty() {
+        println!(
+            "{} {}",
+            "Sources:".green().bold(),
+            answer.sources.join(", ")
+        );
+    }
+}
+
+/// Collects the keys given as flags.
+fn keys(git_key: &Option<String>, open_ai_key: &Option<String>) -> HashMap<Service, String> {
+    let mut keys = HashMap::new();
+
+    if let Some(key) = git_key {
+        keys.insert(Service::Github, key.clone());
+    }
+
+    if let Some(key) = open_ai_key {
+        keys.insert(Service::Openai, key.clone());
+    }
+
+    keys
+}
+
+fn print_catalog(batch: &Batch, catalog: Catalog, config: &Config) -> Result<(), Error> {
+    if config.output == OutputFormat::Json {
+        return print_json(&catalog);
+    }
+
+    println!(
+        "{} {} repositories, {} failed, index written to {}",
+        "Summarized".green().bold(),
+        catalog.entries.len(),
+        catalog.failures.len(),
+        batch.output_dir.join("index.md").display()
+    );
+
+    Ok(())
+}
+
+/// Progress bars for text output, unless it is quiet.
+fn progress(config: &Config) -> Arc<Progress> {
+    if config.output == OutputFormat::Text && !config.quiet {
+        Arc::new(Progress::with_bars())
+    } else {
+        Arc::new(Progress::default())
+    }
+}
+
+fn print_json<T: serde::Serialize>(value: &T) -> Result<(), Error> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+
+    Ok(())
 }
 
 impl Cli {
+    /// The configuration given on the command line, which takes precedence
+    /// over every other source.
+    fn layer(&self) -> ConfigLayer {
+        let mut layer = match &self.command {
+            Commands::Sum(args) => ConfigLayer {
+                keys: keys(&args.git_key, &args.open_ai_key),
+                generation: GenerationLayer {
+                    model: args.model.clone(),
+                    max_tokens: args.max_tokens,
+                    temperature: args.temperature,
+                    top_p: args.top_p,
+                    frequency_penalty: args.frequency_penalty,
+                    presence_penalty: args.presence_penalty,
+                    ..Default::default()
+                },
+                keep_going: args.keep_going.then_some(true),
+                ..Default::default()
+            },
+            Commands::Ask(args) => ConfigLayer {
+                keys: keys(&None, &args.open_ai_key),
+                ..Default::default()
+            },
+            Commands::Chat(args) => ConfigLayer {
+                keys: keys(&None, &args.open_ai_key),
+                ..Default::default()
+            },
+            Commands::Docs(args) => ConfigLayer {
+                keys: keys(&args.git_key, &None),
+                ..Default::default()
+            },
+            _ => ConfigLayer::default(),
+        };
+
+        layer.output = self.output_format;
+        layer.quiet = self.quiet.then_some(true);
+        layer.generation.seed = self.seed;
+        layer.reproducible = self.reproducible.then_some(true);
+        layer.history = self.history.then_some(true);
+        layer.concurrency = self.concurrency;
+        layer.rate_limit = self.rate_limit;
+        layer.cache_dir = self.cache_dir.clone();
+        layer.prompts = self.prompts.clone();
+        layer.style = self.style;
+        layer.length = self.length;
+        layer.record = self.record.clone();
+        layer.replay = self.replay.clone();
+
+        layer
+    }
+
     pub async fn run() -> Result<(), Error> {
         let args = Self::parse();
+        logging::init(args.verbose, args.quiet, args.log_format);
+
+        let layer = args.layer();
+        let config = Config::load(args.profile.as_deref(), layer.clone())?;
+        http::set_transport(config.transport.clone());
+        http::set_rate_limit(config.rate_limit);
 
         match args.command {
-            Commands::Sum(args) => {
-                let git_key = match args.git_key {
-                    Some(key) => {
-                        if key.is_empty() {
-                            match std::env::var("GITHUB_KEY").ok() {
-                                Some(key) => key,
-                                None => {
-                                    eprintln!("GITHUB_KEY environment variable not set");
-
-                                    std::process::exit(1);
-                                }
-                            }
-                        } else {
-                            key
-                        }
+            Commands::Serve(serve) => {
+                Server::new(args.profile, layer, config, serve.jobs, serve.queue)
+                    .serve(&serve.bind)
+                    .await?;
+            }
+
+            Commands::Mcp => {
+                McpServer::new(config).run().await?;
+            }
+
+            Commands::Org(args) => {
+                let batch = args.options.batch(&config);
+                let targets = batch.org(&args.org).await?;
+
+                print_catalog(&batch, batch.run(targets).await?, &config)?;
+            }
+
+            Commands::Batch(args) => {
+                let batch = args.options.batch(&config);
+                let targets = batch.from_file(&args.file).await?;
+
+                print_catalog(&batch, batch.run(targets).await?, &config)?;
+            }
+
+            Commands::Compare(args) => {
+                let git_key = config.github_key().unwrap_or_default();
+                let (first, second) = tokio::try_join!(
+                    catalog::lookup(&git_key, &args.first),
+                    catalog::lookup(&git_key, &args.second)
+                )?;
+
+                let ((first_contents, first_summaries), (second_contents, second_summaries)) = tokio::try_join!(
+                    catalog::summarize_cached(&config, &first, args.refresh),
+                    catalog::summarize_cached(&config, &second, args.refresh)
+                )?;
+
+                let comparison = Comparison::new(
+                    &config.open_ai_key()?,
+                    &config.repository,
+                    (&first, &first_contents, &first_summaries),
+                    (&second, &second_contents, &second_summaries),
+                )
+                .await?;
+
+                if config.output == OutputFormat::Json {
+                    print_json(&comparison)?;
+                } else {
+                    match args.output {
+                        Some(path) => std::fs::write(path, comparison.render())?,
+                        None => print!("{}", comparison.render()),
                     }
+                }
+            }
 
-                    None => match std::env::var("GITHUB_KEY").ok() {
-                        Some(key) => key,
-                        None => {
-                            eprintln!("GITHUB_KEY environment variable not set");
+            Commands::Issues(args) => {
+                let git_key = config.github_key().unwrap_or_default();
+                let target = catalog::lookup(&git_key, &args.repo).await?;
+                let full_name = &target.repository.full_name;
+                let since = DateTime::<Utc>::from(SystemTime::now()) - args.since;
 
-                            std::process::exit(1);
-                        }
+                let issues = fetch_issues(
+                    &git_key,
+                    full_name,
+                    since,
+                    config.concurrency,
+                    &config.redactor,
+                )
+                .await?;
+
+                let (username, repo) = full_name.split_once('/').unwrap_or_default();
+                let summaries = Cache::new(&config.cache_dir, username, repo, &target.branch)
+                    .load_summaries()
+                    .ok();
+                if summaries.is_none() {
+                    warn!(
+                        "{} has no cached summaries, so themes aren't linked to folders, summarize it with gitsum sum first",
+                        full_name
+                    );
+                }
+
+                let digest = IssueDigest::new(
+                    &config.open_ai_key()?,
+                    &config.repository,
+                    full_name,
+                    issues,
+                    summaries.as_ref(),
+                    since,
+                )
+                .await?;
+
+                if config.output == OutputFormat::Json {
+                    print_json(&digest)?;
+                } else {
+                    match args.output {
+                        Some(path) => std::fs::write(path, digest.render())?,
+                        None => print!("{}", digest.render()),
+                    }
+                }
+            }
+
+            Commands::Ask(args) => {
+                let mut conversation = conversation(&args.repo, &config)?;
+
+                let answer = conversation.ask(&args.question).await?;
+
+                print_answer(&answer);
+            }
+
+            Commands::Chat(args) => {
+                let mut conversation = conversation(&args.repo, &config)?;
+
+                let stdin = io::stdin();
+                loop {
+                    print!("{} ", ">".green().bold());
+                    io::stdout().flush()?;
+
+                    let mut question = String::new();
+                    if stdin.lock().read_line(&mut question)? == 0 {
+                        break;
+                    }
+
+                    let question = question.trim();
+                    if question.is_empty() {
+                        continue;
+                    }
+
+                    if question == "exit" || question == "quit" {
+                        break;
+                    }
+
+                    match conversation.ask(question).await {
+                        Ok(answer) => print_answer(&answer),
+                        Err(e) => eprintln!("{}", e),
+                    }
+
+                    println!();
+                }
+            }
+
+            Commands::Search(args) => {
+                let provider = match args.embeddings_url {
+                    Some(url) => EmbeddingProvider::Local {
+                        url,
+                        model: args.model,
+                    },
+                    None => EmbeddingProvider::OpenAi {
+                        key: config.open_ai_key()?,
+                        model: args.model,
                     },
                 };
 
-                let open_ai_key = match std::env::var("OPEN_AI_KEY").ok() {
-                    Some(key) => {
-                        if key.is_empty() {
-                            match std::env::var("OPEN_AI_KEY").ok() {
-                                Some(key) => key,
-                                None => {
-                                    eprintln!("OPEN_AI_KEY environment variable not set");
+                let cache = Cache::new(
+                    &config.cache_dir,
+                    &args.repo.username,
+                    &args.repo.repo,
+                    &args.repo.branch,
+                );
 
-                                    std::process::exit(1);
-                                }
+                let contents = cache.load_contents()?;
+                let summaries = cache.load_summaries_or_default();
+
+                let index = match cache.load_index() {
+                    Ok(index)
+                        if !args.reindex
+                            && index.is_current(provider.model(), &contents, &summaries) =>
+                    {
+                        index
+                    }
+                    _ => {
+                        println!("{}", "Building index...".green().bold());
+
+                        let index = Index::build(&provider, &contents, &summaries).await?;
+                        cache.save_index(&index)?;
+
+                        index
+                    }
+                };
+
+                for result in index.search(&provider, &args.query, args.limit).await? {
+                    println!(
+                        "{:.3} {}:{}-{}",
+                        result.score,
+                        result.chunk.path.green().bold(),
+                        result.chunk.start_line,
+                        result.chunk.end_line
+                    );
+                }
+            }
+
+            Commands::Docs(args) => {
+                let open_ai_key = config.open_ai_key()?;
+                let cache = Cache::new(
+                    &config.cache_dir,
+                    &args.repo.username,
+                    &args.repo.repo,
+                    &args.repo.branch,
+                );
+
+                let (contents, summaries) = match (cache.load_contents(), cache.load_summaries()) {
+                    (Ok(contents), Ok(summaries)) if !args.refresh => (contents, summaries),
+                    _ => {
+                        let mut git = Git::new(
+                            &config.github_key().unwrap_or_default(),
+                            &open_ai_key,
+                            &args.repo.username,
+                            &args.repo.repo,
+                            &args.repo.branch,
+                        )
+                        .with_config(config.clone())
+                        .with_progress(progress(&config));
+
+                        git.get_contents(Client::new()).await?;
+                        cache.save_contents(&git.repository_content)?;
+
+                        let summaries = git.summarize_repository().await?;
+                        cache.save_summaries(&summaries)?;
+
+                        (git.repository_content, summaries)
+                    }
+                };
+
+                let docs = Docs::new(
+                    &open_ai_key,
+                    config.repository.clone(),
+                    &format!("{}/{}", args.repo.username, args.repo.repo),
+                    &summaries,
+                    &contents,
+                );
+
+                let readme = docs.readme().await?;
+                docs.write(&args.write, &readme, args.merge).await?;
+
+                let architecture = docs.architecture().await?;
+                docs.write(&args.architecture, &architecture, args.merge)
+                    .await?;
+            }
+
+            Commands::Diagram(args) => {
+                let cache = Cache::new(
+                    &config.cache_dir,
+                    &args.repo.username,
+                    &args.repo.repo,
+                    &args.repo.branch,
+                );
+
+                let contents = cache.load_contents()?;
+                let summaries = cache.load_summaries_or_default();
+
+                let graph = match args.kind {
+                    DiagramKind::Tree => Graph::tree(&contents, &summaries),
+                    DiagramKind::Deps => Graph::dependencies(&contents, &summaries),
+                    DiagramKind::Modules => Graph::modules(&contents, &summaries),
+                };
+
+                let diagram = graph.render(args.format);
+
+                match args.output {
+                    Some(path) => std::fs::write(path, diagram)?,
+                    None => print!("{}", diagram),
+                }
+            }
+
+            Commands::Report(args) => {
+                let cache = Cache::new(
+                    &config.cache_dir,
+                    &args.repo.username,
+                    &args.repo.repo,
+                    &args.repo.branch,
+                );
+
+                let contents = cache.load_contents()?;
+                let summaries = cache.load_summaries()?;
+
+                let report = Report::new(
+                    &format!("{}/{}", args.repo.username, args.repo.repo),
+                    &contents,
+                    &summaries,
+                );
+
+                if config.output == OutputFormat::Json {
+                    print_json(&report)?;
+                } else {
+                    match args.output {
+                        Some(path) => std::fs::write(path, report.render())?,
+                        None => print!("{}", report.render()),
+                    }
+                }
+            }
+
+            Commands::Summaries(args) => {
+                let root = args.path.canonicalize()?;
+                let repo = root
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+
+                let open_ai_key = if args.check {
+                    String::new()
+                } else {
+                    config.open_ai_key()?
+                };
+
+                let mut git = Git::new("", &open_ai_key, "local", &repo, "")
+                    .with_config(config.clone())
+                    .with_progress(progress(&config));
+                git.repository_content =
+                    RepositoryContent::from_dir(&root, &config.filter, &[&args.name])?;
+                if config.history {
+                    git.repository_content.history = History::from_git_log(&root)?;
+                }
+                git.repository_content.redact(&config.redactor);
+
+                let summary_files = SummaryFiles::new(&root, &args.name, &git);
+
+                if args.check {
+                    let stale = summary_files.stale();
+
+                    for folder in &stale {
+                        println!(
+                            "{} {}",
+                            "Outdated".yellow().bold(),
+                            summary_files.path(folder).display()
+                        );
+                    }
+
+                    if !stale.is_empty() {
+                        return Err(eyre::eyre!(
+                            "{} summary files are missing or outdated",
+                            stale.len()
+                        ));
+                    }
+                } else {
+                    let written = summary_files.write().await?;
+
+                    if git.verbose() {
+                        println!("{} summary files written", written.len());
+                    }
+                }
+            }
+
+            Commands::Auth(args) => match args.command {
+                AuthCommands::Status => {
+                    for service in Service::ALL {
+                        match config.credentials.resolve(service) {
+                            Some((_, source)) => {
+                                println!("{} {}", service.to_string().green().bold(), source)
                             }
-                        } else {
-                            key
+                            None => println!("{} not set", service.to_string().yellow().bold()),
                         }
                     }
+                }
 
-                    None => {
-                        eprintln!("OPEN_AI_KEY environment variable not set");
+                AuthCommands::Set { service } => {
+                    print!("{} key: ", service);
+                    io::stdout().flush()?;
+
+                    let mut key = String::new();
+                    io::stdin().lock().read_line(&mut key)?;
 
-                        std::process::exit(1);
+                    let key = key.trim();
+                    if key.is_empty() {
+                        return Err(eyre::eyre!("No key given"));
                     }
-                };
 
+                    credentials::store(service, key)?;
+                    println!("Stored {} key in the OS keyring", service);
+                }
+
+                AuthCommands::Delete { service } => {
+                    credentials::delete(service)?;
+                    println!("Deleted {} key from the OS keyring", service);
+                }
+            },
+
+            Commands::Sum(args) => {
                 let mut git = Git::new(
-                    &git_key,
-                    &open_ai_key,
-                    &args.username,
-                    &args.repo,
-                    &args.branch,
+                    &config.github_key().unwrap_or_default(),
+                    &config.open_ai_key()?,
+                    &args.repo.username,
+                    &args.repo.repo,
+                    &args.repo.branch,
+                )
+                .with_config(config.clone())
+                .with_progress(progress(&config));
+
+                let cache = Cache::new(
+                    &config.cache_dir,
+                    &args.repo.username,
+                    &args.repo.repo,
+                    &args.repo.branch,
                 );
 
-                git.get_contents(Client::new()).await?;
+                // A resumed run summarizes the contents fetched by the run it continues.
+                match cache.load_contents().ok().filter(|_| args.resume) {
+                    Some(contents) => git.repository_content = contents,
+                    None => {
+                        git.get_contents(Client::new()).await?;
+                        cache.save_contents(&git.repository_content)?;
+                    }
+                }
+
+                if let Some(file) = args.file {
+                    if let Some(folder) = args.folder {
+                        let file_summary = git.summarize_file(&folder, &file).await?;
 
-                if args.file.is_some() {
-                    if args.folder.is_some() {
-                        git.summarize_file(&args.folder.unwrap(), &args.file.unwrap())
-                            .await?;
+                        if config.output == OutputFormat::Json {
+                            print_json(&file_summary)?;
+                        }
                     } else {
                         return Err(eyre::eyre!("You must specify a folder to summarize a file"));
                     }
-                } else if args.folder.is_some() {
-                    git.summarize_folder(&args.folder.unwrap()).await?;
+                } else if let Some(folder) = args.folder {
+                    let (folder_summary, file_summaries) = git.summarize_folder(&folder).await?;
+
+                    let mut summaries = cache.load_summaries_or_default();
+                    summaries.insert_folder(&folder, folder_summary, file_summaries);
+                    cache.save_summaries(&summaries)?;
+
+                    if config.output == OutputFormat::Json {
+                        print_json(&summaries.folders[&folder])?;
+                    }
                 } else {
-                    git.summarize_repository().await?;
+                    let journal = Arc::new(Journal::open(&cache.journal_path(), args.resume)?);
+                    if args.resume {
+                        info!(files = journal.resumed_files(), "resuming");
+                    }
+
+                    let summaries = git
+                        .with_journal(journal.clone())
+                        .summarize_repository()
+                        .await?;
+
+                    cache.save_summaries(&summaries)?;
+
+                    // Failures are left in the journal, to be retried with --resume.
+                    if summaries.failures.is_empty() {
+                        journal.remove()?;
+                    } else {
+                        warn!(
+                            failures = summaries.failures.len(),
+                            "some summaries failed, see `gitsum report` and retry them with --resume"
+                        );
+                    }
+
+                    if config.output == OutputFormat::Json {
+                        print_json(&summaries)?;
+                    }
                 }
             }
         };
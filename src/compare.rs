@@ -0,0 +1,348 @@
+use crate::cache::{normalize, one_line, SummaryTree};
+use crate::catalog::Target;
+use crate::git::RepositoryContent;
+use crate::gpt::ChatSettings;
+use crate::manifests::{Dependencies, DependencyKind};
+use crate::prompts::{ComparePrompt, ComparisonResponse, Difference};
+use crate::report::cell;
+use crate::stats::RepositoryStats;
+use eyre::{Error, Result};
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// The languages listed for each repository in the comparison.
+const MAX_COMPARED_LANGUAGES: usize = 3;
+
+/// Files and folders that configure continuous integration.
+const CI_PATHS: &[&str] = &[
+    ".github/workflows/",
+    ".gitlab-ci.yml",
+    ".circleci/",
+    ".travis.yml",
+    "Jenkinsfile",
+    "azure-pipelines.yml",
+    ".buildkite/",
+];
+
+/// Signs of how mature a repository is, found without asking a model.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MaturitySignals {
+    pub files: usize,
+
+    pub code_lines: usize,
+
+    pub test_files: usize,
+
+    pub ci: bool,
+
+    pub readme: bool,
+
+    pub license: bool,
+
+    pub changelog: bool,
+
+    /// The dependencies declared for use outside of development and builds.
+    pub dependencies: usize,
+}
+
+/// Whether a path looks like a test file or is in a test folder.
+fn is_test(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or_default();
+
+    path.split('/')
+        .any(|segment| matches!(segment, "test" | "tests" | "__tests__" | "spec"))
+        || name.starts_with("test_")
+        || name.contains("_test.")
+        || name.contains(".test.")
+        || name.contains(".spec.")
+}
+
+impl MaturitySignals {
+    pub fn new(contents: &RepositoryContent, stats: &RepositoryStats) -> MaturitySignals {
+        let paths = contents
+            .folders
+            .values()
+            .flat_map(|folder| folder.files.keys())
+            .collect::<Vec<&String>>();
+        let root = |prefix: &str| {
+            paths
+                .iter()
+                .any(|path| !path.contains('/') && path.to_ascii_uppercase().starts_with(prefix))
+        };
+
+        MaturitySignals {
+            files: paths.len(),
+            code_lines: stats.languages.iter().map(|l| l.code).sum(),
+            test_files: paths.iter().filter(|path| is_test(path)).count(),
+            ci: paths
+                .iter()
+                .any(|path| CI_PATHS.iter().any(|ci| path.starts_with(ci))),
+            readme: root("README"),
+            license: root("LICENSE") || root("COPYING"),
+            changelog: root("CHANGELOG") || root("CHANGES"),
+            dependencies: Dependencies::new(contents)
+                .dependencies
+                .iter()
+                .filter(|d| d.kind == DependencyKind::Normal)
+                .count(),
+        }
+    }
+
+    /// Describes the signals as text, for the comparison prompt.
+    pub fn describe(&self) -> String {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+
+        format!(
+            "{} files, {} lines of code, {} test files, CI: {}, README: {}, license: {}, changelog: {}, {} dependencies",
+            self.files,
+            self.code_lines,
+            self.test_files,
+            yes_no(self.ci),
+            yes_no(self.readme),
+            yes_no(self.license),
+            yes_no(self.changelog),
+            self.dependencies
+        )
+    }
+}
+
+/// One of the compared repositories.
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparedRepository {
+    pub repository: String,
+
+    pub branch: String,
+
+    pub summary: String,
+
+    pub stats: RepositoryStats,
+
+    pub maturity: MaturitySignals,
+}
+
+impl ComparedRepository {
+    pub fn new(
+        target: &Target,
+        contents: &RepositoryContent,
+        summaries: &SummaryTree,
+    ) -> ComparedRepository {
+        let stats = RepositoryStats::new(contents);
+
+        ComparedRepository {
+            repository: target.repository.full_name.clone(),
+            branch: target.branch.clone(),
+            summary: summaries.summary.trim().to_string(),
+            maturity: MaturitySignals::new(contents, &stats),
+            stats,
+        }
+    }
+}
+
+/// Describes a repository for the comparison prompt: its summaries, stats,
+/// maturity signals and what its major dependencies are used for.
+fn describe(compared: &ComparedRepository, summaries: &SummaryTree) -> String {
+    let folders = summaries
+        .folders
+        .iter()
+        .filter(|(_, folder)| !folder.summary.is_empty())
+        .map(|(name, folder)| {
+            let name = if name.is_empty() { "." } else { name };
+
+            format!("- {}: {}", name, one_line(&folder.summary))
+        })
+        .collect::<Vec<String>>();
+
+    let dependencies = summaries
+        .dependencies
+        .iter()
+        .map(|d| format!("- {}: {}", d.name, d.usage))
+        .collect::<Vec<String>>();
+
+    format!(
+        "{}\nSummary: {}\nFolders:\n{}\n{}\nMaturity: {}\nMajor dependencies:\n{}\n",
+        compared.repository,
+        compared.summary,
+        folders.join("\n"),
+        compared.stats.describe(),
+        compared.maturity.describe(),
+        dependencies.join("\n")
+    )
+}
+
+/// A side by side comparison of two repositories.
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub first: ComparedRepository,
+
+    pub second: ComparedRepository,
+
+    /// The dependencies both repositories declare.
+    pub shared_dependencies: Vec<String>,
+
+    pub comparison: ComparisonResponse,
+}
+
+impl Comparison {
+    /// Compares two summarized repositories, asking the model how they overlap and differ.
+    pub async fn new(
+        open_ai_key: &str,
+        settings: &ChatSettings,
+        first: (&Target, &RepositoryContent, &SummaryTree),
+        second: (&Target, &RepositoryContent, &SummaryTree),
+    ) -> Result<Comparison, Error> {
+        let compared_first = ComparedRepository::new(first.0, first.1, first.2);
+        let compared_second = ComparedRepository::new(second.0, second.1, second.2);
+
+        let declared = |contents: &RepositoryContent| {
+            Dependencies::new(contents)
+                .dependencies
+                .into_iter()
+                .map(|d| d.name)
+                .collect::<BTreeSet<String>>()
+        };
+        let shared_dependencies = declared(first.1)
+            .intersection(&declared(second.1))
+            .cloned()
+            .collect();
+
+        let comparison = ComparePrompt::new(
+            &describe(&compared_first, first.2),
+            &describe(&compared_second, second.2),
+        )
+        .send(open_ai_key, settings)
+        .await?;
+
+        Ok(Comparison {
+            first: compared_first,
+            second: compared_second,
+            shared_dependencies,
+            comparison,
+        })
+    }
+
+    /// Renders the comparison as a markdown report.
+    pub fn render(&self) -> String {
+        let (a, b) = (&self.first, &self.second);
+        let yes_no = |v: bool| if v { "yes" } else { "no" };
+
+        let mut out = format!(
+            "# {} vs {}\n\n{}\n\n## At a glance\n\n| | {} | {} |\n| --- | --- | --- |\n",
+            a.repository,
+            b.repository,
+            self.comparison.summary.trim(),
+            a.repository,
+            b.repository
+        );
+
+        let rows = [
+            (
+                "Summary",
+                cell(&one_line(&a.summary)),
+                cell(&one_line(&b.summary)),
+            ),
+            ("Branch", a.branch.clone(), b.branch.clone()),
+            (
+                "Languages",
+                a.stats.main_languages(MAX_COMPARED_LANGUAGES),
+                b.stats.main_languages(MAX_COMPARED_LANGUAGES),
+            ),
+            (
+                "Tech stack",
+                a.stats.stack().join(", "),
+                b.stats.stack().join(", "),
+            ),
+            (
+                "Files",
+                a.maturity.files.to_string(),
+                b.maturity.files.to_string(),
+            ),
+            (
+                "Lines of code",
+                a.maturity.code_lines.to_string(),
+                b.maturity.code_lines.to_string(),
+            ),
+            (
+                "Test files",
+                a.maturity.test_files.to_string(),
+                b.maturity.test_files.to_string(),
+            ),
+            (
+                "CI",
+                yes_no(a.maturity.ci).to_string(),
+                yes_no(b.maturity.ci).to_string(),
+            ),
+            (
+                "README",
+                yes_no(a.maturity.readme).to_string(),
+                yes_no(b.maturity.readme).to_string(),
+            ),
+            (
+                "License",
+                yes_no(a.maturity.license).to_string(),
+                yes_no(b.maturity.license).to_string(),
+            ),
+            (
+                "Changelog",
+                yes_no(a.maturity.changelog).to_string(),
+                yes_no(b.maturity.changelog).to_string(),
+            ),
+            (
+                "Dependencies",
+                a.maturity.dependencies.to_string(),
+                b.maturity.dependencies.to_string(),
+            ),
+        ];
+        for (label, first, second) in rows {
+            out.push_str(&format!("| {} | {} | {} |\n", label, first, second));
+        }
+
+        if !self.comparison.overlap.is_empty() {
+            out.push_str("\n## Overlapping functionality\n\n");
+            for overlap in &self.comparison.overlap {
+                out.push_str(&format!("- {}\n", overlap.trim()));
+            }
+        }
+
+        self.differences(
+            &mut out,
+            "Different approaches",
+            &self.comparison.differences,
+        );
+        self.differences(&mut out, "Maturity", &self.comparison.maturity);
+
+        if !self.shared_dependencies.is_empty() {
+            out.push_str(&format!(
+                "\n## Shared dependencies\n\n{}\n",
+                self.shared_dependencies.join(", ")
+            ));
+        }
+
+        if !self.comparison.recommendation.is_empty() {
+            out.push_str(&format!(
+                "\n## Recommendation\n\n{}\n",
+                self.comparison.recommendation.trim()
+            ));
+        }
+
+        normalize(&out)
+    }
+
+    fn differences(&self, out: &mut String, title: &str, differences: &[Difference]) {
+        if differences.is_empty() {
+            return;
+        }
+
+        out.push_str(&format!(
+            "\n## {}\n\n| Aspect | {} | {} |\n| --- | --- | --- |\n",
+            title, self.first.repository, self.second.repository
+        ));
+        for difference in differences {
+            out.push_str(&format!(
+                "| {} | {} | {} |\n",
+                cell(&difference.aspect),
+                cell(&difference.first),
+                cell(&difference.second)
+            ));
+        }
+    }
+}
@@ -0,0 +1,592 @@
+use crate::credentials::{Credentials, Service};
+use crate::gpt::{ChatSettings, MOCK_PROVIDER, OPENAI_PROVIDER};
+use crate::http::Transport;
+use crate::prompts::{Length, Style};
+use crate::redact::Redactor;
+use crate::templates::Templates;
+use clap::ValueEnum;
+use eyre::{eyre, Error, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The project configuration file, read from the current directory.
+pub const PROJECT_CONFIG_FILE: &str = "gitsum.toml";
+
+/// The providers chat completions can be sent to.
+pub const PROVIDERS: &[&str] = &[OPENAI_PROVIDER, MOCK_PROVIDER];
+
+/// The seed used in reproducible mode, unless another is set.
+pub const REPRODUCIBLE_SEED: i64 = 0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Colored text, printed as summaries are generated
+    #[default]
+    Text,
+
+    /// A single JSON document, printed once everything is summarized
+    Json,
+}
+
+/// Sampling parameters that override the defaults of one or every summary level.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenerationLayer {
+    pub model: Option<String>,
+
+    pub max_tokens: Option<i64>,
+
+    pub temperature: Option<f64>,
+
+    pub top_p: Option<f64>,
+
+    pub frequency_penalty: Option<f64>,
+
+    pub presence_penalty: Option<f64>,
+
+    pub seed: Option<i64>,
+}
+
+impl GenerationLayer {
+    fn apply(&self, settings: &mut ChatSettings) {
+        if let Some(model) = &self.model {
+            settings.model = model.clone();
+        }
+
+        if let Some(max_tokens) = self.max_tokens {
+            settings.max_tokens = max_tokens;
+        }
+
+        if let Some(temperature) = self.temperature {
+            settings.temperature = temperature;
+        }
+
+        if let Some(top_p) = self.top_p {
+            settings.top_p = top_p;
+        }
+
+        if let Some(frequency_penalty) = self.frequency_penalty {
+            settings.frequency_penalty = frequency_penalty;
+        }
+
+        if let Some(presence_penalty) = self.presence_penalty {
+            settings.presence_penalty = presence_penalty;
+        }
+
+        if let Some(seed) = self.seed {
+            settings.seed = Some(seed);
+        }
+    }
+}
+
+/// One source of configuration: a config file, a profile, the environment
+/// or the command line. Unset fields fall through to the layers below.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigLayer {
+    pub provider: Option<String>,
+
+    /// Keys given as command line flags, never read from config files.
+    #[serde(skip)]
+    pub keys: HashMap<Service, String>,
+
+    /// Commands printing the key of a service, such as `gh auth token`.
+    /// Rejected in the project config file, like `keyring`.
+    pub credential_helpers: Option<HashMap<Service, String>>,
+
+    /// Whether keys are looked up in the OS keyring.
+    pub keyring: Option<bool>,
+
+    /// Applies to every summary level.
+    pub generation: GenerationLayer,
+
+    pub file: GenerationLayer,
+
+    pub folder: GenerationLayer,
+
+    pub repository: GenerationLayer,
+
+    pub include: Option<Vec<String>>,
+
+    pub exclude: Option<Vec<String>>,
+
+    pub output: Option<OutputFormat>,
+
+    /// Whether progress bars and summaries are left out of text output.
+    pub quiet: Option<bool>,
+
+    /// Whether failed summaries are recorded rather than stopping the run.
+    pub keep_going: Option<bool>,
+
+    /// Whether summaries are sampled at temperature 0 with a fixed seed, so
+    /// runs on the same contents give the same summaries as far as the model allows.
+    pub reproducible: Option<bool>,
+
+    /// Whether the commit history and CODEOWNERS are fetched and given to the
+    /// folder and repository prompts.
+    pub history: Option<bool>,
+
+    pub concurrency: Option<usize>,
+
+    /// The most requests sent to GitHub and the model each minute, shared
+    /// by everything being summarized.
+    pub rate_limit: Option<u32>,
+
+    pub cache_dir: Option<PathBuf>,
+
+    /// A directory of prompt templates overriding the built-in ones.
+    pub prompts: Option<PathBuf>,
+
+    pub style: Option<Style>,
+
+    pub length: Option<Length>,
+
+    /// Patterns redacted from file contents on top of the built-in ones,
+    /// keyed by the name they are reported as.
+    pub redact: Option<HashMap<String, String>>,
+
+    /// A directory responses are recorded to, never read from config files.
+    #[serde(skip)]
+    pub record: Option<PathBuf>,
+
+    /// A directory responses are replayed from, never read from config files.
+    #[serde(skip)]
+    pub replay: Option<PathBuf>,
+}
+
+impl ConfigLayer {
+    /// Reads the configuration understood from `GITSUM_*` environment
+    /// variables. Keys are read from the environment by `Credentials`.
+    pub fn from_env() -> Result<ConfigLayer, Error> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+
+        Ok(ConfigLayer {
+            provider: var("GITSUM_PROVIDER"),
+            generation: GenerationLayer {
+                model: var("GITSUM_MODEL"),
+                ..Default::default()
+            },
+            output: var("GITSUM_OUTPUT")
+                .map(|output| {
+                    OutputFormat::from_str(&output, true)
+                        .map_err(|e| eyre!("invalid GITSUM_OUTPUT: {}", e))
+                })
+                .transpose()?,
+            concurrency: var("GITSUM_CONCURRENCY")
+                .map(|concurrency| {
+                    concurrency
+                        .parse()
+                        .map_err(|e| eyre!("invalid GITSUM_CONCURRENCY: {}", e))
+                })
+                .transpose()?,
+            cache_dir: var("GITSUM_CACHE_DIR").map(PathBuf::from),
+            prompts: var("GITSUM_PROMPTS").map(PathBuf::from),
+            style: var("GITSUM_STYLE")
+                .map(|style| {
+                    Style::from_str(&style, true).map_err(|e| eyre!("invalid GITSUM_STYLE: {}", e))
+                })
+                .transpose()?,
+            length: var("GITSUM_LENGTH")
+                .map(|length| {
+                    Length::from_str(&length, true)
+                        .map_err(|e| eyre!("invalid GITSUM_LENGTH: {}", e))
+                })
+                .transpose()?,
+            record: var("GITSUM_RECORD").map(PathBuf::from),
+            replay: var("GITSUM_REPLAY").map(PathBuf::from),
+            ..Default::default()
+        })
+    }
+}
+
+/// A configuration file, with its named profiles.
+#[derive(Debug, Default)]
+struct ConfigFile {
+    layer: ConfigLayer,
+
+    profiles: HashMap<String, ConfigLayer>,
+}
+
+impl ConfigFile {
+    /// Reads a config file, returning `None` if it doesn't exist.
+    fn read(path: &Path) -> Result<Option<ConfigFile>, Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return Ok(None),
+        };
+
+        let error = |e: toml::de::Error| eyre!("failed to parse {}: {}", path.display(), e);
+
+        // Profiles are split out before parsing the rest of the file, so
+        // that unknown keys are still rejected.
+        let mut table = toml::from_str::<toml::Table>(&contents).map_err(error)?;
+        let profiles = match table.remove("profiles") {
+            Some(profiles) => profiles.try_into().map_err(error)?,
+            None => HashMap::new(),
+        };
+
+        Ok(Some(ConfigFile {
+            layer: table.try_into().map_err(error)?,
+            profiles,
+        }))
+    }
+
+    /// Fails if the file or one of its profiles configures how keys are
+    /// found. Project files come with untrusted checkouts, and credential
+    /// helpers are run with `sh -c`.
+    fn reject_credentials(&self) -> Result<(), Error> {
+        let layers = std::iter::once(&self.layer).chain(self.profiles.values());
+        for layer in layers {
+            if layer.credential_helpers.is_some() || layer.keyring.is_some() {
+                return Err(eyre!(
+                    "credential_helpers and keyring can only be set in the user config file, not in {}",
+                    PROJECT_CONFIG_FILE
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Decides which files are summarized, from glob patterns matched against
+/// their paths. Files must match an include pattern, if there are any, and
+/// no exclude pattern.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+
+    exclude: Option<GlobSet>,
+}
+
+fn glob_set(patterns: &[String]) -> Result<Option<GlobSet>, Error> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).map_err(|e| eyre!("invalid pattern {}: {}", pattern, e))?);
+    }
+
+    Ok(Some(builder.build()?))
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<PathFilter, Error> {
+        Ok(PathFilter {
+            include: glob_set(include)?,
+            exclude: glob_set(exclude)?,
+        })
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        let included = self
+            .include
+            .as_ref()
+            .map(|include| include.is_match(path))
+            .unwrap_or(true);
+        let excluded = self
+            .exclude
+            .as_ref()
+            .map(|exclude| exclude.is_match(path))
+            .unwrap_or(false);
+
+        included && !excluded
+    }
+}
+
+/// The resolved configuration, combined from the command line, the
+/// environment, the project and user config files and the defaults, in that
+/// order of precedence.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub provider: String,
+
+    pub credentials: Credentials,
+
+    pub file: ChatSettings,
+
+    pub folder: ChatSettings,
+
+    pub repository: ChatSettings,
+
+    pub filter: PathFilter,
+
+    pub output: OutputFormat,
+
+    pub quiet: bool,
+
+    pub keep_going: bool,
+
+    pub history: bool,
+
+    pub concurrency: usize,
+
+    pub rate_limit: Option<u32>,
+
+    pub cache_dir: PathBuf,
+
+    pub templates: Templates,
+
+    pub redactor: Redactor,
+
+    pub transport: Transport,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let home = std::env::var("HOME").unwrap_or_else(|_| String::from("."));
+
+        Config {
+            provider: String::from(OPENAI_PROVIDER),
+            credentials: Credentials::default(),
+            file: ChatSettings::default(),
+            folder: ChatSettings::default(),
+            repository: ChatSettings::default(),
+            filter: PathFilter::default(),
+            output: OutputFormat::Text,
+            quiet: false,
+            keep_going: false,
+            history: false,
+            concurrency: 1,
+            rate_limit: None,
+            cache_dir: PathBuf::from(home).join(".gitsum"),
+            templates: Templates::default(),
+            redactor: Redactor::default(),
+            transport: Transport::Live,
+        }
+    }
+}
+
+/// Returns `~/.config/gitsum/config.toml`, respecting `XDG_CONFIG_HOME`.
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_home = std::env::var("XDG_CONFIG_HOME")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var("HOME")
+                .ok()
+                .map(|home| PathBuf::from(home).join(".config"))
+        })?;
+
+    Some(config_home.join("gitsum").join("config.toml"))
+}
+
+impl Config {
+    /// Loads the user and project config files and resolves them with the
+    /// environment and the command line. The profile, if any, is applied on
+    /// top of each config file that defines it.
+    pub fn load(profile: Option<&str>, cli: ConfigLayer) -> Result<Config, Error> {
+        let profile = profile
+            .map(|p| p.to_string())
+            .or_else(|| std::env::var("GITSUM_PROFILE").ok())
+            .filter(|p| !p.is_empty());
+
+        Config::load_from(
+            user_config_path().as_deref(),
+            Path::new(PROJECT_CONFIG_FILE),
+            profile.as_deref(),
+            ConfigLayer::from_env()?,
+            cli,
+        )
+    }
+
+    /// Resolves the user config file, then the project config file, each
+    /// followed by its `profile` if it defines it, then `env` and `cli`.
+    pub fn load_from(
+        user: Option<&Path>,
+        project: &Path,
+        profile: Option<&str>,
+        env: ConfigLayer,
+        cli: ConfigLayer,
+    ) -> Result<Config, Error> {
+        let user = match user {
+            Some(path) => ConfigFile::read(path)?,
+            None => None,
+        };
+        let project = ConfigFile::read(project)?;
+        if let Some(file) = &project {
+            file.reject_credentials()?;
+        }
+        let files = [user, project].into_iter().flatten();
+
+        let mut layers = vec![];
+        let mut profile_found = false;
+        for mut file in files {
+            layers.push(file.layer);
+
+            if let Some(layer) = profile.and_then(|p| file.profiles.remove(p)) {
+                layers.push(layer);
+                profile_found = true;
+            }
+        }
+
+        if let Some(profile) = profile {
+            if !profile_found {
+                return Err(eyre!("Profile {} not found in any config file", profile));
+            }
+        }
+
+        layers.push(env);
+        layers.push(cli);
+
+        Config::resolve(layers)
+    }
+
+    /// Applies each layer on top of the defaults, in order.
+    pub fn resolve(layers: Vec<ConfigLayer>) -> Result<Config, Error> {
+        let mut config = Config::default();
+        let mut keys = HashMap::new();
+        let mut helpers = HashMap::new();
+        let mut keyring = true;
+        let mut include = vec![];
+        let mut exclude = vec![];
+        let mut prompts = None;
+        let mut style = Style::default();
+        let mut length = None;
+        let mut redact = HashMap::new();
+        let mut reproducible = false;
+
+        for layer in layers {
+            if let Some(provider) = layer.provider {
+                config.provider = provider;
+            }
+
+            keys.extend(layer.keys);
+
+            if let Some(layer_helpers) = layer.credential_helpers {
+                helpers.extend(layer_helpers);
+            }
+
+            if let Some(layer_keyring) = layer.keyring {
+                keyring = layer_keyring;
+            }
+
+            for (level, settings) in [
+                (&layer.file, &mut config.file),
+                (&layer.folder, &mut config.folder),
+                (&layer.repository, &mut config.repository),
+            ] {
+                layer.generation.apply(settings);
+                level.apply(settings);
+            }
+
+            if let Some(patterns) = layer.include {
+                include = patterns;
+            }
+
+            if let Some(patterns) = layer.exclude {
+                exclude = patterns;
+            }
+
+            if let Some(output) = layer.output {
+                config.output = output;
+            }
+
+            if let Some(quiet) = layer.quiet {
+                config.quiet = quiet;
+            }
+
+            if let Some(layer_reproducible) = layer.reproducible {
+                reproducible = layer_reproducible;
+            }
+
+            if let Some(keep_going) = layer.keep_going {
+                config.keep_going = keep_going;
+            }
+
+            if let Some(history) = layer.history {
+                config.history = history;
+            }
+
+            if let Some(concurrency) = layer.concurrency {
+                config.concurrency = concurrency.max(1);
+            }
+
+            if let Some(rate_limit) = layer.rate_limit {
+                config.rate_limit = Some(rate_limit);
+            }
+
+            if let Some(cache_dir) = layer.cache_dir {
+                config.cache_dir = cache_dir;
+            }
+
+            if let Some(dir) = layer.prompts {
+                prompts = Some(dir);
+            }
+
+            if let Some(layer_style) = layer.style {
+                style = layer_style;
+            }
+
+            if let Some(layer_length) = layer.length {
+                length = Some(layer_length);
+            }
+
+            if let Some(patterns) = layer.redact {
+                redact.extend(patterns);
+            }
+
+            if let Some(dir) = layer.record {
+                config.transport = Transport::Record(dir);
+            }
+
+            if let Some(dir) = layer.replay {
+                config.transport = Transport::Replay(dir);
+            }
+        }
+
+        if !PROVIDERS.contains(&config.provider.as_str()) {
+            return Err(eyre!(
+                "Unsupported provider {}, expected one of: {}",
+                config.provider,
+                PROVIDERS.join(", ")
+            ));
+        }
+
+        for settings in [&mut config.file, &mut config.folder, &mut config.repository] {
+            settings.provider = config.provider.clone();
+
+            if reproducible {
+                settings.temperature = 0.0;
+                settings.top_p = 1.0;
+                settings.seed = settings.seed.or(Some(REPRODUCIBLE_SEED));
+            }
+        }
+
+        config.filter = PathFilter::new(&include, &exclude)?;
+        config.credentials = Credentials::new(keys, helpers, keyring);
+        config.redactor = Redactor::new(&redact)?;
+
+        config.templates = match prompts {
+            Some(dir) => Templates::from_dir(&dir, style, length)?,
+            None => Templates::builtin(style, length),
+        };
+
+        Ok(config)
+    }
+
+    /// Returns the github key, if any. Public repositories can be fetched without one.
+    pub fn github_key(&self) -> Option<String> {
+        self.credentials.get(Service::Github)
+    }
+
+    /// Returns the OpenAI key. The mock provider doesn't need one, so a
+    /// placeholder is returned if none is set.
+    pub fn open_ai_key(&self) -> Result<String, Error> {
+        if self.provider == MOCK_PROVIDER {
+            return Ok(self
+                .credentials
+                .get(Service::Openai)
+                .unwrap_or_else(|| String::from(MOCK_PROVIDER)));
+        }
+
+        self.credentials.require(Service::Openai)
+    }
+}
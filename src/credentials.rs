@@ -0,0 +1,220 @@
+use clap::ValueEnum;
+use eyre::{eyre, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::process::Command;
+
+/// The service name secrets are stored under in the OS keyring.
+pub const KEYRING_SERVICE: &str = "gitsum";
+
+/// The `.env` file read from the current directory.
+pub const DOTENV_FILE: &str = ".env";
+
+/// A service gitsum authenticates with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Service {
+    Github,
+
+    Gitlab,
+
+    Openai,
+
+    Anthropic,
+}
+
+impl Service {
+    pub const ALL: [Service; 4] = [
+        Service::Github,
+        Service::Gitlab,
+        Service::Openai,
+        Service::Anthropic,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Service::Github => "github",
+            Service::Gitlab => "gitlab",
+            Service::Openai => "openai",
+            Service::Anthropic => "anthropic",
+        }
+    }
+
+    /// The environment variables checked for the key, in order.
+    pub fn env_vars(&self) -> &'static [&'static str] {
+        match self {
+            Service::Github => &["GITHUB_KEY", "GITHUB_TOKEN", "GH_TOKEN"],
+            Service::Gitlab => &["GITLAB_KEY", "GITLAB_TOKEN"],
+            Service::Openai => &["OPEN_AI_KEY", "OPENAI_API_KEY"],
+            Service::Anthropic => &["ANTHROPIC_API_KEY"],
+        }
+    }
+
+    /// The helper command used when none is configured.
+    fn default_helper(&self) -> Option<&'static str> {
+        match self {
+            Service::Github => Some("gh auth token"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Where a key was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Flag,
+
+    Env(&'static str),
+
+    DotEnv(&'static str),
+
+    Keyring,
+
+    Helper(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Flag => write!(f, "command line flag"),
+            Source::Env(var) => write!(f, "environment variable {}", var),
+            Source::DotEnv(var) => write!(f, "{} in {}", var, DOTENV_FILE),
+            Source::Keyring => write!(f, "OS keyring"),
+            Source::Helper(command) => write!(f, "helper command `{}`", command),
+        }
+    }
+}
+
+/// Resolves the keys of every service from, in order: command line flags,
+/// environment variables, the `.env` file, the OS keyring and helper
+/// commands such as `gh auth token`.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub flags: HashMap<Service, String>,
+
+    pub dotenv: HashMap<String, String>,
+
+    pub helpers: HashMap<Service, String>,
+
+    pub keyring: bool,
+}
+
+fn keyring_entry(service: Service) -> Result<keyring::Entry, Error> {
+    keyring::Entry::new(KEYRING_SERVICE, service.name())
+        .map_err(|e| eyre!("failed to open keyring entry for {}: {}", service, e))
+}
+
+/// Runs a helper command through the shell, returning its trimmed output if it succeeds.
+fn run_helper(command: &str) -> Option<String> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    }
+    .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string()).filter(|key| !key.is_empty())
+}
+
+impl Credentials {
+    /// Creates a resolver, reading the `.env` file in the current directory if there is one.
+    pub fn new(
+        flags: HashMap<Service, String>,
+        helpers: HashMap<Service, String>,
+        keyring: bool,
+    ) -> Credentials {
+        let dotenv = dotenvy::from_path_iter(DOTENV_FILE)
+            .map(|iter| iter.filter_map(|item| item.ok()).collect())
+            .unwrap_or_default();
+
+        Credentials {
+            flags,
+            dotenv,
+            helpers,
+            keyring,
+        }
+    }
+
+    /// Returns the key for `service` along with where it was found.
+    pub fn resolve(&self, service: Service) -> Option<(String, Source)> {
+        let non_empty = |key: &String| !key.is_empty();
+
+        if let Some(key) = self.flags.get(&service).filter(|k| non_empty(k)) {
+            return Some((key.clone(), Source::Flag));
+        }
+
+        for var in service.env_vars() {
+            if let Some(key) = std::env::var(var).ok().filter(non_empty) {
+                return Some((key, Source::Env(var)));
+            }
+        }
+
+        for var in service.env_vars() {
+            if let Some(key) = self.dotenv.get(*var).filter(|k| non_empty(k)) {
+                return Some((key.clone(), Source::DotEnv(var)));
+            }
+        }
+
+        if self.keyring {
+            let key = keyring_entry(service)
+                .and_then(|entry| entry.get_password().map_err(Error::from))
+                .ok()
+                .filter(non_empty);
+
+            if let Some(key) = key {
+                return Some((key, Source::Keyring));
+            }
+        }
+
+        let helper = self
+            .helpers
+            .get(&service)
+            .map(|h| h.as_str())
+            .or_else(|| service.default_helper())?;
+
+        run_helper(helper).map(|key| (key, Source::Helper(helper.to_string())))
+    }
+
+    pub fn get(&self, service: Service) -> Option<String> {
+        self.resolve(service).map(|(key, _)| key)
+    }
+
+    /// Returns the key for `service`, or an error explaining where it can be set.
+    pub fn require(&self, service: Service) -> Result<String, Error> {
+        self.get(service).ok_or_else(|| {
+            eyre!(
+                "No {} key found. Pass it as a flag, set {}, add it to {}, store it with \
+                 `gitsum auth set {}` or configure a credential helper",
+                service,
+                service.env_vars().join(" or "),
+                DOTENV_FILE,
+                service
+            )
+        })
+    }
+}
+
+/// Stores a key in the OS keyring.
+pub fn store(service: Service, key: &str) -> Result<(), Error> {
+    keyring_entry(service)?
+        .set_password(key)
+        .map_err(|e| eyre!("failed to store {} key in keyring: {}", service, e))
+}
+
+/// Removes a key from the OS keyring.
+pub fn delete(service: Service) -> Result<(), Error> {
+    keyring_entry(service)?
+        .delete_credential()
+        .map_err(|e| eyre!("failed to delete {} key from keyring: {}", service, e))
+}
@@ -0,0 +1,457 @@
+use crate::cache::{one_line, SummaryTree};
+use crate::git::RepositoryContent;
+use clap::ValueEnum;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DiagramKind {
+    /// The folder tree of the repository
+    Tree,
+
+    /// The imports between folders
+    Deps,
+
+    /// The imports between files
+    Modules,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DiagramFormat {
+    /// Mermaid, for markdown documents
+    Mermaid,
+
+    /// DOT, for Graphviz
+    Dot,
+}
+
+/// A directed graph of folders, labelled with their summaries.
+#[derive(Debug, Default)]
+pub struct Graph {
+    pub nodes: BTreeMap<String, String>,
+
+    pub edges: BTreeSet<(String, String)>,
+}
+
+/// Returns the folder a file lives in, or `""` for the repository root.
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/')
+        .map(|(dir, _)| dir)
+        .unwrap_or_default()
+}
+
+impl Graph {
+    fn add_node(&mut self, path: &str, summaries: &SummaryTree) {
+        let name = if path.is_empty() { "/" } else { path };
+
+        let label = match summaries.folders.get(path) {
+            Some(folder) if !folder.summary.is_empty() => {
+                format!("{}\n{}", name, one_line(&folder.summary))
+            }
+            _ => name.to_string(),
+        };
+
+        self.nodes.insert(path.to_string(), label);
+    }
+
+    /// Builds the folder tree of the repository, with an edge from each folder to its subfolders.
+    pub fn tree(contents: &RepositoryContent, summaries: &SummaryTree) -> Graph {
+        let mut graph = Graph::default();
+
+        for name in contents.folders.keys() {
+            graph.add_node(name, summaries);
+
+            if !name.is_empty() {
+                let parent = parent(name);
+
+                graph.add_node(parent, summaries);
+                graph.edges.insert((parent.to_string(), name.to_string()));
+            }
+        }
+
+        graph
+    }
+
+    fn add_file_node(&mut self, path: &str, summaries: &SummaryTree) {
+        let summary = summaries
+            .folders
+            .get(parent(path))
+            .and_then(|folder| folder.files.iter().find(|f| f.name == path));
+
+        let label = match summary {
+            Some(file) if !file.summary.is_empty() => {
+                format!("{}\n{}", path, one_line(&file.summary))
+            }
+            _ => path.to_string(),
+        };
+
+        self.nodes.insert(path.to_string(), label);
+    }
+
+    /// Builds the dependency graph between folders by statically parsing the
+    /// imports of every file, with an edge from a folder to each folder it imports from.
+    pub fn dependencies(contents: &RepositoryContent, summaries: &SummaryTree) -> Graph {
+        let resolver = Resolver::new(contents);
+        let mut graph = Graph::default();
+
+        for folder in contents.folders.values() {
+            for (name, file) in &folder.files {
+                for target in resolver.imports(name, &file.content) {
+                    let from = parent(name);
+                    let to = if resolver.files.contains(target.as_str()) {
+                        parent(&target)
+                    } else {
+                        &target
+                    };
+
+                    if from == to {
+                        continue;
+                    }
+
+                    graph.add_node(from, summaries);
+                    graph.add_node(to, summaries);
+                    graph.edges.insert((from.to_string(), to.to_string()));
+                }
+            }
+        }
+
+        graph
+    }
+
+    /// Builds the dependency graph between individual files, labelled with
+    /// the file summaries. Imports of a whole folder point at the folder.
+    pub fn modules(contents: &RepositoryContent, summaries: &SummaryTree) -> Graph {
+        let resolver = Resolver::new(contents);
+        let mut graph = Graph::default();
+
+        for folder in contents.folders.values() {
+            for (name, file) in &folder.files {
+                for target in resolver.imports(name, &file.content) {
+                    if *name == target {
+                        continue;
+                    }
+
+                    graph.add_file_node(name, summaries);
+                    if resolver.files.contains(target.as_str()) {
+                        graph.add_file_node(&target, summaries);
+                    } else {
+                        graph.add_node(&target, summaries);
+                    }
+
+                    graph.edges.insert((name.clone(), target));
+                }
+            }
+        }
+
+        graph
+    }
+
+    pub fn render(&self, format: DiagramFormat) -> String {
+        match format {
+            DiagramFormat::Mermaid => self.mermaid(),
+            DiagramFormat::Dot => self.dot(),
+        }
+    }
+
+    fn mermaid(&self) -> String {
+        let ids = self
+            .nodes
+            .keys()
+            .enumerate()
+            .map(|(i, path)| (path.as_str(), format!("n{}", i)))
+            .collect::<BTreeMap<&str, String>>();
+
+        let mut out = String::from("graph TD\n");
+        for (path, label) in &self.nodes {
+            out.push_str(&format!(
+                "    {}[\"{}\"]\n",
+                ids[path.as_str()],
+                label.replace('"', "#quot;").replace('\n', "<br/>")
+            ));
+        }
+
+        for (from, to) in &self.edges {
+            out.push_str(&format!(
+                "    {} --> {}\n",
+                ids[from.as_str()],
+                ids[to.as_str()]
+            ));
+        }
+
+        out
+    }
+
+    fn dot(&self) -> String {
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+
+        let mut out = String::from("digraph gitsum {\n    rankdir=LR;\n    node [shape=box];\n");
+        for (path, label) in &self.nodes {
+            out.push_str(&format!(
+                "    \"{}\" [label=\"{}\"];\n",
+                escape(path),
+                escape(label).replace('\n', "\\n")
+            ));
+        }
+
+        for (from, to) in &self.edges {
+            out.push_str(&format!(
+                "    \"{}\" -> \"{}\";\n",
+                escape(from),
+                escape(to)
+            ));
+        }
+
+        out.push_str("}\n");
+
+        out
+    }
+}
+
+/// Resolves the imports of a file to the files and folders of the repository they refer to.
+struct Resolver<'a> {
+    files: HashSet<&'a str>,
+
+    folders: HashSet<&'a str>,
+
+    go_module: Option<String>,
+
+    rust_use: Regex,
+
+    rust_mod: Regex,
+
+    js_import: Regex,
+
+    python_from: Regex,
+
+    python_import: Regex,
+
+    go_import: Regex,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(contents: &'a RepositoryContent) -> Self {
+        let files = contents
+            .folders
+            .values()
+            .flat_map(|folder| folder.files.keys().map(|k| k.as_str()))
+            .collect::<HashSet<&str>>();
+
+        let go_module = contents
+            .folders
+            .values()
+            .find_map(|folder| folder.files.get("go.mod"))
+            .and_then(|file| {
+                file.content
+                    .lines()
+                    .find_map(|line| line.strip_prefix("module "))
+                    .map(|module| module.trim().to_string())
+            });
+
+        Resolver {
+            files,
+            folders: contents.folders.keys().map(|k| k.as_str()).collect(),
+            go_module,
+            rust_use: Regex::new(r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?use\s+((?:crate|super|self)(?:::\w+)+)")
+                .unwrap(),
+            rust_mod: Regex::new(r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?mod\s+(\w+)\s*;").unwrap(),
+            js_import: Regex::new(
+                r#"(?:import\s[^'"]*?from\s*|import\s*\(?\s*|require\s*\(\s*)['"](\.{1,2}/[^'"]+)['"]"#,
+            )
+            .unwrap(),
+            python_from: Regex::new(r"(?m)^\s*from\s+(\.*[\w.]*)\s+import").unwrap(),
+            python_import: Regex::new(r"(?m)^\s*import\s+([\w.]+)").unwrap(),
+            go_import: Regex::new(r#""([^"\s]+)""#).unwrap(),
+        }
+    }
+
+    /// Returns the first candidate path that is a file or folder of the repository.
+    fn resolve(&self, candidates: Vec<String>) -> Option<String> {
+        candidates
+            .into_iter()
+            .map(|candidate| normalize(&candidate))
+            .find(|candidate| {
+                self.files.contains(candidate.as_str()) || self.folders.contains(candidate.as_str())
+            })
+    }
+
+    fn imports(&self, path: &str, content: &str) -> BTreeSet<String> {
+        let extension = path
+            .rsplit_once('.')
+            .map(|(_, ext)| ext)
+            .unwrap_or_default();
+
+        let candidates = match extension {
+            "rs" => self.rust(path, content),
+            "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => self.javascript(path, content),
+            "py" => self.python(path, content),
+            "go" => self.go(content),
+            _ => vec![],
+        };
+
+        candidates
+            .into_iter()
+            .filter_map(|c| self.resolve(c))
+            .collect()
+    }
+
+    fn rust(&self, path: &str, content: &str) -> Vec<Vec<String>> {
+        let dir = parent(path);
+        let stem = path
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches(".rs");
+
+        // Modules declared in `main.rs`, `lib.rs` and `mod.rs` live next to
+        // them, otherwise in a folder named after the declaring file.
+        let module_dir = if ["main", "lib", "mod"].contains(&stem) {
+            dir.to_string()
+        } else {
+            join(dir, stem)
+        };
+
+        let crate_root = match path.rfind("src/") {
+            Some(i) => &path[..i + 3],
+            None => dir,
+        };
+
+        let mut candidates = vec![];
+        for capture in self.rust_mod.captures_iter(content) {
+            let base = join(&module_dir, &capture[1]);
+
+            candidates.push(vec![
+                format!("{}.rs", base),
+                format!("{}/mod.rs", base),
+                base,
+            ]);
+        }
+
+        for capture in self.rust_use.captures_iter(content) {
+            let mut segments = capture[1].split("::").collect::<Vec<&str>>();
+
+            let root = match segments.remove(0) {
+                "crate" => crate_root.to_string(),
+                "super" => dir.to_string(),
+                _ => module_dir.clone(),
+            };
+
+            // `use crate::a::b::C` may refer to `a/b.rs`, `a.rs` or the
+            // folder `a`, so try the longest path first.
+            let mut paths = vec![];
+            for i in (1..=segments.len()).rev() {
+                let base = join(&root, &segments[..i].join("/"));
+
+                paths.push(format!("{}.rs", base));
+                paths.push(format!("{}/mod.rs", base));
+                paths.push(base);
+            }
+
+            candidates.push(paths);
+        }
+
+        candidates
+    }
+
+    fn javascript(&self, path: &str, content: &str) -> Vec<Vec<String>> {
+        let dir = parent(path);
+
+        self.js_import
+            .captures_iter(content)
+            .map(|capture| {
+                let base = join(dir, &capture[1]);
+
+                let mut paths = vec![base.clone()];
+                for ext in ["js", "jsx", "ts", "tsx", "mjs"] {
+                    paths.push(format!("{}.{}", base, ext));
+                    paths.push(format!("{}/index.{}", base, ext));
+                }
+
+                paths
+            })
+            .collect()
+    }
+
+    fn python(&self, path: &str, content: &str) -> Vec<Vec<String>> {
+        let dir = parent(path);
+
+        let modules = self
+            .python_from
+            .captures_iter(content)
+            .chain(self.python_import.captures_iter(content))
+            .map(|capture| capture[1].to_string());
+
+        modules
+            .map(|module| {
+                let dots = module.chars().take_while(|c| *c == '.').count();
+                let relative = module[dots..].replace('.', "/");
+
+                // Relative imports start from the importing package, with
+                // each extra dot going up one level.
+                let base = if dots > 0 {
+                    let mut base = dir;
+                    for _ in 1..dots {
+                        base = parent(base);
+                    }
+
+                    join(base, &relative)
+                } else {
+                    relative
+                };
+
+                vec![
+                    format!("{}.py", base),
+                    format!("{}/__init__.py", base),
+                    base,
+                ]
+            })
+            .collect()
+    }
+
+    fn go(&self, content: &str) -> Vec<Vec<String>> {
+        let module = match &self.go_module {
+            Some(module) => module,
+            None => return vec![],
+        };
+
+        let imports = match content.find("import") {
+            Some(start) => &content[start..],
+            None => return vec![],
+        };
+
+        self.go_import
+            .captures_iter(imports)
+            .filter_map(|capture| {
+                capture[1]
+                    .strip_prefix(module.as_str())
+                    .map(|package| vec![package.trim_start_matches('/').to_string()])
+            })
+            .collect()
+    }
+}
+
+fn join(dir: &str, path: &str) -> String {
+    if dir.is_empty() {
+        path.to_string()
+    } else if path.is_empty() {
+        dir.to_string()
+    } else {
+        format!("{}/{}", dir, path)
+    }
+}
+
+/// Resolves `.` and `..` segments in a repository path.
+fn normalize(path: &str) -> String {
+    let mut segments: Vec<&str> = vec![];
+
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    segments.join("/")
+}
@@ -0,0 +1,216 @@
+use crate::cache::SummaryTree;
+use crate::git::RepositoryContent;
+use crate::gpt::ChatSettings;
+use crate::prompts::DocumentPrompt;
+use colored::*;
+use eyre::Error;
+use similar::{ChangeTag, TextDiff};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files that describe how a project is built and run, included verbatim in the prompts.
+const ENTRY_POINTS: &[&str] = &[
+    "main.rs",
+    "lib.rs",
+    "cli.rs",
+    "Cargo.toml",
+    "package.json",
+    "pyproject.toml",
+    "setup.py",
+    "__main__.py",
+    "main.py",
+    "go.mod",
+    "main.go",
+    "index.js",
+    "index.ts",
+    "Makefile",
+    "Dockerfile",
+];
+
+const MAX_LAYOUT_CHARS: usize = 6000;
+
+const MAX_ENTRY_POINT_CHARS: usize = 4000;
+
+fn truncate(text: &str, max: usize) -> String {
+    text.chars().take(max).collect()
+}
+
+/// Lists every folder and file with its summary, in path order.
+pub fn layout(summaries: &SummaryTree) -> String {
+    let mut folders = summaries.folders.iter().collect::<Vec<_>>();
+    folders.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut layout = String::new();
+    for (name, folder) in folders {
+        let name = if name.is_empty() { "/" } else { name };
+        layout.push_str(&format!("- {}: {}\n", name, folder.summary));
+
+        let mut files = folder.files.iter().collect::<Vec<_>>();
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for file in files {
+            layout.push_str(&format!("  - {}: {}\n", file.name, file.summary));
+        }
+    }
+
+    truncate(&layout, MAX_LAYOUT_CHARS)
+}
+
+/// Concatenates the contents of the repository's entry points and manifests.
+pub fn entry_points(contents: &RepositoryContent) -> String {
+    let mut files = contents
+        .folders
+        .values()
+        .flat_map(|folder| folder.files.iter())
+        .filter(|(name, _)| ENTRY_POINTS.contains(&name.rsplit('/').next().unwrap_or_default()))
+        .collect::<Vec<_>>();
+    files.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut entry_points = String::new();
+    for (name, file) in files {
+        entry_points.push_str(&format!("### {}\n{}\n", name, file.content));
+    }
+
+    truncate(&entry_points, MAX_ENTRY_POINT_CHARS)
+}
+
+/// Generates documentation for a repository from its cached summaries.
+#[derive(Debug)]
+pub struct Docs<'a> {
+    pub open_ai_key: String,
+
+    pub settings: ChatSettings,
+
+    pub repository: String,
+
+    pub summaries: &'a SummaryTree,
+
+    pub contents: &'a RepositoryContent,
+}
+
+impl<'a> Docs<'a> {
+    pub fn new(
+        open_ai_key: &str,
+        settings: ChatSettings,
+        repository: &str,
+        summaries: &'a SummaryTree,
+        contents: &'a RepositoryContent,
+    ) -> Self {
+        Docs {
+            open_ai_key: open_ai_key.to_string(),
+            settings,
+            repository: repository.to_string(),
+            summaries,
+            contents,
+        }
+    }
+
+    pub async fn readme(&self) -> Result<String, Error> {
+        let prompt = DocumentPrompt::readme(
+            &self.repository,
+            &self.summaries.summary,
+            &layout(self.summaries),
+            &entry_points(self.contents),
+        );
+
+        Ok(prompt
+            .send(&self.open_ai_key, &self.settings)
+            .await?
+            .content)
+    }
+
+    pub async fn architecture(&self) -> Result<String, Error> {
+        let prompt = DocumentPrompt::architecture(
+            &self.repository,
+            &self.summaries.summary,
+            &layout(self.summaries),
+            &entry_points(self.contents),
+        );
+
+        Ok(prompt
+            .send(&self.open_ai_key, &self.settings)
+            .await?
+            .content)
+    }
+
+    /// Writes a generated document to `path`. Existing files are never
+    /// overwritten: they are left alone while the generated document, or
+    /// its merge with the existing one, is written next to them and the
+    /// difference is printed.
+    pub async fn write(&self, path: &Path, generated: &str, merge: bool) -> Result<(), Error> {
+        let existing = match fs::read_to_string(path) {
+            Ok(existing) => existing,
+            Err(_) => {
+                fs::write(path, generated)?;
+                println!("{} {}", "Created".green().bold(), path.display());
+
+                return Ok(());
+            }
+        };
+
+        if existing.trim() == generated.trim() {
+            println!("{} {}", "Unchanged".green().bold(), path.display());
+
+            return Ok(());
+        }
+
+        let generated_path = generated_path(path);
+
+        if merge {
+            let merged = DocumentPrompt::merge(&existing, generated)
+                .send(&self.open_ai_key, &self.settings)
+                .await?
+                .content;
+
+            print_diff(&existing, &merged);
+            fs::write(&generated_path, merged)?;
+            println!(
+                "{} {} into {}, review it and replace {} with it",
+                "Merged".green().bold(),
+                path.display(),
+                generated_path.display(),
+                path.display()
+            );
+
+            return Ok(());
+        }
+
+        print_diff(&existing, generated);
+        fs::write(&generated_path, generated)?;
+        println!(
+            "{} {} already exists, wrote {} instead",
+            "Skipped".yellow().bold(),
+            path.display(),
+            generated_path.display()
+        );
+
+        Ok(())
+    }
+}
+
+/// Returns the path generated documents are written to when `path` already
+/// exists, e.g. `README.gitsum.md` for `README.md`.
+fn generated_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{}.gitsum.{}", stem, ext.to_string_lossy())),
+        None => path.with_file_name(format!("{}.gitsum", stem)),
+    }
+}
+
+fn print_diff(old: &str, new: &str) {
+    let diff = TextDiff::from_lines(old, new);
+
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Delete => print!("{}", format!("-{}", change).red()),
+            ChangeTag::Insert => print!("{}", format!("+{}", change).green()),
+            ChangeTag::Equal => continue,
+        }
+
+        if change.missing_newline() {
+            println!();
+        }
+    }
+}
@@ -1,13 +1,31 @@
+use crate::cache::{one_line, Failure, FailureKind, SummaryTree};
+use crate::config::{Config, OutputFormat, PathFilter};
+use crate::history::{CodeOwners, History};
+use crate::http;
+use crate::journal::{prompt_hash, Journal};
+use crate::languages::language;
+use crate::manifests::{Dependencies, Dependency};
+use crate::outline::outline;
+use crate::progress::Progress;
 use crate::prompts::{
-    FileSummaryPrompt, FileSummaryResponse, FolderWideSummaryPrompt, FolderWideSummaryResponse,
-    RepositorySummaryPrompt,
+    DependencyPrompt, FileSummaryPrompt, FileSummaryResponse, FolderWideSummaryPrompt,
+    FolderWideSummaryResponse, RepositorySummaryPrompt,
 };
+use crate::redact::{Redaction, Redactor};
+use crate::stats::RepositoryStats;
+use crate::templates::RepositoryMeta;
 use colored::*;
 use eyre::{eyre, Error};
-use reqwest::Client;
+use futures::stream::{self, StreamExt};
+use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use tiktoken_rs::r50k_base;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tiktoken_rs::{r50k_base, CoreBPE};
+use tracing::{info, instrument, warn};
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct File {
@@ -32,7 +50,7 @@ impl File {
 pub struct Folder {
     pub name: String,
 
-    pub files: HashMap<String, File>,
+    pub files: BTreeMap<String, File>,
 }
 
 impl Folder {
@@ -46,28 +64,124 @@ impl Folder {
 
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct RepositoryContent {
-    pub folders: HashMap<String, Folder>,
+    pub folders: BTreeMap<String, Folder>,
 
     pub readme: String,
+
+    /// What was redacted from the files and commit history, in path order.
+    #[serde(default)]
+    pub redactions: Vec<Redaction>,
+
+    /// The recent commits and CODEOWNERS, when history was asked for.
+    #[serde(default)]
+    pub history: History,
 }
 
-impl ToString for RepositoryContent {
-    fn to_string(&self) -> String {
-        let mut content = String::new();
+/// Folders that are never read from a local checkout.
+const IGNORED_FOLDERS: &[&str] = &["target", "node_modules"];
+
+/// Files larger than this are skipped when reading a local checkout.
+const MAX_LOCAL_FILE_SIZE: u64 = 1024 * 1024;
+
+impl RepositoryContent {
+    /// Reads a local checkout, skipping hidden and build folders, binary
+    /// files, files that don't match `filter` and any file named in
+    /// `ignored_files`. Paths are relative to `root` and use `/` as a
+    /// separator, like those fetched from github.
+    pub fn from_dir(
+        root: &Path,
+        filter: &PathFilter,
+        ignored_files: &[&str],
+    ) -> Result<RepositoryContent, Error> {
+        if !root.is_dir() {
+            return Err(eyre!("{} is not a directory", root.display()));
+        }
+
+        let mut content = RepositoryContent::default();
+        let mut dirs = vec![(root.to_path_buf(), String::new())];
+
+        while let Some((dir, name)) = dirs.pop() {
+            let mut folder = Folder::new(&name);
+
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let path = if name.is_empty() {
+                    file_name.clone()
+                } else {
+                    format!("{}/{}", name, file_name)
+                };
+
+                let file_type = entry.file_type()?;
+                if file_type.is_dir() {
+                    if !file_name.starts_with('.') && !IGNORED_FOLDERS.contains(&file_name.as_str())
+                    {
+                        dirs.push((entry.path(), path));
+                    }
+                } else if file_type.is_file()
+                    && filter.matches(&path)
+                    && !ignored_files.contains(&file_name.as_str())
+                    && entry.metadata()?.len() <= MAX_LOCAL_FILE_SIZE
+                {
+                    if let Ok(text) = fs::read_to_string(entry.path()) {
+                        folder
+                            .files
+                            .insert(path.clone(), File::new(&path, &text, ""));
+                    }
+                }
+            }
+
+            content.folders.insert(name, folder);
+        }
+
+        Ok(content)
+    }
+}
+
+impl RepositoryContent {
+    /// Redacts secrets and personal data from every file and from the
+    /// messages and authors of the commits, so that they never reach a
+    /// prompt or the cache.
+    pub fn redact(&mut self, redactor: &Redactor) {
+        for folder in self.folders.values_mut() {
+            for file in folder.files.values_mut() {
+                let (content, redactions) = redactor.redact(&file.name, &file.content);
+
+                file.content = content;
+                self.redactions.extend(redactions);
+            }
+        }
+
+        for commit in &mut self.history.commits {
+            let source = format!("commit {}", commit.sha.get(..7).unwrap_or(&commit.sha));
 
+            let (message, redactions) = redactor.redact(&source, &commit.message);
+            commit.message = message;
+            self.redactions.extend(redactions);
+
+            let (author, redactions) = redactor.redact(&source, &commit.author);
+            commit.author = author;
+            self.redactions.extend(redactions);
+        }
+
+        self.redactions
+            .sort_by(|a, b| a.file.cmp(&b.file).then(a.line.cmp(&b.line)));
+    }
+}
+
+impl fmt::Display for RepositoryContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for (name, folder) in &self.folders {
-            content.push_str(&format!("## name: {}, files: {}\n", name, {
-                let mut files = String::new();
+            write!(f, "## name: {}, files: ", name)?;
 
-                for (name, file) in &folder.files {
-                    files.push_str(&format!("{}: {}\n", name, file.content));
-                }
+            for (name, file) in &folder.files {
+                writeln!(f, "{}: {}", name, file.content)?;
+            }
 
-                files
-            }));
+            writeln!(f)?;
         }
 
-        content
+        Ok(())
     }
 }
 
@@ -99,6 +213,47 @@ struct GitTreeResponse {
     tree: Vec<GitTree>,
 }
 
+/// Creates a github api request, authenticated if a key was given. Public
+/// repositories can be fetched without one, with a lower rate limit.
+pub(crate) fn request(client: &Client, git_key: &str, url: &str) -> RequestBuilder {
+    let request = client
+        .get(url)
+        .header("User-Agent", String::from("baribari2"));
+
+    if git_key.is_empty() {
+        request
+    } else {
+        request.header("Authorization", format!("Bearer {}", git_key))
+    }
+}
+
+/// The most importing files listed for each dependency in the dependency prompt.
+const MAX_FILES_PER_DEPENDENCY: usize = 5;
+
+/// The most tokens of a file, and its outline, sent to be summarized.
+const MAX_FILE_TOKENS: usize = 4096;
+
+/// Decides what is sent to summarize a file: its contents along with its
+/// outline if both fit in `MAX_FILE_TOKENS`, its contents alone, or only the
+/// outline for files too large to send. Returns `None` if nothing fits.
+fn summary_input<'a>(bpe: &CoreBPE, file: &'a File) -> Option<(&'a str, Option<String>)> {
+    let tokens = |text: &str| bpe.encode_with_special_tokens(text).len();
+
+    let content_tokens = tokens(&file.content);
+    let outline = outline(&file.name, &file.content);
+    let outline_tokens = outline.as_deref().map(tokens).unwrap_or_default();
+
+    if content_tokens + outline_tokens <= MAX_FILE_TOKENS {
+        Some((&file.content, outline))
+    } else if content_tokens <= MAX_FILE_TOKENS {
+        Some((&file.content, None))
+    } else if outline.is_some() && outline_tokens <= MAX_FILE_TOKENS {
+        Some(("", outline))
+    } else {
+        None
+    }
+}
+
 #[derive(Debug)]
 pub struct Git {
     pub git_key: String,
@@ -112,6 +267,16 @@ pub struct Git {
     pub repository_name: String,
 
     pub repository_content: RepositoryContent,
+
+    pub config: Config,
+
+    pub progress: Arc<Progress>,
+
+    /// Where completed summaries are checkpointed, and reused from when resuming.
+    pub journal: Option<Arc<Journal>>,
+
+    /// What failed to be summarized when keeping going.
+    failures: Mutex<Vec<Failure>>,
 }
 
 impl Git {
@@ -123,6 +288,61 @@ impl Git {
             repository_username: username.to_string(),
             repository_name: repo.to_string(),
             repository_content: RepositoryContent::default(),
+            config: Config::default(),
+            progress: Arc::new(Progress::default()),
+            journal: None,
+            failures: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn with_config(mut self, config: Config) -> Git {
+        self.config = config;
+        self
+    }
+
+    /// Shares the progress of summarizing with the caller.
+    pub fn with_progress(mut self, progress: Arc<Progress>) -> Git {
+        self.progress = progress;
+        self
+    }
+
+    /// Checkpoints summaries to the journal as they are completed.
+    pub fn with_journal(mut self, journal: Arc<Journal>) -> Git {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Records a failed summary when keeping going, and returns the error otherwise.
+    fn fail(&self, kind: FailureKind, path: &str, error: Error) -> Result<(), Error> {
+        if !self.config.keep_going {
+            return Err(error);
+        }
+
+        warn!(?kind, path, %error, "failed to summarize");
+        self.failures
+            .lock()
+            .unwrap()
+            .push(Failure::new(kind, path, &error));
+
+        Ok(())
+    }
+
+    /// Whether progress should be printed as summaries are generated.
+    pub fn verbose(&self) -> bool {
+        self.config.output == OutputFormat::Text && !self.config.quiet
+    }
+
+    /// The history of the repository, if it was fetched.
+    fn history(&self) -> Option<&History> {
+        Some(&self.repository_content.history).filter(|h| !h.is_empty())
+    }
+
+    /// The repository metadata passed to prompt templates.
+    fn meta(&self) -> RepositoryMeta {
+        RepositoryMeta {
+            owner: self.repository_username.clone(),
+            name: self.repository_name.clone(),
+            branch: self.branch.clone(),
         }
     }
 
@@ -142,17 +362,23 @@ impl Git {
             self.repository_username, self.repository_name, self.branch
         );
 
-        let response = client
-            .get(repo_url)
-            .header("User-Agent", String::from("baribari2"))
-            .header("Authorization", format!("Bearer {}", &self.git_key))
-            .send()
-            .await?
-            .text()
-            .await?;
+        let response = http::send(request(&client, &self.git_key, &repo_url)).await?;
 
         let tree_response: GitTreeResponse = serde_json::from_str(&response)?;
 
+        // The root of the repository, which holds the README and manifests.
+        self.repository_content
+            .folders
+            .insert(String::new(), Folder::new(""));
+
+        self.progress.start_fetch(
+            tree_response
+                .tree
+                .iter()
+                .filter(|item| item.object_type == "blob" && self.config.filter.matches(&item.path))
+                .count(),
+        );
+
         for item in tree_response.tree {
             if item.object_type == "tree" {
                 self.repository_content
@@ -167,43 +393,24 @@ impl Git {
                 self.repository_username, self.repository_name, name, self.branch
             );
 
-            let response = client
-                .get(&folder_url)
-                .header("User-Agent", String::from("baribari2"))
-                .header("Authorization", format!("Bearer {}", &self.git_key))
-                .send()
-                .await?
-                .text()
-                .await?;
+            let response = http::send(request(&client, &self.git_key, &folder_url)).await?;
 
             let folder_contents: Vec<GitFile> = serde_json::from_str(&response)?;
 
             for file in folder_contents {
-                if file.object_type == "file" {
+                if file.object_type == "file" && self.config.filter.matches(&file.path) {
                     let file_url = format!(
                         "https://api.github.com/repos/{}/{}/contents/{}?ref={}",
                         self.repository_username, self.repository_name, file.path, self.branch
                     );
 
-                    let response = client
-                        .get(&file_url)
-                        .header("User-Agent", String::from("baribari2"))
-                        .header("Authorization", format!("Bearer {}", &self.git_key))
-                        .send()
-                        .await?
-                        .text()
-                        .await?;
+                    let response = http::send(request(&client, &self.git_key, &file_url)).await?;
 
                     let file_response: GitFileResponse = serde_json::from_str(&response)?;
 
-                    let download_response = client
-                        .get(&file_response.download_url)
-                        .header("User-Agent", String::from("baribari2"))
-                        .header("Authorization", format!("Bearer {}", &self.git_key))
-                        .send()
-                        .await?
-                        .text()
-                        .await?;
+                    let download_response =
+                        http::send(request(&client, &self.git_key, &file_response.download_url))
+                            .await?;
 
                     folder.files.insert(
                         file.path.clone(),
@@ -213,14 +420,43 @@ impl Git {
                             &file_response.download_url.clone(),
                         ),
                     );
+                    self.progress.file_fetched();
                 }
             }
         }
 
+        self.progress.finish_fetch();
+        info!(
+            files = self
+                .repository_content
+                .folders
+                .values()
+                .map(|folder| folder.files.len())
+                .sum::<usize>(),
+            "fetched {}/{}", self.repository_username, self.repository_name
+        );
+
+        if self.config.history {
+            let mut history = History::fetch(
+                &client,
+                &self.git_key,
+                &format!("{}/{}", self.repository_username, self.repository_name),
+                &self.branch,
+                self.config.concurrency,
+            )
+            .await?;
+            history.owners = CodeOwners::from_contents(&self.repository_content);
+            info!(commits = history.commits.len(), "fetched history");
+
+            self.repository_content.history = history;
+        }
+
+        self.repository_content.redact(&self.config.redactor);
+
         Ok(())
     }
 
-    pub async fn summarize_repository(&self) -> Result<(), Error> {
+    pub async fn summarize_repository(&self) -> Result<SummaryTree, Error> {
         if self.repository_username.is_empty() {
             return Err(eyre!("No username provided"));
         }
@@ -233,37 +469,126 @@ impl Git {
             return Err(eyre!("No key provided"));
         }
 
-        let mut summaries = vec![String::from("")];
-        for (name, _) in &self.repository_content.folders {
-            let (folder_wide, _) = self.summarize_folder(name).await?;
+        self.progress.add_files(
+            self.repository_content
+                .folders
+                .values()
+                .map(|folder| folder.files.len())
+                .sum(),
+        );
 
-            summaries.push(folder_wide.summary.clone());
-        }
+        let mut tree = SummaryTree::default();
+        for name in self.repository_content.folders.keys() {
+            let (folder_wide, files) = self.summarize_folder(name).await?;
 
-        let s = summaries
-            .iter()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>()
-            .join(" ");
-
-        let repo_prompt = RepositorySummaryPrompt::new(&s);
+            tree.insert_folder(name, folder_wide, files);
+        }
 
-        let repo_summary = match repo_prompt.send(&self.open_ai_key).await {
-            Ok(s) => s,
+        tree.dependencies = match self.describe_dependencies(&tree).await {
+            Ok(dependencies) => dependencies,
             Err(e) => {
-                println!("Error: {}", e);
-                return Err(eyre!("Error: {}", e));
+                self.fail(FailureKind::Dependencies, "", e)?;
+
+                Dependencies::new(&self.repository_content).major()
             }
         };
 
-        println!(
-            "{}/{} Summary: {}",
-            self.repository_username, self.repository_name, repo_summary.summary
+        let repo_prompt = RepositorySummaryPrompt::new(
+            self.config.templates.repository(
+                &self.meta(),
+                &tree,
+                &RepositoryStats::new(&self.repository_content),
+                self.history()
+                    .map(|h| h.summarize(&self.repository_content))
+                    .as_ref(),
+            )?,
         );
 
-        Ok(())
+        let repo_summary = repo_prompt
+            .send(&self.open_ai_key, &self.config.repository)
+            .await
+            .map_err(|e| eyre!("failed to summarize the repository: {}", e));
+
+        self.progress.finish();
+
+        match repo_summary {
+            Ok(repo_summary) => {
+                if self.verbose() {
+                    self.progress.println(&format!(
+                        "{}/{} Summary: {}",
+                        self.repository_username, self.repository_name, repo_summary.summary
+                    ));
+                }
+
+                tree.summary = repo_summary.summary;
+            }
+            Err(e) => self.fail(FailureKind::Repository, "", e)?,
+        }
+
+        tree.failures = std::mem::take(&mut *self.failures.lock().unwrap());
+
+        Ok(tree)
+    }
+
+    /// Finds the major dependencies of the repository in its manifests and
+    /// asks what each is used for, given the summaries of the files importing it.
+    async fn describe_dependencies(&self, tree: &SummaryTree) -> Result<Vec<Dependency>, Error> {
+        let mut major = Dependencies::new(&self.repository_content).major();
+
+        if major.is_empty() {
+            return Ok(major);
+        }
+
+        let purposes = tree
+            .folders
+            .values()
+            .flat_map(|folder| &folder.files)
+            .map(|file| {
+                let purpose = if file.purpose.is_empty() {
+                    &file.summary
+                } else {
+                    &file.purpose
+                };
+
+                (file.name.as_str(), one_line(purpose))
+            })
+            .collect::<HashMap<&str, String>>();
+
+        let described = major
+            .iter()
+            .map(|dependency| {
+                let files = dependency
+                    .files
+                    .iter()
+                    .take(MAX_FILES_PER_DEPENDENCY)
+                    .map(|file| {
+                        format!(
+                            "{} ({})",
+                            file,
+                            purposes.get(file.as_str()).cloned().unwrap_or_default()
+                        )
+                    })
+                    .collect::<Vec<String>>();
+
+                format!("- {}: {}", dependency.name, files.join("; "))
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        let response = DependencyPrompt::new(&described)
+            .send(&self.open_ai_key, &self.config.repository)
+            .await?;
+
+        for usage in response.dependencies {
+            if let Some(dependency) = major.iter_mut().find(|d| d.name == usage.name) {
+                dependency.usage = usage.usage;
+            }
+        }
+
+        Ok(major)
     }
 
+    #[instrument(level = "debug", skip(self))]
     pub async fn summarize_folder(
         &self,
         folder: &str,
@@ -273,43 +598,89 @@ impl Git {
         }
 
         let mut summaries: Vec<FileSummaryResponse> = vec![];
+        let mut complete = true;
         let bpe = r50k_base().unwrap();
         if let Some(folder) = self.repository_content.folders.get(folder) {
+            let mut files = vec![];
             for (name, file) in &folder.files {
-                let file_token_size = bpe.encode_with_special_tokens(&file.content);
-
-                if file_token_size.len() > 4096 {
-                    println!("File {} is too large to summarize", name);
-
-                    continue;
+                match summary_input(&bpe, file) {
+                    Some((content, outline)) => files.push(async move {
+                        let summary = self.summarize_input(file, content, outline).await;
+
+                        (file.name.clone(), summary)
+                    }),
+                    None => {
+                        warn!(file = %name, "file is too large to summarize");
+                        self.progress.file_done();
+                    }
                 }
+            }
 
-                let file_summary = self.summarize_file(&folder.name, &file.name).await?;
-
-                summaries.push(file_summary);
+            let results = stream::iter(files)
+                .buffered(self.config.concurrency)
+                .collect::<Vec<(String, Result<FileSummaryResponse, Error>)>>()
+                .await;
+
+            for (file, summary) in results {
+                match summary {
+                    Ok(summary) => summaries.push(summary),
+                    Err(e) => {
+                        self.fail(FailureKind::File, &file, e)?;
+                        self.progress.file_done();
+                        complete = false;
+                    }
+                }
             }
         };
 
-        let sum = summaries
-            .iter()
-            .map(|s| s.summary.clone())
-            .collect::<Vec<String>>()
-            .join(" ");
+        let rp = FolderWideSummaryPrompt::new(self.config.templates.folder(
+            &self.meta(),
+            folder,
+            &summaries,
+            self.history().map(|h| h.activity(folder)).as_ref(),
+        )?);
+
+        // A folder summary is only checkpointed once every file in it is summarized.
+        let hash = prompt_hash(&rp.prompt, &self.config.folder);
+        if let Some(summary) = self
+            .journal
+            .as_ref()
+            .and_then(|journal| journal.folder(folder, &hash))
+        {
+            return Ok((summary, summaries));
+        }
 
-        let rp = FolderWideSummaryPrompt::new(&sum);
+        let folder_summary_res = match rp.send(&self.open_ai_key, &self.config.folder).await {
+            Ok(summary) => summary,
+            Err(e) => {
+                self.fail(FailureKind::Folder, folder, e)?;
+
+                return Ok((
+                    FolderWideSummaryResponse {
+                        summary: String::new(),
+                    },
+                    summaries,
+                ));
+            }
+        };
 
-        let folder_summary_res = rp.send(&self.open_ai_key).await?;
+        if let (Some(journal), true) = (&self.journal, complete) {
+            journal.record_folder(folder, &hash, &folder_summary_res)?;
+        }
 
-        println!(
-            "{} {}\n {}",
-            folder.green().bold(),
-            "summary".green().bold(),
-            folder_summary_res.summary
-        );
+        if self.verbose() {
+            self.progress.println(&format!(
+                "{} {}\n {}",
+                folder.green().bold(),
+                "summary".green().bold(),
+                folder_summary_res.summary
+            ));
+        }
 
         Ok((folder_summary_res, summaries))
     }
 
+    #[instrument(level = "debug", skip(self))]
     pub async fn summarize_file(
         &self,
         folder: &str,
@@ -319,33 +690,64 @@ impl Git {
             return Err(eyre!("No folders in specified repository"));
         }
 
+        let file = self
+            .repository_content
+            .folders
+            .get(folder)
+            .ok_or_else(|| eyre!("Folder not found"))?
+            .files
+            .get(file)
+            .ok_or_else(|| eyre!("File not found"))?;
+
         let bpe = r50k_base().unwrap();
-        let file_summary = if let Some(folder) = self.repository_content.folders.get(folder) {
-            if let Some(file) = folder.files.get(file) {
-                let file_token_size = bpe.encode_with_special_tokens(&file.content);
+        let (content, outline) =
+            summary_input(&bpe, file).ok_or_else(|| eyre!("File is too large to summarize"))?;
 
-                if file_token_size.len() > 4096 {
-                    return Err(eyre!("File is too large to summarize"));
-                }
+        self.summarize_input(file, content, outline).await
+    }
 
-                let fp = FileSummaryPrompt::new(&file.content);
+    /// Summarizes `file` from what `summary_input` decided to send of it.
+    async fn summarize_input(
+        &self,
+        file: &File,
+        content: &str,
+        outline: Option<String>,
+    ) -> Result<FileSummaryResponse, Error> {
+        let fp = FileSummaryPrompt::new(self.config.templates.file(
+            &self.meta(),
+            &file.name,
+            language(&file.name),
+            content,
+            outline.as_deref(),
+        )?);
+
+        let hash = prompt_hash(&fp.prompt, &self.config.file);
+        if let Some(summary) = self
+            .journal
+            .as_ref()
+            .and_then(|journal| journal.file(&file.name, &hash))
+        {
+            self.progress.file_done();
+
+            return Ok(summary);
+        }
 
-                let file_summary_res = fp.send(&self.open_ai_key).await?;
+        let mut file_summary = fp.send(&self.open_ai_key, &self.config.file).await?;
+        file_summary.name = file.name.clone();
+        self.progress.file_done();
 
-                println!(
-                    "{} {}\n {}\n",
-                    "Summary for".green().bold(),
-                    file.name.green().bold(),
-                    file_summary_res.summary
-                );
+        if let Some(journal) = &self.journal {
+            journal.record_file(&hash, &file_summary)?;
+        }
 
-                file_summary_res
-            } else {
-                return Err(eyre!("File not found"));
-            }
-        } else {
-            return Err(eyre!("Folder not found"));
-        };
+        if self.verbose() {
+            self.progress.println(&format!(
+                "{} {}\n {}\n",
+                "Summary for".green().bold(),
+                file.name.green().bold(),
+                file_summary.summary
+            ));
+        }
 
         Ok(file_summary)
     }
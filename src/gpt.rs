@@ -1,6 +1,20 @@
+use crate::http;
+use crate::mock;
 use eyre::{eyre, Error, Result};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::{debug_span, Instrument};
+
+/// The tokens used by every chat completion so far.
+static TOKENS_USED: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the tokens used by every chat completion so far.
+pub fn tokens_used() -> u64 {
+    TOKENS_USED.load(Ordering::Relaxed)
+}
 
 #[derive(Debug, Default, Deserialize)]
 pub struct ChatUsage {
@@ -11,13 +25,34 @@ pub struct ChatUsage {
     pub total_tokens: i64,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct ChatMessage {
     pub role: String,
 
     pub content: String,
 }
 
+impl ChatMessage {
+    pub fn new(role: &str, content: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    pub fn system(content: &str) -> Self {
+        Self::new("system", content)
+    }
+
+    pub fn user(content: &str) -> Self {
+        Self::new("user", content)
+    }
+
+    pub fn assistant(content: &str) -> Self {
+        Self::new("assistant", content)
+    }
+}
+
 #[derive(Debug, Default, Deserialize)]
 pub struct ChatChoice {
     pub index: i64,
@@ -40,6 +75,46 @@ pub struct ChatResponse {
     pub usage: ChatUsage,
 }
 
+/// Asks the model for a valid JSON object rather than free text.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseFormat {
+    #[serde(rename = "type")]
+    pub format_type: String,
+}
+
+impl ResponseFormat {
+    pub fn json_object() -> Self {
+        Self {
+            format_type: String::from("json_object"),
+        }
+    }
+}
+
+pub const OPENAI_PROVIDER: &str = "openai";
+
+/// Answers every request locally with a deterministic response, see `mock::respond`.
+pub const MOCK_PROVIDER: &str = "mock";
+
+/// Model name prefixes that support `response_format`, from OpenAI's JSON mode docs.
+const JSON_MODE_MODELS: &[&str] = &[
+    "gpt-3.5-turbo-1106",
+    "gpt-3.5-turbo-0125",
+    "gpt-4-turbo",
+    "gpt-4-1106",
+    "gpt-4-0125",
+    "gpt-4o",
+    "gpt-4.1",
+    "gpt-5",
+    "o1",
+    "o3",
+    "o4",
+];
+
+/// Whether `model` can be asked for JSON with `response_format`.
+pub fn supports_json_mode(model: &str) -> bool {
+    model == "gpt-3.5-turbo" || JSON_MODE_MODELS.iter().any(|m| model.starts_with(m))
+}
+
 #[derive(Debug, Default, Serialize)]
 pub struct ChatRequest {
     pub messages: Vec<ChatMessage>,
@@ -55,11 +130,26 @@ pub struct ChatRequest {
     pub frequency_penalty: f64,
 
     pub presence_penalty: f64,
+
+    /// Sequences where the model stops generating, if any.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+
+    /// Asks the model to sample deterministically, as far as it can.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+
+    /// Where the request is sent, one of `config::PROVIDERS`.
+    #[serde(skip)]
+    pub provider: String,
 }
 
 impl ChatRequest {
     pub fn new(
-        message: String,
+        messages: Vec<ChatMessage>,
         model: String,
         max_tokens: i64,
         temperature: f64,
@@ -68,47 +158,99 @@ impl ChatRequest {
         presence_penalty: f64,
     ) -> Self {
         Self {
-            messages: vec![ChatMessage {
-                role: String::from("user"),
-                content: message,
-            }],
+            messages,
             model,
             max_tokens,
             temperature,
             top_p,
             frequency_penalty,
             presence_penalty,
+            stop: vec![],
+            response_format: None,
+            seed: None,
+            provider: String::from(OPENAI_PROVIDER),
         }
     }
 
+    /// Sends the request in a span recording the model, the tokens used and
+    /// how long it took.
     pub async fn send(&self, open_ai_key: &str, client: Client) -> Result<ChatResponse, Error> {
+        let span = debug_span!(
+            "chat",
+            provider = %self.provider,
+            model = %self.model,
+            tokens = Empty,
+            elapsed_ms = Empty
+        );
+        let started = Instant::now();
+
+        let res = self
+            .complete(open_ai_key, client)
+            .instrument(span.clone())
+            .await?;
+
+        let tokens = res.usage.total_tokens.max(0) as u64;
+        TOKENS_USED.fetch_add(tokens, Ordering::Relaxed);
+        span.record("tokens", tokens);
+        span.record("elapsed_ms", started.elapsed().as_millis() as u64);
+
+        Ok(res)
+    }
+
+    async fn complete(&self, open_ai_key: &str, client: Client) -> Result<ChatResponse, Error> {
+        if self.provider == MOCK_PROVIDER {
+            return Ok(mock::respond(self));
+        }
+
         let url = "https://api.openai.com/v1/chat/completions";
 
-        let res = client
-            .post(url)
-            .json(self)
-            .bearer_auth(open_ai_key)
-            .send()
-            .await;
+        let res = http::send(client.post(url).json(self).bearer_auth(open_ai_key)).await?;
 
-        let res = match res?.json::<ChatResponse>().await {
-            Ok(res) => res,
-            Err(e) => {
-                return Err(eyre!(
-                    "failed to send gpt request: {}: {}",
-                    &self.messages[0].content,
-                    e
-                ))
-            }
-        };
+        serde_json::from_str::<ChatResponse>(&res)
+            .map_err(|e| eyre!("failed to parse gpt response: {}", e))
+    }
+}
 
-        Ok(res)
+/// The model and sampling parameters used for a chat completion.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatSettings {
+    /// Where requests are sent, one of `config::PROVIDERS`.
+    pub provider: String,
+
+    pub model: String,
+
+    pub max_tokens: i64,
+
+    pub temperature: f64,
+
+    pub top_p: f64,
+
+    pub frequency_penalty: f64,
+
+    pub presence_penalty: f64,
+
+    #[serde(default)]
+    pub seed: Option<i64>,
+}
+
+impl Default for ChatSettings {
+    fn default() -> Self {
+        Self {
+            provider: String::from(OPENAI_PROVIDER),
+            model: String::from("gpt-3.5-turbo"),
+            max_tokens: 2000,
+            temperature: 0.7,
+            top_p: 1.0,
+            frequency_penalty: 0.0,
+            presence_penalty: 0.0,
+            seed: None,
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct ChatRequestBuilder {
-    message: String,
+    messages: Vec<ChatMessage>,
 
     model: String,
 
@@ -123,6 +265,12 @@ pub struct ChatRequestBuilder {
     presence_penalty: f64,
 
     stop: Vec<String>,
+
+    json_mode: bool,
+
+    seed: Option<i64>,
+
+    provider: String,
 }
 
 impl ChatRequestBuilder {
@@ -136,7 +284,7 @@ impl ChatRequestBuilder {
         presence_penalty: f64,
     ) -> Self {
         Self {
-            message,
+            messages: vec![ChatMessage::user(&message)],
             model,
             max_tokens,
             temperature,
@@ -144,11 +292,27 @@ impl ChatRequestBuilder {
             frequency_penalty,
             presence_penalty,
             stop: vec![],
+            json_mode: false,
+            seed: None,
+            provider: String::from(OPENAI_PROVIDER),
         }
     }
 
+    /// Sets the request to a single `user` message.
     pub fn messages(mut self, message: String) -> Self {
-        self.message = message.to_string();
+        self.messages = vec![ChatMessage::user(&message)];
+        self
+    }
+
+    /// Sets the full conversation, allowing `system` and `assistant` turns
+    /// to be sent alongside `user` messages.
+    pub fn conversation(mut self, messages: Vec<ChatMessage>) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    pub fn provider(mut self, provider: String) -> Self {
+        self.provider = provider;
         self
     }
 
@@ -182,35 +346,133 @@ impl ChatRequestBuilder {
         self
     }
 
+    /// Applies the provider, model and every sampling parameter from `settings`.
+    pub fn settings(self, settings: &ChatSettings) -> Self {
+        self.provider(settings.provider.clone())
+            .model(settings.model.clone())
+            .max_tokens(settings.max_tokens)
+            .temperature(settings.temperature)
+            .top_p(settings.top_p)
+            .frequency_penalty(settings.frequency_penalty)
+            .presence_penalty(settings.presence_penalty)
+            .seed(settings.seed)
+    }
+
+    pub fn seed(mut self, seed: Option<i64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
     pub fn stop(mut self, stop: Vec<String>) -> Self {
         self.stop = stop;
         self
     }
 
+    /// Asks for a JSON object, if the model supports JSON mode. Otherwise
+    /// the prompt alone has to ask for JSON.
+    pub fn json_mode(mut self, json_mode: bool) -> Self {
+        self.json_mode = json_mode;
+        self
+    }
+
     pub fn build(self) -> ChatRequest {
-        ChatRequest::new(
-            self.message,
+        let json_mode = self.json_mode && supports_json_mode(&self.model);
+
+        let mut request = ChatRequest::new(
+            self.messages,
             self.model,
             self.max_tokens,
             self.temperature,
             self.top_p,
             self.frequency_penalty,
             self.presence_penalty,
-        )
+        );
+
+        request.provider = self.provider;
+        request.stop = self.stop;
+        request.seed = self.seed;
+
+        if json_mode {
+            request.response_format = Some(ResponseFormat::json_object());
+        }
+
+        request
     }
 }
 
 impl Default for ChatRequestBuilder {
     fn default() -> Self {
         Self {
-            message: String::new(),
+            messages: vec![],
             model: String::from("gpt-3.5-turbo"),
             max_tokens: 0,
             temperature: 0.0,
             top_p: 0.0,
             frequency_penalty: 0.0,
             presence_penalty: 0.0,
-            stop: vec![String::from("")],
+            stop: vec![],
+            json_mode: false,
+            seed: None,
+            provider: String::from(OPENAI_PROVIDER),
         }
     }
 }
+
+#[derive(Debug, Default, Serialize)]
+pub struct EmbeddingRequest {
+    pub model: String,
+
+    pub input: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EmbeddingData {
+    pub index: usize,
+
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EmbeddingResponse {
+    pub data: Vec<EmbeddingData>,
+}
+
+impl EmbeddingRequest {
+    pub fn new(model: &str, input: Vec<String>) -> Self {
+        Self {
+            model: model.to_string(),
+            input,
+        }
+    }
+
+    /// Sends the request to an OpenAI compatible embeddings endpoint, returning
+    /// one embedding per input in the order they were given.
+    pub async fn send(
+        &self,
+        url: &str,
+        key: Option<&str>,
+        client: Client,
+    ) -> Result<Vec<Vec<f32>>, Error> {
+        let mut req = client.post(url).json(self);
+        if let Some(key) = key {
+            req = req.bearer_auth(key);
+        }
+
+        let mut res = match serde_json::from_str::<EmbeddingResponse>(&http::send(req).await?) {
+            Ok(res) => res,
+            Err(e) => return Err(eyre!("failed to send embeddings request: {}", e)),
+        };
+
+        if res.data.len() != self.input.len() {
+            return Err(eyre!(
+                "expected {} embeddings, got {}",
+                self.input.len(),
+                res.data.len()
+            ));
+        }
+
+        res.data.sort_by_key(|d| d.index);
+
+        Ok(res.data.into_iter().map(|d| d.embedding).collect())
+    }
+}
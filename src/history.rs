@@ -0,0 +1,563 @@
+use crate::git::{request, RepositoryContent};
+use crate::http;
+use chrono::{DateTime, Duration, Utc};
+use eyre::{eyre, Error, Result};
+use futures::stream::{self, StreamExt};
+use globset::GlobBuilder;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// The most recent commits read for the history of a repository.
+pub const MAX_HISTORY_COMMITS: usize = 100;
+
+/// Where GitHub looks for a CODEOWNERS file, in order.
+pub const CODEOWNERS_PATHS: [&str; 3] = ["CODEOWNERS", ".github/CODEOWNERS", "docs/CODEOWNERS"];
+
+/// Files changed this many days before the latest commit count towards hot spots.
+pub const HOT_SPOT_DAYS: i64 = 90;
+
+/// Folders unchanged for this many days before the latest commit are stale.
+pub const STALE_DAYS: i64 = 365;
+
+/// The contributors listed for a file or folder.
+const MAX_CONTRIBUTORS: usize = 3;
+
+/// The recent commit messages listed for a file or folder.
+const MAX_MESSAGES: usize = 3;
+
+/// The hot spots, stale areas and folders listed in a history summary.
+const MAX_LISTED: usize = 10;
+
+/// A commit, with the files it changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Commit {
+    pub sha: String,
+
+    /// The GitHub login of the author as `@login` when known, otherwise their name.
+    pub author: String,
+
+    pub date: DateTime<Utc>,
+
+    /// The first line of the commit message.
+    pub message: String,
+
+    pub files: Vec<String>,
+}
+
+/// A line of a CODEOWNERS file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OwnerRule {
+    pub pattern: String,
+
+    pub owners: Vec<String>,
+}
+
+/// The rules of a CODEOWNERS file, where the last rule matching a path wins.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CodeOwners {
+    pub rules: Vec<OwnerRule>,
+}
+
+/// Whether a CODEOWNERS pattern matches a path. Patterns follow gitignore:
+/// one without a `/` matches at any depth, a leading `/` anchors it to the
+/// root, and a pattern matching a folder matches everything in it, unless it
+/// ends with a `*` which only matches the files directly in a folder.
+fn owns(pattern: &str, path: &str) -> bool {
+    let anchored = pattern.starts_with('/') || pattern.trim_end_matches('/').contains('/');
+    let pattern = pattern.trim_start_matches('/').trim_end_matches('/');
+
+    let glob = match (pattern, anchored) {
+        ("*", _) => String::from("**"),
+        (pattern, true) => pattern.to_string(),
+        (pattern, false) => format!("**/{}", pattern),
+    };
+
+    let mut globs = vec![glob.clone()];
+    if !glob.ends_with('*') {
+        globs.push(format!("{}/**", glob));
+    }
+
+    globs.iter().any(|glob| {
+        GlobBuilder::new(glob)
+            .literal_separator(true)
+            .build()
+            .map(|glob| glob.compile_matcher().is_match(path))
+            .unwrap_or(false)
+    })
+}
+
+impl CodeOwners {
+    pub fn parse(text: &str) -> CodeOwners {
+        let rules = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+
+                Some(OwnerRule {
+                    pattern: parts.next()?.to_string(),
+                    owners: parts.map(String::from).collect(),
+                })
+            })
+            .collect();
+
+        CodeOwners { rules }
+    }
+
+    /// Finds the CODEOWNERS file among fetched contents.
+    pub fn from_contents(contents: &RepositoryContent) -> CodeOwners {
+        CODEOWNERS_PATHS
+            .iter()
+            .find_map(|path| {
+                contents
+                    .folders
+                    .values()
+                    .find_map(|folder| folder.files.get(*path))
+            })
+            .map(|file| CodeOwners::parse(&file.content))
+            .unwrap_or_default()
+    }
+
+    /// Reads the CODEOWNERS file of a local checkout.
+    pub fn from_dir(root: &Path) -> CodeOwners {
+        CODEOWNERS_PATHS
+            .iter()
+            .find_map(|path| fs::read_to_string(root.join(path)).ok())
+            .map(|text| CodeOwners::parse(&text))
+            .unwrap_or_default()
+    }
+
+    /// The owners of a file or folder.
+    pub fn owners(&self, path: &str) -> Vec<String> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| owns(&rule.pattern, path))
+            .map(|rule| rule.owners.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Someone who changed a file or folder, and how often.
+#[derive(Debug, Clone, Serialize)]
+pub struct Contributor {
+    pub name: String,
+
+    pub commits: usize,
+}
+
+/// How a file or folder has changed over the commits read.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Activity {
+    pub path: String,
+
+    /// The commits changing it, its churn.
+    pub commits: usize,
+
+    /// When it last changed, unknown if it didn't change in the commits read.
+    pub last_modified: Option<DateTime<Utc>>,
+
+    pub contributors: Vec<Contributor>,
+
+    pub recent_messages: Vec<String>,
+
+    /// Its owners, from CODEOWNERS.
+    pub owners: Vec<String>,
+}
+
+impl Activity {
+    /// Describes the activity as text, for the folder prompt.
+    pub fn describe(&self) -> String {
+        let mut out = match self.last_modified {
+            Some(date) => format!(
+                "History: changed in {} recent commits, last on {}",
+                self.commits,
+                date.format("%Y-%m-%d")
+            ),
+            None => String::from("History: unchanged in recent commits"),
+        };
+
+        if !self.contributors.is_empty() {
+            out.push_str(&format!(", mostly by {}", contributors(&self.contributors)));
+        }
+
+        if !self.owners.is_empty() {
+            out.push_str(&format!(". Owned by {}", self.owners.join(", ")));
+        }
+
+        if !self.recent_messages.is_empty() {
+            out.push_str(&format!(
+                ". Recent changes: {}",
+                self.recent_messages.join("; ")
+            ));
+        }
+
+        out
+    }
+}
+
+/// Lists contributors with their commits, e.g. `@octocat (3)`.
+pub fn contributors(contributors: &[Contributor]) -> String {
+    contributors
+        .iter()
+        .map(|c| format!("{} ({})", c.name, c.commits))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// What the history says about a repository as a whole.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HistorySummary {
+    /// The commits read.
+    pub commits: usize,
+
+    /// The date of the oldest commit read.
+    pub since: Option<DateTime<Utc>>,
+
+    pub contributors: Vec<Contributor>,
+
+    /// The files changed most in the last `HOT_SPOT_DAYS` days of the history.
+    pub hot_spots: Vec<Activity>,
+
+    /// Folders that last changed over `STALE_DAYS` days before the latest
+    /// commit. Folders the commits read don't change are left out, as when
+    /// they last changed isn't known.
+    pub stale: Vec<Activity>,
+
+    /// The owners and main contributors of each top level folder.
+    pub who_to_ask: Vec<Activity>,
+}
+
+impl HistorySummary {
+    pub fn is_empty(&self) -> bool {
+        self.commits == 0 && self.who_to_ask.is_empty()
+    }
+
+    /// Describes the summary as text, for the repository prompt.
+    pub fn describe(&self) -> String {
+        let mut out = format!("History: {} recent commits", self.commits);
+
+        if let Some(since) = self.since {
+            out.push_str(&format!(" since {}", since.format("%Y-%m-%d")));
+        }
+
+        if !self.contributors.is_empty() {
+            out.push_str(&format!(", mostly by {}", contributors(&self.contributors)));
+        }
+
+        if !self.hot_spots.is_empty() {
+            let hot_spots = self
+                .hot_spots
+                .iter()
+                .map(|a| format!("{} ({} commits)", a.path, a.commits))
+                .collect::<Vec<String>>();
+
+            out.push_str(&format!("\nHot spots: {}", hot_spots.join(", ")));
+        }
+
+        if !self.stale.is_empty() {
+            let stale = self
+                .stale
+                .iter()
+                .map(|a| a.path.clone())
+                .collect::<Vec<String>>();
+
+            out.push_str(&format!("\nStale areas: {}", stale.join(", ")));
+        }
+
+        out
+    }
+}
+
+/// The commit history of a repository along with its CODEOWNERS, newest commit first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct History {
+    pub commits: Vec<Commit>,
+
+    pub owners: CodeOwners,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitCommitAuthor {
+    name: String,
+
+    date: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitCommitDetails {
+    author: GitCommitAuthor,
+
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitUser {
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitCommitFile {
+    filename: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitCommit {
+    sha: String,
+
+    commit: GitCommitDetails,
+
+    #[serde(default)]
+    author: Option<GitUser>,
+
+    #[serde(default)]
+    files: Vec<GitCommitFile>,
+}
+
+/// Whether `path` is `folder` or inside it. Every path is inside the root, `""`.
+fn inside(path: &str, folder: &str) -> bool {
+    folder.is_empty()
+        || path == folder
+        || path
+            .strip_prefix(folder)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+impl History {
+    /// Fetches the most recent commits of a branch from GitHub, and the files each changed.
+    pub async fn fetch(
+        client: &Client,
+        git_key: &str,
+        full_name: &str,
+        branch: &str,
+        concurrency: usize,
+    ) -> Result<History, Error> {
+        let url = format!(
+            "https://api.github.com/repos/{}/commits?sha={}&per_page={}",
+            full_name, branch, MAX_HISTORY_COMMITS
+        );
+        let response = http::send(request(client, git_key, &url)).await?;
+        let listed = serde_json::from_str::<Vec<GitCommit>>(&response)
+            .map_err(|e| eyre!("failed to read the commits of {}: {}", full_name, e))?;
+
+        // The files changed are only listed for each commit on its own.
+        let commits = stream::iter(listed)
+            .map(|commit| async move {
+                let url = format!(
+                    "https://api.github.com/repos/{}/commits/{}",
+                    full_name, commit.sha
+                );
+                let response = http::send(request(client, git_key, &url)).await?;
+
+                Ok::<GitCommit, Error>(serde_json::from_str(&response)?)
+            })
+            .buffered(concurrency.max(1))
+            .collect::<Vec<Result<GitCommit, Error>>>()
+            .await
+            .into_iter()
+            .map(|commit| {
+                let commit = commit?;
+
+                Ok(Commit {
+                    sha: commit.sha,
+                    author: commit
+                        .author
+                        .map(|user| format!("@{}", user.login))
+                        .unwrap_or(commit.commit.author.name),
+                    date: commit.commit.author.date,
+                    message: commit
+                        .commit
+                        .message
+                        .lines()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    files: commit.files.into_iter().map(|f| f.filename).collect(),
+                })
+            })
+            .collect::<Result<Vec<Commit>, Error>>()?;
+
+        Ok(History {
+            commits,
+            owners: CodeOwners::default(),
+        })
+    }
+
+    /// Reads the most recent commits of a local checkout with `git log`.
+    pub fn from_git_log(root: &Path) -> Result<History, Error> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(root)
+            .args([
+                "log",
+                &format!("-n{}", MAX_HISTORY_COMMITS),
+                "--name-only",
+                "--format=%x1e%H%x1f%an%x1f%aI%x1f%s",
+            ])
+            .output()
+            .map_err(|e| eyre!("failed to run git: {}", e))?;
+
+        if !output.status.success() {
+            return Err(eyre!(
+                "failed to read the history of {}: {}",
+                root.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let mut commits = vec![];
+        for record in String::from_utf8_lossy(&output.stdout).split('\x1e') {
+            let mut lines = record.lines();
+            let fields = lines
+                .next()
+                .unwrap_or_default()
+                .split('\x1f')
+                .collect::<Vec<&str>>();
+
+            if let [sha, author, date, message] = fields[..] {
+                commits.push(Commit {
+                    sha: sha.to_string(),
+                    author: author.to_string(),
+                    date: DateTime::parse_from_rfc3339(date)?.with_timezone(&Utc),
+                    message: message.to_string(),
+                    files: lines
+                        .filter(|line| !line.is_empty())
+                        .map(String::from)
+                        .collect(),
+                });
+            }
+        }
+
+        Ok(History {
+            commits,
+            owners: CodeOwners::from_dir(root),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commits.is_empty() && self.owners.rules.is_empty()
+    }
+
+    /// The date of the latest commit, which ages are measured from so that
+    /// the same history always gives the same summary.
+    fn latest(&self) -> Option<DateTime<Utc>> {
+        self.commits.iter().map(|c| c.date).max()
+    }
+
+    /// How a file or folder, and everything in it, has changed.
+    pub fn activity(&self, path: &str) -> Activity {
+        self.activity_since(path, None)
+    }
+
+    fn activity_since(&self, path: &str, since: Option<DateTime<Utc>>) -> Activity {
+        let commits = self
+            .commits
+            .iter()
+            .filter(|c| since.is_none_or(|since| c.date >= since))
+            .filter(|c| c.files.iter().any(|file| inside(file, path)))
+            .collect::<Vec<&Commit>>();
+
+        let mut counts = BTreeMap::<&str, usize>::new();
+        for commit in &commits {
+            *counts.entry(&commit.author).or_default() += 1;
+        }
+
+        Activity {
+            path: path.to_string(),
+            commits: commits.len(),
+            last_modified: commits.iter().map(|c| c.date).max(),
+            contributors: top_contributors(counts),
+            recent_messages: commits
+                .iter()
+                .take(MAX_MESSAGES)
+                .map(|c| c.message.clone())
+                .collect(),
+            owners: self.owners.owners(path),
+        }
+    }
+
+    /// Summarizes the history of the files and folders of `contents`.
+    pub fn summarize(&self, contents: &RepositoryContent) -> HistorySummary {
+        let files = contents
+            .folders
+            .values()
+            .flat_map(|folder| folder.files.keys())
+            .map(String::as_str)
+            .collect::<BTreeSet<&str>>();
+
+        let mut counts = BTreeMap::<&str, usize>::new();
+        for commit in &self.commits {
+            *counts.entry(&commit.author).or_default() += 1;
+        }
+
+        let latest = self.latest();
+
+        let mut hot_spots = files
+            .iter()
+            .map(|file| {
+                self.activity_since(file, latest.map(|l| l - Duration::days(HOT_SPOT_DAYS)))
+            })
+            .filter(|a| a.commits > 0)
+            .collect::<Vec<Activity>>();
+        hot_spots.sort_by(|a, b| b.commits.cmp(&a.commits).then(a.path.cmp(&b.path)));
+        hot_spots.truncate(MAX_LISTED);
+
+        let mut stale = match latest {
+            Some(latest) => contents
+                .folders
+                .keys()
+                .filter(|name| !name.is_empty())
+                .map(|name| self.activity(name))
+                .filter(|a| {
+                    a.last_modified
+                        .is_some_and(|date| date < latest - Duration::days(STALE_DAYS))
+                })
+                .collect::<Vec<Activity>>(),
+            None => vec![],
+        };
+        stale.sort_by(|a, b| {
+            a.last_modified
+                .cmp(&b.last_modified)
+                .then(a.path.cmp(&b.path))
+        });
+        stale.truncate(MAX_LISTED);
+
+        let who_to_ask = contents
+            .folders
+            .keys()
+            .filter(|name| !name.is_empty() && !name.contains('/'))
+            .map(|name| self.activity(name))
+            .filter(|a| !a.owners.is_empty() || !a.contributors.is_empty())
+            .collect();
+
+        HistorySummary {
+            commits: self.commits.len(),
+            since: self.commits.iter().map(|c| c.date).min(),
+            contributors: top_contributors(counts),
+            hot_spots,
+            stale,
+            who_to_ask,
+        }
+    }
+}
+
+/// The contributors with the most commits, then by name.
+fn top_contributors(counts: BTreeMap<&str, usize>) -> Vec<Contributor> {
+    let mut contributors = counts
+        .into_iter()
+        .map(|(name, commits)| Contributor {
+            name: name.to_string(),
+            commits,
+        })
+        .collect::<Vec<Contributor>>();
+
+    contributors.sort_by(|a, b| b.commits.cmp(&a.commits).then(a.name.cmp(&b.name)));
+    contributors.truncate(MAX_CONTRIBUTORS);
+
+    contributors
+}
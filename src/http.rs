@@ -0,0 +1,185 @@
+use eyre::{eyre, Error, Result};
+use reqwest::{Client, Request, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::field::Empty;
+use tracing::{debug_span, Instrument};
+
+/// How requests to GitHub and model providers are sent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Transport {
+    /// Sent to the live services.
+    #[default]
+    Live,
+
+    /// Sent to the live services, with every response saved as a fixture in the directory.
+    Record(PathBuf),
+
+    /// Answered from the fixtures in the directory, without touching the network.
+    Replay(PathBuf),
+}
+
+static TRANSPORT: RwLock<Transport> = RwLock::new(Transport::Live);
+
+/// Sets how every following request is sent.
+pub fn set_transport(transport: Transport) {
+    *TRANSPORT.write().unwrap() = transport;
+}
+
+fn transport() -> Transport {
+    TRANSPORT.read().unwrap().clone()
+}
+
+/// Spaces requests out evenly so that no more than a number are sent each
+/// minute, however many repositories and files are being summarized at once.
+#[derive(Debug)]
+struct RateLimit {
+    interval: Duration,
+
+    next: tokio::sync::Mutex<Instant>,
+}
+
+static RATE_LIMIT: RwLock<Option<Arc<RateLimit>>> = RwLock::new(None);
+
+/// Limits every following request sent to the network to `per_minute`, or
+/// lifts the limit.
+pub fn set_rate_limit(per_minute: Option<u32>) {
+    *RATE_LIMIT.write().unwrap() = per_minute.filter(|n| *n > 0).map(|n| {
+        Arc::new(RateLimit {
+            interval: Duration::from_secs(60) / n,
+            next: tokio::sync::Mutex::new(Instant::now()),
+        })
+    });
+}
+
+/// Waits until the rate limit, if any, allows another request.
+async fn wait_for_rate_limit() {
+    let limit = RATE_LIMIT.read().unwrap().clone();
+
+    if let Some(limit) = limit {
+        let mut next = limit.next.lock().await;
+        let at = (*next).max(Instant::now());
+        *next = at + limit.interval;
+        drop(next);
+
+        tokio::time::sleep_until(at.into()).await;
+    }
+}
+
+/// A recorded request and its response. Headers aren't recorded, so keys
+/// never end up in fixtures.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Fixture {
+    pub method: String,
+
+    pub url: String,
+
+    /// The request body, if there was one.
+    #[serde(default)]
+    pub body: String,
+
+    pub status: u16,
+
+    pub response: String,
+}
+
+/// The file a request is recorded in, named after a hash of its method, url and body.
+fn fixture_path(dir: &Path, method: &str, url: &str, body: &str) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{} {}\n{}", method, url, body).as_bytes());
+
+    let hash = format!("{:x}", hasher.finalize());
+
+    dir.join(format!("{}.json", &hash[..16]))
+}
+
+/// Sends a request through the current transport and returns the body of
+/// the response, in a span recording how long it took.
+pub async fn send(request: RequestBuilder) -> Result<String, Error> {
+    Ok(send_with_status(request).await?.1)
+}
+
+/// Sends a request like `send`, returning the status of the response along
+/// with its body.
+pub async fn send_with_status(request: RequestBuilder) -> Result<(u16, String), Error> {
+    let (client, request) = request.build_split();
+    let request = request?;
+
+    let span = debug_span!(
+        "http",
+        method = %request.method(),
+        url = %request.url(),
+        elapsed_ms = Empty
+    );
+    let started = Instant::now();
+
+    let response = execute(client, request).instrument(span.clone()).await;
+    span.record("elapsed_ms", started.elapsed().as_millis() as u64);
+
+    response
+}
+
+async fn execute(client: Client, request: Request) -> Result<(u16, String), Error> {
+    let method = request.method().to_string();
+    let url = request.url().to_string();
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map(|body| String::from_utf8_lossy(body).to_string())
+        .unwrap_or_default();
+
+    match transport() {
+        Transport::Live => {
+            wait_for_rate_limit().await;
+
+            let response = client.execute(request).await?;
+            let status = response.status().as_u16();
+
+            Ok((status, response.text().await?))
+        }
+
+        Transport::Record(dir) => {
+            wait_for_rate_limit().await;
+
+            let response = client.execute(request).await?;
+            let status = response.status().as_u16();
+            let text = response.text().await?;
+
+            let path = fixture_path(&dir, &method, &url, &body);
+            fs::create_dir_all(&dir)?;
+            fs::write(
+                &path,
+                serde_json::to_string_pretty(&Fixture {
+                    method,
+                    url,
+                    body,
+                    status,
+                    response: text.clone(),
+                })?,
+            )?;
+
+            Ok((status, text))
+        }
+
+        Transport::Replay(dir) => {
+            let path = fixture_path(&dir, &method, &url, &body);
+
+            let fixture = fs::read_to_string(&path).map_err(|_| {
+                eyre!(
+                    "no recorded response for {} {} in {}, record it with --record",
+                    method,
+                    url,
+                    path.display()
+                )
+            })?;
+
+            let fixture = serde_json::from_str::<Fixture>(&fixture)?;
+
+            Ok((fixture.status, fixture.response))
+        }
+    }
+}
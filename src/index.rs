@@ -0,0 +1,225 @@
+use crate::cache::SummaryTree;
+use crate::git::RepositoryContent;
+use crate::gpt::EmbeddingRequest;
+use eyre::{eyre, Error};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const OPEN_AI_EMBEDDINGS_URL: &str = "https://api.openai.com/v1/embeddings";
+
+pub const DEFAULT_EMBEDDINGS_MODEL: &str = "text-embedding-ada-002";
+
+/// The number of lines in each chunk of file content.
+const CHUNK_LINES: usize = 50;
+
+/// Chunks are truncated to this many characters so they fit in a single embedding input.
+const MAX_CHUNK_CHARS: usize = 6000;
+
+/// The number of inputs sent in each embeddings request.
+const BATCH_SIZE: usize = 64;
+
+/// Where embeddings come from: the OpenAI embeddings endpoint, or a local
+/// server exposing the same API, such as Ollama or llama.cpp.
+#[derive(Debug, Clone)]
+pub enum EmbeddingProvider {
+    OpenAi { key: String, model: String },
+
+    Local { url: String, model: String },
+}
+
+impl EmbeddingProvider {
+    pub fn model(&self) -> &str {
+        match self {
+            EmbeddingProvider::OpenAi { model, .. } => model,
+            EmbeddingProvider::Local { model, .. } => model,
+        }
+    }
+
+    pub async fn embed(&self, input: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+        let req = EmbeddingRequest::new(self.model(), input);
+
+        match self {
+            EmbeddingProvider::OpenAi { key, .. } => {
+                req.send(OPEN_AI_EMBEDDINGS_URL, Some(key), Client::new())
+                    .await
+            }
+            EmbeddingProvider::Local { url, .. } => req.send(url, None, Client::new()).await,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ChunkKind {
+    Content,
+
+    Summary,
+}
+
+/// A span of a file, or its summary, along with its embedding.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Chunk {
+    pub path: String,
+
+    pub start_line: usize,
+
+    pub end_line: usize,
+
+    pub kind: ChunkKind,
+
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Index {
+    pub model: String,
+
+    /// A hash of the contents and summaries the index was built from.
+    #[serde(default)]
+    pub source: String,
+
+    pub chunks: Vec<Chunk>,
+}
+
+#[derive(Debug)]
+pub struct SearchResult<'a> {
+    pub score: f32,
+
+    pub chunk: &'a Chunk,
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot = a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+
+    dot / (norm_a * norm_b)
+}
+
+fn truncate(text: &str) -> String {
+    text.chars().take(MAX_CHUNK_CHARS).collect()
+}
+
+/// Hashes the contents and summaries of a repository, so an index built
+/// from older ones can be told apart.
+fn source_hash(contents: &RepositoryContent, summaries: &SummaryTree) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(contents)?);
+    hasher.update(serde_json::to_vec(summaries)?);
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+impl Index {
+    /// Whether the index was built with `model` from these contents and summaries.
+    pub fn is_current(
+        &self,
+        model: &str,
+        contents: &RepositoryContent,
+        summaries: &SummaryTree,
+    ) -> bool {
+        self.model == model
+            && source_hash(contents, summaries).is_ok_and(|source| source == self.source)
+    }
+
+    /// Embeds every file of the repository in chunks of `CHUNK_LINES` lines,
+    /// along with each file summary, which spans the whole file.
+    pub async fn build(
+        provider: &EmbeddingProvider,
+        contents: &RepositoryContent,
+        summaries: &SummaryTree,
+    ) -> Result<Index, Error> {
+        let mut chunks = vec![];
+        let mut inputs = vec![];
+
+        for folder in contents.folders.values() {
+            for (name, file) in &folder.files {
+                let lines = file.content.lines().collect::<Vec<&str>>();
+
+                for (i, chunk) in lines.chunks(CHUNK_LINES).enumerate() {
+                    let start_line = i * CHUNK_LINES + 1;
+
+                    chunks.push(Chunk {
+                        path: name.clone(),
+                        start_line,
+                        end_line: start_line + chunk.len() - 1,
+                        kind: ChunkKind::Content,
+                        embedding: vec![],
+                    });
+                    inputs.push(truncate(&format!("{}\n{}", name, chunk.join("\n"))));
+                }
+            }
+        }
+
+        for folder in summaries.folders.values() {
+            for file in &folder.files {
+                let end_line = contents
+                    .folders
+                    .values()
+                    .find_map(|f| f.files.get(&file.name))
+                    .map(|f| f.content.lines().count())
+                    .unwrap_or(1);
+
+                chunks.push(Chunk {
+                    path: file.name.clone(),
+                    start_line: 1,
+                    end_line,
+                    kind: ChunkKind::Summary,
+                    embedding: vec![],
+                });
+                inputs.push(truncate(&format!("{}\n{}", file.name, file.summary)));
+            }
+        }
+
+        if chunks.is_empty() {
+            return Err(eyre!("No files to index"));
+        }
+
+        let mut embeddings = vec![];
+        for batch in inputs.chunks(BATCH_SIZE) {
+            embeddings.extend(provider.embed(batch.to_vec()).await?);
+        }
+
+        for (chunk, embedding) in chunks.iter_mut().zip(embeddings) {
+            chunk.embedding = embedding;
+        }
+
+        Ok(Index {
+            model: provider.model().to_string(),
+            source: source_hash(contents, summaries)?,
+            chunks,
+        })
+    }
+
+    /// Ranks the chunks by their similarity to the query, returning at most `limit` results.
+    pub async fn search(
+        &self,
+        provider: &EmbeddingProvider,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchResult<'_>>, Error> {
+        let query = provider
+            .embed(vec![query.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| eyre!("No embedding returned for query"))?;
+
+        let mut results = self
+            .chunks
+            .iter()
+            .map(|chunk| SearchResult {
+                score: cosine_similarity(&query, &chunk.embedding),
+                chunk,
+            })
+            .collect::<Vec<SearchResult>>();
+
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        results.truncate(limit);
+
+        Ok(results)
+    }
+}
@@ -0,0 +1,567 @@
+use crate::cache::{normalize, one_line, SummaryTree};
+use crate::git::request;
+use crate::gpt::ChatSettings;
+use crate::http;
+use crate::prompts::{DuplicateIssues, IssueTheme, IssuesPrompt, PainPoint};
+use crate::redact::{Redaction, Redactor};
+use crate::report::{cell, redaction_rows};
+use chrono::{DateTime, Duration, Utc};
+use eyre::{eyre, Error, Result};
+use futures::stream::{self, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tiktoken_rs::r50k_base;
+
+/// The issues listed per page of the GitHub API.
+const PER_PAGE: usize = 100;
+
+/// The most pages of open, and of closed, issues fetched.
+const MAX_PAGES: usize = 5;
+
+/// The most engaged issues whose comments are fetched and sent to be clustered.
+const MAX_PROMPT_ISSUES: usize = 50;
+
+/// The most tokens of issues sent to be clustered.
+const MAX_PROMPT_TOKENS: usize = 8192;
+
+/// The characters of an issue body, and of each of its comments, sent to be clustered.
+const MAX_EXCERPT: usize = 300;
+
+/// The comments of an issue sent to be clustered.
+const MAX_COMMENTS: usize = 3;
+
+/// The comments and reactions an open issue needs to be listed as stale.
+const MIN_STALE_ENGAGEMENT: usize = 3;
+
+/// The stale issues listed in the digest.
+const MAX_STALE: usize = 10;
+
+/// Parses how far back to look, such as `30d`, `2w` or `12h`.
+pub fn parse_since(since: &str) -> Result<Duration, Error> {
+    let since = since.trim();
+    let unit = since
+        .char_indices()
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or_default();
+    let (amount, unit) = since.split_at(unit);
+    let amount = amount.parse::<i64>().map_err(|_| {
+        eyre!(
+            "{} must be a number of days, weeks or hours, such as 30d",
+            since
+        )
+    })?;
+
+    match unit {
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        "h" => Ok(Duration::hours(amount)),
+        _ => Err(eyre!("{} must end with d, w or h, such as 30d", since)),
+    }
+}
+
+/// A comment on an issue.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssueComment {
+    pub author: String,
+
+    pub body: String,
+}
+
+/// An issue, without pull requests, which GitHub lists as issues too.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Issue {
+    pub number: u64,
+
+    pub title: String,
+
+    pub body: String,
+
+    /// `open` or `closed`.
+    pub state: String,
+
+    pub url: String,
+
+    pub author: String,
+
+    pub labels: Vec<String>,
+
+    pub comments: usize,
+
+    pub reactions: usize,
+
+    pub created_at: DateTime<Utc>,
+
+    pub updated_at: DateTime<Utc>,
+
+    pub closed_at: Option<DateTime<Utc>>,
+
+    /// The comments fetched, only for the issues sent to be clustered.
+    pub discussion: Vec<IssueComment>,
+
+    /// What was redacted from the title, body and the comments fetched.
+    #[serde(skip)]
+    pub redactions: Vec<Redaction>,
+}
+
+impl Issue {
+    /// How many comments and reactions the issue has.
+    pub fn engagement(&self) -> usize {
+        self.comments + self.reactions
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state == "open"
+    }
+
+    /// Redacts secrets and personal data from the title, body and comments,
+    /// so that they never reach a prompt.
+    fn redact(&mut self, redactor: &Redactor) {
+        let source = format!("#{} title", self.number);
+        let (title, redactions) = redactor.redact(&source, &self.title);
+        self.title = title;
+        self.redactions.extend(redactions);
+
+        let (body, redactions) = redactor.redact(&format!("#{}", self.number), &self.body);
+        self.body = body;
+        self.redactions.extend(redactions);
+
+        for (i, comment) in self.discussion.iter_mut().enumerate() {
+            let source = format!("#{} comment {}", self.number, i + 1);
+            let (body, redactions) = redactor.redact(&source, &comment.body);
+
+            comment.body = body;
+            self.redactions.extend(redactions);
+        }
+    }
+
+    /// Describes the issue for the issues prompt.
+    fn describe(&self) -> String {
+        let mut out = format!(
+            "- #{} [{}, {} comments, {} reactions] {}: {}",
+            self.number,
+            self.state,
+            self.comments,
+            self.reactions,
+            self.title,
+            excerpt(&self.body)
+        );
+
+        for comment in self.discussion.iter().take(MAX_COMMENTS) {
+            out.push_str(&format!(
+                "\n  - {}: {}",
+                comment.author,
+                excerpt(&comment.body)
+            ));
+        }
+
+        out
+    }
+}
+
+/// The start of a text on one line, cut to `MAX_EXCERPT` characters.
+fn excerpt(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+    if text.chars().count() > MAX_EXCERPT {
+        format!("{}...", text.chars().take(MAX_EXCERPT).collect::<String>())
+    } else {
+        text
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GitUser {
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabel {
+    name: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GitReactions {
+    total_count: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitIssue {
+    number: u64,
+
+    title: String,
+
+    #[serde(default)]
+    body: Option<String>,
+
+    state: String,
+
+    html_url: String,
+
+    user: GitUser,
+
+    #[serde(default)]
+    labels: Vec<GitLabel>,
+
+    #[serde(default)]
+    comments: usize,
+
+    #[serde(default)]
+    reactions: GitReactions,
+
+    created_at: DateTime<Utc>,
+
+    updated_at: DateTime<Utc>,
+
+    #[serde(default)]
+    closed_at: Option<DateTime<Utc>>,
+
+    /// Only set for pull requests.
+    #[serde(default)]
+    pull_request: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitComment {
+    user: GitUser,
+
+    #[serde(default)]
+    body: String,
+}
+
+impl From<GitIssue> for Issue {
+    fn from(issue: GitIssue) -> Issue {
+        Issue {
+            number: issue.number,
+            title: issue.title,
+            body: issue.body.unwrap_or_default(),
+            state: issue.state,
+            url: issue.html_url,
+            author: issue.user.login,
+            labels: issue.labels.into_iter().map(|l| l.name).collect(),
+            comments: issue.comments,
+            reactions: issue.reactions.total_count,
+            created_at: issue.created_at,
+            updated_at: issue.updated_at,
+            closed_at: issue.closed_at,
+            discussion: vec![],
+            redactions: vec![],
+        }
+    }
+}
+
+/// Fetches one page of the issues of a repository.
+async fn page(
+    client: &Client,
+    git_key: &str,
+    full_name: &str,
+    query: &str,
+    page: usize,
+) -> Result<Vec<GitIssue>, Error> {
+    let url = format!(
+        "https://api.github.com/repos/{}/issues?{}&per_page={}&page={}",
+        full_name, query, PER_PAGE, page
+    );
+    let response = http::send(request(client, git_key, &url)).await?;
+
+    serde_json::from_str::<Vec<GitIssue>>(&response)
+        .map_err(|e| eyre!("failed to read the issues of {}: {}", full_name, e))
+}
+
+/// Fetches the open issues of a repository and those closed since `since`,
+/// along with the comments of the most engaged ones.
+pub async fn fetch_issues(
+    git_key: &str,
+    full_name: &str,
+    since: DateTime<Utc>,
+    concurrency: usize,
+    redactor: &Redactor,
+) -> Result<Vec<Issue>, Error> {
+    let client = Client::new();
+    let mut issues = vec![];
+
+    for n in 1..=MAX_PAGES {
+        let listed = page(&client, git_key, full_name, "state=open", n).await?;
+        let last = listed.len() < PER_PAGE;
+
+        issues.extend(listed.into_iter().filter(|i| i.pull_request.is_none()));
+        if last {
+            break;
+        }
+    }
+
+    // Closed issues are listed most recently updated first, and an issue
+    // closed since `since` was updated since then too.
+    for n in 1..=MAX_PAGES {
+        let listed = page(
+            &client,
+            git_key,
+            full_name,
+            "state=closed&sort=updated&direction=desc",
+            n,
+        )
+        .await?;
+        let last = listed.len() < PER_PAGE || listed.iter().any(|i| i.updated_at < since);
+
+        issues.extend(listed.into_iter().filter(|i| {
+            i.pull_request.is_none() && i.closed_at.is_some_and(|closed| closed >= since)
+        }));
+        if last {
+            break;
+        }
+    }
+
+    let mut issues = issues.into_iter().map(Issue::from).collect::<Vec<Issue>>();
+    issues.sort_by(|a, b| {
+        b.engagement()
+            .cmp(&a.engagement())
+            .then(a.number.cmp(&b.number))
+    });
+
+    let discussions = stream::iter(issues.iter().take(MAX_PROMPT_ISSUES))
+        .filter(|issue| futures::future::ready(issue.comments > 0))
+        .map(|issue| {
+            let client = client.clone();
+
+            async move {
+                let url = format!(
+                    "https://api.github.com/repos/{}/issues/{}/comments?per_page={}",
+                    full_name, issue.number, PER_PAGE
+                );
+                let response = http::send(request(&client, git_key, &url)).await?;
+                let comments = serde_json::from_str::<Vec<GitComment>>(&response)?;
+
+                Ok::<(u64, Vec<IssueComment>), Error>((
+                    issue.number,
+                    comments
+                        .into_iter()
+                        .map(|c| IssueComment {
+                            author: c.user.login,
+                            body: c.body,
+                        })
+                        .collect(),
+                ))
+            }
+        })
+        .buffered(concurrency.max(1))
+        .collect::<Vec<Result<(u64, Vec<IssueComment>), Error>>>()
+        .await
+        .into_iter()
+        .collect::<Result<BTreeMap<u64, Vec<IssueComment>>, Error>>()?;
+
+    for issue in &mut issues {
+        if let Some(discussion) = discussions.get(&issue.number) {
+            issue.discussion = discussion.clone();
+        }
+
+        issue.redact(redactor);
+    }
+
+    Ok(issues)
+}
+
+/// A digest of the issues of a repository: the themes they cluster into,
+/// the top pain points, likely duplicates and stale issues people care about.
+#[derive(Debug, Clone, Serialize)]
+pub struct IssueDigest {
+    pub repository: String,
+
+    /// Issues closed before this aren't included.
+    pub since: DateTime<Utc>,
+
+    pub open: usize,
+
+    pub closed: usize,
+
+    pub themes: Vec<IssueTheme>,
+
+    pub pain_points: Vec<PainPoint>,
+
+    pub duplicates: Vec<DuplicateIssues>,
+
+    /// Open issues not updated since `since`, with the most engagement first.
+    pub stale: Vec<Issue>,
+
+    /// What was redacted from the issues before they were clustered.
+    pub redactions: Vec<Redaction>,
+
+    #[serde(skip)]
+    links: BTreeMap<u64, String>,
+}
+
+/// Lists the folders of a summarized repository for the issues prompt.
+fn describe_folders(summaries: &SummaryTree) -> String {
+    summaries
+        .folders
+        .iter()
+        .map(|(name, folder)| {
+            let name = if name.is_empty() { "." } else { name };
+
+            format!("- {}: {}", name, one_line(&folder.summary))
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+impl IssueDigest {
+    /// Clusters `issues` with the model and finds the stale ones. Themes are
+    /// linked to the folders of `summaries`, if the repository was summarized.
+    pub async fn new(
+        open_ai_key: &str,
+        settings: &ChatSettings,
+        repository: &str,
+        issues: Vec<Issue>,
+        summaries: Option<&SummaryTree>,
+        since: DateTime<Utc>,
+    ) -> Result<IssueDigest, Error> {
+        let bpe = r50k_base().unwrap();
+        let mut tokens = 0;
+        let mut described = vec![];
+        for issue in issues.iter().take(MAX_PROMPT_ISSUES) {
+            let description = issue.describe();
+
+            tokens += bpe.encode_with_special_tokens(&description).len();
+            if tokens > MAX_PROMPT_TOKENS {
+                break;
+            }
+
+            described.push(description);
+        }
+
+        let mut response = if described.is_empty() {
+            Default::default()
+        } else {
+            let folders = summaries
+                .map(describe_folders)
+                .unwrap_or_else(|| String::from("(the repository has not been summarized)"));
+
+            IssuesPrompt::new(&described.join("\n"), &folders)
+                .send(open_ai_key, settings)
+                .await?
+        };
+
+        // Only folders that exist are kept, and none if the repository wasn't summarized.
+        for theme in &mut response.themes {
+            theme.folders.retain(|folder| {
+                summaries.is_some_and(|summaries| {
+                    summaries.folders.contains_key(folder)
+                        || (folder == "." && summaries.folders.contains_key(""))
+                })
+            });
+        }
+
+        let mut stale = issues
+            .iter()
+            .filter(|i| {
+                i.is_open() && i.updated_at < since && i.engagement() >= MIN_STALE_ENGAGEMENT
+            })
+            .cloned()
+            .collect::<Vec<Issue>>();
+        stale.truncate(MAX_STALE);
+
+        Ok(IssueDigest {
+            repository: repository.to_string(),
+            since,
+            open: issues.iter().filter(|i| i.is_open()).count(),
+            closed: issues.iter().filter(|i| !i.is_open()).count(),
+            themes: response.themes,
+            pain_points: response.pain_points,
+            duplicates: response.duplicates,
+            stale,
+            redactions: issues
+                .iter()
+                .flat_map(|i| i.redactions.iter().cloned())
+                .collect(),
+            links: issues.into_iter().map(|i| (i.number, i.url)).collect(),
+        })
+    }
+
+    /// Links to issues by number, e.g. `[#12](https://github.com/...)`.
+    fn links(&self, numbers: &[u64]) -> String {
+        numbers
+            .iter()
+            .map(|n| match self.links.get(n) {
+                Some(url) => format!("[#{}]({})", n, url),
+                None => format!("#{}", n),
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Renders the digest as markdown.
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "# Issue digest for {}\n\n{} open issues, and {} closed since {}.\n",
+            self.repository,
+            self.open,
+            self.closed,
+            self.since.format("%Y-%m-%d")
+        );
+
+        if !self.pain_points.is_empty() {
+            out.push_str("\n## Top pain points\n\n");
+            for (i, pain_point) in self.pain_points.iter().enumerate() {
+                out.push_str(&format!(
+                    "{}. {} ({})\n",
+                    i + 1,
+                    pain_point.summary.trim(),
+                    self.links(&pain_point.issues)
+                ));
+            }
+        }
+
+        if !self.themes.is_empty() {
+            out.push_str(
+                "\n## Themes\n\n| Theme | Issues | Folders | Summary |\n| --- | --- | --- | --- |\n",
+            );
+            for theme in &self.themes {
+                out.push_str(&format!(
+                    "| {} | {} | {} | {} |\n",
+                    cell(&theme.name),
+                    self.links(&theme.issues),
+                    cell(&theme.folders.join(", ")),
+                    cell(&theme.summary)
+                ));
+            }
+        }
+
+        if !self.duplicates.is_empty() {
+            out.push_str("\n## Possible duplicates\n\n");
+            for duplicate in &self.duplicates {
+                out.push_str(&format!(
+                    "- {}: {}\n",
+                    self.links(&duplicate.issues),
+                    duplicate.reason.trim()
+                ));
+            }
+        }
+
+        if !self.stale.is_empty() {
+            out.push_str(&format!(
+                "\n## Stale high-engagement issues\n\nOpen issues not updated since {}, with the most comments and reactions first.\n\n| Issue | Title | Comments | Reactions | Last updated |\n| --- | --- | --- | --- | --- |\n",
+                self.since.format("%Y-%m-%d")
+            ));
+            for issue in &self.stale {
+                out.push_str(&format!(
+                    "| {} | {} | {} | {} | {} |\n",
+                    self.links(&[issue.number]),
+                    cell(&issue.title),
+                    issue.comments,
+                    issue.reactions,
+                    issue.updated_at.format("%Y-%m-%d")
+                ));
+            }
+        }
+
+        if !self.redactions.is_empty() {
+            out.push_str(&format!(
+                "\n## Redactions\n\n{} values were redacted from the issues before they were clustered.\n\n| Issue | Kind | Lines |\n| --- | --- | --- |\n",
+                self.redactions.len()
+            ));
+            out.push_str(&redaction_rows(&self.redactions));
+        }
+
+        normalize(&out)
+    }
+}
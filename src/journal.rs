@@ -0,0 +1,167 @@
+use crate::gpt::ChatSettings;
+use crate::prompts::{FileSummaryResponse, FolderWideSummaryResponse};
+use eyre::{eyre, Error, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A summary completed during a run, along with the hash of what it was
+/// generated from so it is only reused for the same prompt and settings.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Entry {
+    File {
+        hash: String,
+
+        summary: FileSummaryResponse,
+    },
+
+    Folder {
+        name: String,
+
+        hash: String,
+
+        summary: FolderWideSummaryResponse,
+    },
+}
+
+/// Hashes what a summary is generated from: its rendered prompt, which
+/// covers the contents, style, length and templates, and the model and
+/// sampling settings it is sent with.
+pub fn prompt_hash(prompt: &str, settings: &ChatSettings) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(prompt.as_bytes());
+    hasher.update(
+        serde_json::to_string(settings)
+            .unwrap_or_default()
+            .as_bytes(),
+    );
+
+    format!("{:x}", hasher.finalize())
+}
+
+/// A checkpoint of the file and folder summaries completed so far, written
+/// as one JSON line per summary so a run that dies can be resumed from it.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+
+    file: Mutex<fs::File>,
+
+    files: HashMap<String, (String, FileSummaryResponse)>,
+
+    folders: HashMap<String, (String, FolderWideSummaryResponse)>,
+}
+
+impl Journal {
+    /// Opens the journal at `path`. When resuming, the summaries already in
+    /// it are kept and can be reused, otherwise it is started afresh.
+    pub fn open(path: &Path, resume: bool) -> Result<Journal, Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut files = HashMap::new();
+        let mut folders = HashMap::new();
+
+        if resume {
+            let contents = fs::read_to_string(path).unwrap_or_default();
+
+            // A line cut short by the run dying is skipped.
+            for entry in contents
+                .lines()
+                .filter_map(|line| serde_json::from_str::<Entry>(line).ok())
+            {
+                match entry {
+                    Entry::File { hash, summary } => {
+                        files.insert(summary.name.clone(), (hash, summary));
+                    }
+                    Entry::Folder {
+                        name,
+                        hash,
+                        summary,
+                    } => {
+                        folders.insert(name, (hash, summary));
+                    }
+                }
+            }
+        } else {
+            fs::write(path, "")?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| eyre!("failed to open {}: {}", path.display(), e))?;
+
+        Ok(Journal {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            files,
+            folders,
+        })
+    }
+
+    /// The number of file summaries that can be reused.
+    pub fn resumed_files(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns the recorded summary of a file, if it was generated from `hash`.
+    pub fn file(&self, name: &str, hash: &str) -> Option<FileSummaryResponse> {
+        self.files
+            .get(name)
+            .filter(|(recorded, _)| recorded == hash)
+            .map(|(_, summary)| summary.clone())
+    }
+
+    /// Returns the recorded summary of a folder, if it was generated from `hash`.
+    pub fn folder(&self, name: &str, hash: &str) -> Option<FolderWideSummaryResponse> {
+        self.folders
+            .get(name)
+            .filter(|(recorded, _)| recorded == hash)
+            .map(|(_, summary)| summary.clone())
+    }
+
+    pub fn record_file(&self, hash: &str, summary: &FileSummaryResponse) -> Result<(), Error> {
+        self.append(&Entry::File {
+            hash: hash.to_string(),
+            summary: summary.clone(),
+        })
+    }
+
+    pub fn record_folder(
+        &self,
+        name: &str,
+        hash: &str,
+        summary: &FolderWideSummaryResponse,
+    ) -> Result<(), Error> {
+        self.append(&Entry::Folder {
+            name: name.to_string(),
+            hash: hash.to_string(),
+            summary: summary.clone(),
+        })
+    }
+
+    fn append(&self, entry: &Entry) -> Result<(), Error> {
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        file.flush()?;
+
+        Ok(())
+    }
+
+    /// Deletes the journal once the run it checkpoints has completed.
+    pub fn remove(&self) -> Result<(), Error> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+
+        Ok(())
+    }
+}
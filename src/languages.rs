@@ -0,0 +1,163 @@
+/// File extensions and the languages they are written in.
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("rs", "Rust"),
+    ("py", "Python"),
+    ("js", "JavaScript"),
+    ("jsx", "JavaScript"),
+    ("mjs", "JavaScript"),
+    ("cjs", "JavaScript"),
+    ("ts", "TypeScript"),
+    ("tsx", "TypeScript"),
+    ("go", "Go"),
+    ("java", "Java"),
+    ("kt", "Kotlin"),
+    ("scala", "Scala"),
+    ("rb", "Ruby"),
+    ("php", "PHP"),
+    ("c", "C"),
+    ("h", "C"),
+    ("cc", "C++"),
+    ("cpp", "C++"),
+    ("hpp", "C++"),
+    ("cs", "C#"),
+    ("swift", "Swift"),
+    ("m", "Objective-C"),
+    ("sh", "Shell"),
+    ("bash", "Shell"),
+    ("zsh", "Shell"),
+    ("ps1", "PowerShell"),
+    ("lua", "Lua"),
+    ("pl", "Perl"),
+    ("ex", "Elixir"),
+    ("exs", "Elixir"),
+    ("erl", "Erlang"),
+    ("hs", "Haskell"),
+    ("ml", "OCaml"),
+    ("clj", "Clojure"),
+    ("dart", "Dart"),
+    ("sol", "Solidity"),
+    ("sql", "SQL"),
+    ("html", "HTML"),
+    ("css", "CSS"),
+    ("scss", "SCSS"),
+    ("vue", "Vue"),
+    ("svelte", "Svelte"),
+    ("md", "Markdown"),
+    ("json", "JSON"),
+    ("toml", "TOML"),
+    ("yaml", "YAML"),
+    ("yml", "YAML"),
+    ("xml", "XML"),
+    ("proto", "Protocol Buffers"),
+];
+
+/// File names that identify a language regardless of their extension.
+const FILE_NAMES: &[(&str, &str)] = &[
+    ("Dockerfile", "Dockerfile"),
+    ("Makefile", "Makefile"),
+    ("CMakeLists.txt", "CMake"),
+    ("Gemfile", "Ruby"),
+    ("Rakefile", "Ruby"),
+];
+
+/// Returns the language of a file from its name or extension, or `"Text"`
+/// if it isn't recognized.
+pub fn language(path: &str) -> &'static str {
+    let name = path.rsplit('/').next().unwrap_or_default();
+
+    if let Some((_, language)) = FILE_NAMES.iter().find(|(n, _)| *n == name) {
+        return language;
+    }
+
+    name.rsplit_once('.')
+        .and_then(|(_, ext)| EXTENSIONS.iter().find(|(e, _)| e.eq_ignore_ascii_case(ext)))
+        .map(|(_, language)| *language)
+        .unwrap_or("Text")
+}
+
+/// Interpreters named in shebang lines and the languages they run.
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("python", "Python"),
+    ("node", "JavaScript"),
+    ("deno", "TypeScript"),
+    ("bash", "Shell"),
+    ("sh", "Shell"),
+    ("zsh", "Shell"),
+    ("ruby", "Ruby"),
+    ("perl", "Perl"),
+    ("php", "PHP"),
+    ("lua", "Lua"),
+];
+
+/// Returns the language of a file from its name, falling back to its
+/// shebang line and then to markers in its content for files whose name
+/// doesn't say. C headers using C++ features are detected as C++.
+pub fn detect(path: &str, content: &str) -> &'static str {
+    let by_name = language(path);
+
+    if by_name == "C" && path.ends_with(".h") {
+        let cpp = ["namespace ", "template <", "template<", "class ", "std::"];
+
+        if cpp.iter().any(|marker| content.contains(marker)) {
+            return "C++";
+        }
+    }
+
+    if by_name != "Text" {
+        return by_name;
+    }
+
+    if let Some(shebang) = content.lines().next().and_then(|l| l.strip_prefix("#!")) {
+        // `#!/usr/bin/env python3` and `#!/bin/bash` both name the interpreter last.
+        let interpreter = shebang
+            .split_whitespace()
+            .last()
+            .and_then(|word| word.rsplit('/').next())
+            .unwrap_or_default();
+
+        if let Some((_, language)) = INTERPRETERS
+            .iter()
+            .find(|(name, _)| interpreter.starts_with(name))
+        {
+            return language;
+        }
+    }
+
+    let start = content.trim_start();
+    if start.starts_with("<?php") {
+        "PHP"
+    } else if start.starts_with("<?xml") {
+        "XML"
+    } else if start.to_lowercase().starts_with("<!doctype html") {
+        "HTML"
+    } else {
+        "Text"
+    }
+}
+
+/// How comments are written in a language: the prefixes of line comments
+/// and the delimiters of block comments.
+pub fn comment_syntax(
+    language: &str,
+) -> (
+    &'static [&'static str],
+    Option<(&'static str, &'static str)>,
+) {
+    match language {
+        "Rust" | "JavaScript" | "TypeScript" | "Go" | "Java" | "Kotlin" | "Scala" | "C" | "C++"
+        | "C#" | "Swift" | "Objective-C" | "Dart" | "Solidity" | "PHP" | "SCSS"
+        | "Protocol Buffers" | "Vue" | "Svelte" => (&["//"], Some(("/*", "*/"))),
+        "CSS" => (&[], Some(("/*", "*/"))),
+        "Python" => (&["#"], Some(("\"\"\"", "\"\"\""))),
+        "Shell" | "Ruby" | "Perl" | "Elixir" | "TOML" | "YAML" | "Makefile" | "Dockerfile"
+        | "CMake" | "PowerShell" => (&["#"], None),
+        "Lua" => (&["--"], Some(("--[[", "]]"))),
+        "SQL" => (&["--"], Some(("/*", "*/"))),
+        "Haskell" => (&["--"], Some(("{-", "-}"))),
+        "OCaml" => (&[], Some(("(*", "*)"))),
+        "Erlang" => (&["%"], None),
+        "Clojure" => (&[";"], None),
+        "HTML" | "XML" | "Markdown" => (&[], Some(("<!--", "-->"))),
+        _ => (&[], None),
+    }
+}
@@ -0,0 +1,30 @@
+pub mod ask;
+pub mod cache;
+pub mod catalog;
+pub mod cli;
+pub mod compare;
+pub mod config;
+pub mod credentials;
+pub mod diagram;
+pub mod docs;
+pub mod git;
+pub mod gpt;
+pub mod history;
+pub mod http;
+pub mod index;
+pub mod issues;
+pub mod journal;
+pub mod languages;
+pub mod logging;
+pub mod manifests;
+pub mod mcp;
+pub mod mock;
+pub mod outline;
+pub mod progress;
+pub mod prompts;
+pub mod redact;
+pub mod report;
+pub mod serve;
+pub mod stats;
+pub mod summary_files;
+pub mod templates;
@@ -0,0 +1,45 @@
+use clap::ValueEnum;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+/// How log lines are written to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+
+    /// One JSON object per line
+    Json,
+}
+
+/// The level gitsum logs at: errors when quiet, warnings by default, then
+/// info, debug and trace for each `-v`.
+fn level(verbosity: u8, quiet: bool) -> &'static str {
+    match (quiet, verbosity) {
+        (true, _) => "error",
+        (false, 0) => "warn",
+        (false, 1) => "info",
+        (false, 2) => "debug",
+        (false, _) => "trace",
+    }
+}
+
+/// Sends logs to stderr, keeping stdout for summaries and the MCP protocol.
+/// `RUST_LOG` overrides the level. From `-vv`, the time spent in every HTTP
+/// and LLM call is logged as its span closes.
+pub fn init(verbosity: u8, quiet: bool, format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,gitsum={}", level(verbosity, quiet))));
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_span_events(FmtSpan::CLOSE);
+
+    // A second subscriber can't be installed, which only matters in tests.
+    let _ = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    };
+}
@@ -1,10 +1,5 @@
 use eyre::{Error, Result};
-use tokio;
-
-mod cli;
-mod git;
-mod gpt;
-mod prompts;
+use gitsum::cli;
 
 // TODO: Summarize each folder flag
 #[tokio::main]
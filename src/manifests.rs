@@ -0,0 +1,651 @@
+use crate::git::{File, RepositoryContent};
+use crate::languages::detect;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The most dependencies described in summaries and reports.
+const MAX_MAJOR_DEPENDENCIES: usize = 25;
+
+/// The package ecosystem a dependency comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ecosystem {
+    Cargo,
+
+    Npm,
+
+    Python,
+
+    Go,
+
+    Ruby,
+}
+
+impl Ecosystem {
+    /// The languages of the files that can import dependencies of this ecosystem.
+    fn languages(&self) -> &'static [&'static str] {
+        match self {
+            Ecosystem::Cargo => &["Rust"],
+            Ecosystem::Npm => &["JavaScript", "TypeScript", "Vue", "Svelte"],
+            Ecosystem::Python => &["Python"],
+            Ecosystem::Go => &["Go"],
+            Ecosystem::Ruby => &["Ruby"],
+        }
+    }
+
+    /// A pattern matching an import of `name` in a source file.
+    fn import_pattern(&self, name: &str) -> String {
+        match self {
+            Ecosystem::Cargo => format!(
+                r"\b(?:use|extern crate)\s+::?{0}\b|\b{0}::",
+                regex::escape(&name.replace('-', "_"))
+            ),
+            Ecosystem::Npm => format!(
+                r#"(?:from|import|require\()\s*['"]{}(?:/[^'"]*)?['"]"#,
+                regex::escape(name)
+            ),
+            Ecosystem::Python => format!(
+                r"(?m)^\s*(?:import|from)\s+{}\b",
+                regex::escape(&name.to_lowercase().replace('-', "_"))
+            ),
+            Ecosystem::Go => format!(r#""{}(?:/[^"]*)?""#, regex::escape(name)),
+            Ecosystem::Ruby => format!(r#"require\s*\(?\s*['"]{}['"]"#, regex::escape(name)),
+        }
+    }
+}
+
+/// How a dependency is used by the project declaring it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependencyKind {
+    Normal,
+
+    Dev,
+
+    Build,
+}
+
+/// A dependency declared in a manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dependency {
+    pub name: String,
+
+    /// The version requirement in the manifest, or where the dependency comes
+    /// from if it isn't a registry version, such as `path` or `git`.
+    pub version: String,
+
+    /// The version in a lockfile, if there is one.
+    #[serde(default)]
+    pub resolved: Option<String>,
+
+    pub kind: DependencyKind,
+
+    pub ecosystem: Ecosystem,
+
+    /// The path of the manifest declaring it.
+    pub manifest: String,
+
+    /// The files importing it.
+    #[serde(default)]
+    pub files: Vec<String>,
+
+    /// What the repository uses it for, described by the model.
+    #[serde(default)]
+    pub usage: String,
+}
+
+/// A manifest declaring a workspace, and the paths of its members.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workspace {
+    pub manifest: String,
+
+    pub members: Vec<String>,
+}
+
+/// The dependencies and workspaces declared in the manifests of a repository.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Dependencies {
+    pub dependencies: Vec<Dependency>,
+
+    pub workspaces: Vec<Workspace>,
+}
+
+/// Collects dependencies while a manifest is parsed.
+struct Collector<'a> {
+    manifest: &'a str,
+
+    ecosystem: Ecosystem,
+
+    dependencies: Vec<Dependency>,
+}
+
+impl<'a> Collector<'a> {
+    fn new(manifest: &'a str, ecosystem: Ecosystem) -> Self {
+        Collector {
+            manifest,
+            ecosystem,
+            dependencies: vec![],
+        }
+    }
+
+    fn add(&mut self, name: &str, version: &str, kind: DependencyKind) {
+        let name = name.trim();
+
+        if name.is_empty() {
+            return;
+        }
+
+        self.dependencies.push(Dependency {
+            name: name.to_string(),
+            version: version.trim().to_string(),
+            resolved: None,
+            kind,
+            ecosystem: self.ecosystem,
+            manifest: self.manifest.to_string(),
+            files: vec![],
+            usage: String::new(),
+        });
+    }
+}
+
+/// Returns the version requirement of a Cargo dependency, which is either a
+/// string or a table with a `version`, `path`, `git` or `workspace` key.
+fn cargo_version(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(version) => version.clone(),
+        toml::Value::Table(table) => table
+            .get("version")
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string())
+            .or_else(|| {
+                ["path", "git", "workspace"]
+                    .into_iter()
+                    .find(|key| table.contains_key(*key))
+                    .map(|key| key.to_string())
+            })
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+fn cargo_toml(file: &File, workspaces: &mut Vec<Workspace>) -> Vec<Dependency> {
+    let mut collector = Collector::new(&file.name, Ecosystem::Cargo);
+    let table = match toml::from_str::<toml::Table>(&file.content) {
+        Ok(table) => table,
+        Err(_) => return vec![],
+    };
+
+    // Dependencies of the package, shared by a workspace, or of a platform.
+    let mut sections = vec![&table];
+    if let Some(workspace) = table.get("workspace").and_then(|w| w.as_table()) {
+        sections.push(workspace);
+
+        if let Some(members) = workspace.get("members").and_then(|m| m.as_array()) {
+            workspaces.push(Workspace {
+                manifest: file.name.clone(),
+                members: members
+                    .iter()
+                    .filter_map(|m| m.as_str().map(|m| m.to_string()))
+                    .collect(),
+            });
+        }
+    }
+
+    if let Some(targets) = table.get("target").and_then(|t| t.as_table()) {
+        for target in targets.values().filter_map(|t| t.as_table()) {
+            sections.push(target);
+        }
+    }
+
+    for section in sections {
+        for (key, kind) in [
+            ("dependencies", DependencyKind::Normal),
+            ("dev-dependencies", DependencyKind::Dev),
+            ("build-dependencies", DependencyKind::Build),
+        ] {
+            if let Some(dependencies) = section.get(key).and_then(|d| d.as_table()) {
+                for (name, value) in dependencies {
+                    collector.add(name, &cargo_version(value), kind);
+                }
+            }
+        }
+    }
+
+    collector.dependencies
+}
+
+fn package_json(file: &File, workspaces: &mut Vec<Workspace>) -> Vec<Dependency> {
+    let mut collector = Collector::new(&file.name, Ecosystem::Npm);
+    let json = match serde_json::from_str::<serde_json::Value>(&file.content) {
+        Ok(json) => json,
+        Err(_) => return vec![],
+    };
+
+    for (key, kind) in [
+        ("dependencies", DependencyKind::Normal),
+        ("peerDependencies", DependencyKind::Normal),
+        ("optionalDependencies", DependencyKind::Normal),
+        ("devDependencies", DependencyKind::Dev),
+    ] {
+        if let Some(dependencies) = json.get(key).and_then(|d| d.as_object()) {
+            for (name, version) in dependencies {
+                collector.add(name, version.as_str().unwrap_or_default(), kind);
+            }
+        }
+    }
+
+    // Workspaces are either a list of globs or `{ "packages": [...] }`.
+    let members = json
+        .get("workspaces")
+        .and_then(|w| w.as_array().or_else(|| w.get("packages")?.as_array()));
+    if let Some(members) = members {
+        workspaces.push(Workspace {
+            manifest: file.name.clone(),
+            members: members
+                .iter()
+                .filter_map(|m| m.as_str().map(|m| m.to_string()))
+                .collect(),
+        });
+    }
+
+    collector.dependencies
+}
+
+/// Splits a PEP 508 requirement such as `requests[socks]>=2.0; python_version > "3"`
+/// into its name and version specifier.
+fn python_requirement(requirement: &str) -> Option<(&str, &str)> {
+    let requirement = requirement.split(';').next()?.split('#').next()?.trim();
+    let end = requirement
+        .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_' || c == '.'))
+        .unwrap_or(requirement.len());
+
+    let (name, rest) = requirement.split_at(end);
+    let version = match rest.find(']') {
+        Some(i) if rest.starts_with('[') => &rest[i + 1..],
+        _ => rest,
+    };
+
+    (!name.is_empty()).then_some((name, version.trim()))
+}
+
+fn requirements_txt(file: &File) -> Vec<Dependency> {
+    let mut collector = Collector::new(&file.name, Ecosystem::Python);
+    let kind = if file.name.contains("dev") || file.name.contains("test") {
+        DependencyKind::Dev
+    } else {
+        DependencyKind::Normal
+    };
+
+    for line in file.content.lines() {
+        let line = line.trim();
+
+        // Options such as `-r other.txt` and `-e .`, and direct urls.
+        if line.starts_with('-') || line.contains("://") {
+            continue;
+        }
+
+        if let Some((name, version)) = python_requirement(line) {
+            collector.add(name, version, kind);
+        }
+    }
+
+    collector.dependencies
+}
+
+fn pyproject_toml(file: &File) -> Vec<Dependency> {
+    let mut collector = Collector::new(&file.name, Ecosystem::Python);
+    let table = match toml::from_str::<toml::Table>(&file.content) {
+        Ok(table) => table,
+        Err(_) => return vec![],
+    };
+
+    let requirements = |value: Option<&toml::Value>| -> Vec<String> {
+        value
+            .and_then(|v| v.as_array())
+            .map(|a| {
+                a.iter()
+                    .filter_map(|r| r.as_str().map(|r| r.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    if let Some(project) = table.get("project") {
+        for requirement in requirements(project.get("dependencies")) {
+            if let Some((name, version)) = python_requirement(&requirement) {
+                collector.add(name, version, DependencyKind::Normal);
+            }
+        }
+
+        if let Some(groups) = project
+            .get("optional-dependencies")
+            .and_then(|o| o.as_table())
+        {
+            for group in groups.values() {
+                for requirement in requirements(Some(group)) {
+                    if let Some((name, version)) = python_requirement(&requirement) {
+                        collector.add(name, version, DependencyKind::Dev);
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(poetry) = table
+        .get("tool")
+        .and_then(|t| t.get("poetry"))
+        .and_then(|p| p.as_table())
+    {
+        let mut sections = vec![
+            (poetry.get("dependencies"), DependencyKind::Normal),
+            (poetry.get("dev-dependencies"), DependencyKind::Dev),
+        ];
+        if let Some(groups) = poetry.get("group").and_then(|g| g.as_table()) {
+            for group in groups.values() {
+                sections.push((group.get("dependencies"), DependencyKind::Dev));
+            }
+        }
+
+        for (section, kind) in sections {
+            for (name, value) in section.and_then(|s| s.as_table()).into_iter().flatten() {
+                if name != "python" {
+                    collector.add(name, &cargo_version(value), kind);
+                }
+            }
+        }
+    }
+
+    collector.dependencies
+}
+
+fn go_mod(file: &File) -> Vec<Dependency> {
+    let mut collector = Collector::new(&file.name, Ecosystem::Go);
+    let mut in_block = false;
+
+    for line in file.content.lines() {
+        let line = line.trim();
+
+        let requirement = if in_block {
+            if line == ")" {
+                in_block = false;
+                continue;
+            }
+            line
+        } else if line == "require (" {
+            in_block = true;
+            continue;
+        } else if let Some(requirement) = line.strip_prefix("require ") {
+            requirement
+        } else {
+            continue;
+        };
+
+        // Indirect requirements are dependencies of dependencies.
+        if requirement.ends_with("// indirect") {
+            continue;
+        }
+
+        let mut parts = requirement.split_whitespace();
+        if let (Some(name), Some(version)) = (parts.next(), parts.next()) {
+            collector.add(name, version, DependencyKind::Normal);
+        }
+    }
+
+    // go.mod versions are exact, so they are already resolved.
+    for dependency in &mut collector.dependencies {
+        dependency.resolved = Some(dependency.version.clone());
+    }
+
+    collector.dependencies
+}
+
+fn gemfile(file: &File) -> Vec<Dependency> {
+    let mut collector = Collector::new(&file.name, Ecosystem::Ruby);
+    let gem = Regex::new(r#"^gem\s+['"]([^'"]+)['"](?:\s*,\s*['"]([^'"]+)['"])?"#).unwrap();
+    let mut groups = 0;
+
+    for line in file.content.lines() {
+        let line = line.trim();
+
+        if line.starts_with("group ") && line.contains(" do") {
+            groups += 1;
+        } else if line == "end" && groups > 0 {
+            groups -= 1;
+        } else if let Some(captures) = gem.captures(line) {
+            let dev = groups > 0 || line.contains(":development") || line.contains(":test");
+
+            collector.add(
+                &captures[1],
+                captures.get(2).map(|v| v.as_str()).unwrap_or_default(),
+                if dev {
+                    DependencyKind::Dev
+                } else {
+                    DependencyKind::Normal
+                },
+            );
+        }
+    }
+
+    collector.dependencies
+}
+
+/// Reads the resolved versions from a lockfile, keyed by package name.
+fn lockfile(file_name: &str, content: &str) -> Option<(Ecosystem, HashMap<String, String>)> {
+    let mut versions = HashMap::new();
+
+    let ecosystem = match file_name {
+        // These all list `[[package]]` tables with a name and version.
+        "Cargo.lock" | "poetry.lock" | "uv.lock" => {
+            let table = toml::from_str::<toml::Table>(content).ok()?;
+
+            for package in table.get("package")?.as_array()? {
+                if let (Some(name), Some(version)) = (
+                    package.get("name").and_then(|n| n.as_str()),
+                    package.get("version").and_then(|v| v.as_str()),
+                ) {
+                    versions
+                        .entry(name.to_string())
+                        .or_insert(version.to_string());
+                }
+            }
+
+            if file_name == "Cargo.lock" {
+                Ecosystem::Cargo
+            } else {
+                Ecosystem::Python
+            }
+        }
+        "package-lock.json" => {
+            let json = serde_json::from_str::<serde_json::Value>(content).ok()?;
+
+            // Version 2 and 3 lockfiles key packages by their node_modules path,
+            // version 1 lockfiles by their name.
+            if let Some(packages) = json.get("packages").and_then(|p| p.as_object()) {
+                for (path, package) in packages {
+                    if let (Some(name), Some(version)) = (
+                        path.rsplit("node_modules/")
+                            .next()
+                            .filter(|n| !n.is_empty()),
+                        package.get("version").and_then(|v| v.as_str()),
+                    ) {
+                        versions
+                            .entry(name.to_string())
+                            .or_insert(version.to_string());
+                    }
+                }
+            } else if let Some(dependencies) = json.get("dependencies").and_then(|d| d.as_object())
+            {
+                for (name, package) in dependencies {
+                    if let Some(version) = package.get("version").and_then(|v| v.as_str()) {
+                        versions.insert(name.clone(), version.to_string());
+                    }
+                }
+            }
+
+            Ecosystem::Npm
+        }
+        "yarn.lock" => {
+            let mut names = vec![];
+
+            for line in content.lines() {
+                if !line.starts_with(' ') && line.ends_with(':') {
+                    // `"react@^18.0.0", react@^18:`, where scoped names start with `@`.
+                    names = line
+                        .trim_end_matches(':')
+                        .split(", ")
+                        .filter_map(|spec| {
+                            let spec = spec.trim_matches('"');
+                            spec.rfind('@')
+                                .filter(|i| *i > 0)
+                                .map(|i| spec[..i].to_string())
+                        })
+                        .collect();
+                } else if let Some(version) = line.trim().strip_prefix("version") {
+                    let version = version.trim_start_matches(':').trim().trim_matches('"');
+
+                    for name in names.drain(..) {
+                        versions.entry(name).or_insert(version.to_string());
+                    }
+                }
+            }
+
+            Ecosystem::Npm
+        }
+        "pnpm-lock.yaml" => {
+            let package =
+                Regex::new(r"(?m)^  '?/?((?:@[^/@\s]+/)?[^@/\s':]+)[@/](\d[^:(\s']*)").unwrap();
+
+            for captures in package.captures_iter(content) {
+                versions
+                    .entry(captures[1].to_string())
+                    .or_insert(captures[2].to_string());
+            }
+
+            Ecosystem::Npm
+        }
+        "Gemfile.lock" => {
+            let spec = Regex::new(r"(?m)^    ([^\s(]+) \(([^)]+)\)$").unwrap();
+
+            for captures in spec.captures_iter(content) {
+                versions
+                    .entry(captures[1].to_string())
+                    .or_insert(captures[2].to_string());
+            }
+
+            Ecosystem::Ruby
+        }
+        _ => return None,
+    };
+
+    Some((ecosystem, versions))
+}
+
+impl Dependencies {
+    /// Parses every manifest and lockfile in a repository, and finds the
+    /// files importing each dependency.
+    pub fn new(contents: &RepositoryContent) -> Dependencies {
+        let mut dependencies = vec![];
+        let mut workspaces = vec![];
+        let mut resolved: HashMap<Ecosystem, HashMap<String, String>> = HashMap::new();
+
+        let mut files = contents
+            .folders
+            .values()
+            .flat_map(|folder| folder.files.values())
+            .collect::<Vec<&File>>();
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for file in &files {
+            let file_name = file.name.rsplit('/').next().unwrap_or_default();
+
+            match file_name {
+                "Cargo.toml" => dependencies.extend(cargo_toml(file, &mut workspaces)),
+                "package.json" => dependencies.extend(package_json(file, &mut workspaces)),
+                "pyproject.toml" => dependencies.extend(pyproject_toml(file)),
+                "go.mod" => dependencies.extend(go_mod(file)),
+                "Gemfile" => dependencies.extend(gemfile(file)),
+                name if name.starts_with("requirements") && name.ends_with(".txt") => {
+                    dependencies.extend(requirements_txt(file))
+                }
+                name => {
+                    if let Some((ecosystem, versions)) = lockfile(name, &file.content) {
+                        let known = resolved.entry(ecosystem).or_default();
+                        for (name, version) in versions {
+                            known.entry(name).or_insert(version);
+                        }
+                    }
+                }
+            }
+        }
+
+        for dependency in &mut dependencies {
+            if dependency.resolved.is_none() {
+                dependency.resolved = resolved
+                    .get(&dependency.ecosystem)
+                    .and_then(|versions| versions.get(&dependency.name))
+                    .cloned();
+            }
+
+            let import = match Regex::new(&dependency.ecosystem.import_pattern(&dependency.name)) {
+                Ok(import) => import,
+                Err(_) => continue,
+            };
+
+            dependency.files = files
+                .iter()
+                .filter(|file| {
+                    dependency
+                        .ecosystem
+                        .languages()
+                        .contains(&detect(&file.name, &file.content))
+                        && import.is_match(&file.content)
+                })
+                .map(|file| file.name.clone())
+                .collect();
+        }
+
+        Dependencies {
+            dependencies,
+            workspaces,
+        }
+    }
+
+    /// The dependencies worth describing: those used by the project itself
+    /// rather than its tests or build, imported by the most files first.
+    pub fn major(&self) -> Vec<Dependency> {
+        let mut major = self
+            .dependencies
+            .iter()
+            .filter(|d| d.kind == DependencyKind::Normal && !d.files.is_empty())
+            .cloned()
+            .collect::<Vec<Dependency>>();
+        // Members of a workspace often declare the same dependency, keep the
+        // declaration imported by the most files.
+        major.sort_by(|a, b| a.name.cmp(&b.name).then(b.files.len().cmp(&a.files.len())));
+        major.dedup_by(|a, b| a.name == b.name && a.ecosystem == b.ecosystem);
+        major.sort_by(|a, b| b.files.len().cmp(&a.files.len()).then(a.name.cmp(&b.name)));
+        major.truncate(MAX_MAJOR_DEPENDENCIES);
+
+        major
+    }
+}
+
+/// Describes dependencies as text, one per line with their version and
+/// what they are used for if known.
+pub fn describe(dependencies: &[Dependency]) -> String {
+    dependencies
+        .iter()
+        .map(|d| {
+            let version = d.resolved.as_deref().unwrap_or(&d.version);
+            let mut line = format!("- {} {} ({} files)", d.name, version, d.files.len());
+
+            if !d.usage.is_empty() {
+                line.push_str(&format!(": {}", d.usage));
+            }
+
+            line
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
@@ -0,0 +1,472 @@
+use crate::cache::{self, Cache, SummaryTree};
+use crate::config::{Config, OutputFormat};
+use crate::git::Git;
+use crate::index::{EmbeddingProvider, Index, DEFAULT_EMBEDDINGS_MODEL};
+use crate::report::Report;
+use eyre::{eyre, Error, Result};
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+/// The protocol version answered when the client doesn't ask for one.
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+
+const RESOURCE_SCHEME: &str = "gitsum://";
+
+const PARSE_ERROR: i64 = -32700;
+
+const METHOD_NOT_FOUND: i64 = -32601;
+
+const INVALID_PARAMS: i64 = -32602;
+
+/// The repository every tool works on.
+#[derive(Debug, Deserialize)]
+struct RepoArguments {
+    username: String,
+
+    repo: String,
+
+    branch: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SummarizeRepoArguments {
+    #[serde(flatten)]
+    repo: RepoArguments,
+
+    /// Fetch and summarize again even if summaries are cached.
+    #[serde(default)]
+    refresh: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct SummarizeFolderArguments {
+    #[serde(flatten)]
+    repo: RepoArguments,
+
+    folder: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SummarizeFileArguments {
+    #[serde(flatten)]
+    repo: RepoArguments,
+
+    path: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchArguments {
+    #[serde(flatten)]
+    repo: RepoArguments,
+
+    query: String,
+
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    10
+}
+
+#[derive(Debug, Deserialize)]
+struct CachedSummaryArguments {
+    #[serde(flatten)]
+    repo: RepoArguments,
+
+    /// A folder or file, or the whole repository if missing.
+    #[serde(default)]
+    path: Option<String>,
+}
+
+/// A JSON-RPC error, returned in place of a result.
+struct RpcError(i64, String);
+
+/// The JSON schema of a tool's arguments: the repository, plus `properties`.
+fn schema(properties: Value, required: &[&str]) -> Value {
+    let mut schema = json!({
+        "type": "object",
+        "properties": {
+            "username": { "type": "string", "description": "The username of the repository owner" },
+            "repo": { "type": "string", "description": "The name of the repository" },
+            "branch": { "type": "string", "description": "The branch of the repository" },
+        },
+        "required": ["username", "repo", "branch"],
+    });
+
+    if let (Some(all), Some(properties)) =
+        (schema["properties"].as_object_mut(), properties.as_object())
+    {
+        all.extend(properties.clone());
+    }
+
+    if let Some(all) = schema["required"].as_array_mut() {
+        all.extend(required.iter().map(|name| json!(name)));
+    }
+
+    schema
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "summarize_repo",
+            "description": "Summarize a github repository, returning the summary of the repository and of each of its folders and files. Cached summaries are returned unless refresh is set.",
+            "inputSchema": schema(json!({
+                "refresh": { "type": "boolean", "description": "Summarize again even if summaries are cached" },
+            }), &[]),
+        },
+        {
+            "name": "summarize_folder",
+            "description": "Summarize one folder of a github repository and the files in it.",
+            "inputSchema": schema(json!({
+                "folder": { "type": "string", "description": "The path of the folder, empty for the root" },
+            }), &["folder"]),
+        },
+        {
+            "name": "summarize_file",
+            "description": "Summarize one file of a github repository.",
+            "inputSchema": schema(json!({
+                "path": { "type": "string", "description": "The path of the file" },
+            }), &["path"]),
+        },
+        {
+            "name": "search_repo",
+            "description": "Find the files of a summarized repository most related to a query, using embeddings.",
+            "inputSchema": schema(json!({
+                "query": { "type": "string", "description": "What to search for" },
+                "limit": { "type": "integer", "description": "The maximum number of results, defaults to 10" },
+            }), &["query"]),
+        },
+        {
+            "name": "get_cached_summary",
+            "description": "Return the cached summary of a repository, or of one of its folders or files, without calling a model.",
+            "inputSchema": schema(json!({
+                "path": { "type": "string", "description": "A folder or file, or the whole repository if missing" },
+            }), &[]),
+        },
+    ])
+}
+
+fn arguments<T: DeserializeOwned>(arguments: &Value) -> Result<T, Error> {
+    serde_json::from_value(arguments.clone()).map_err(|e| eyre!("invalid arguments: {}", e))
+}
+
+/// Serves gitsum's summaries as Model Context Protocol tools and resources,
+/// reading JSON-RPC messages from stdin and writing responses to stdout,
+/// one per line.
+#[derive(Debug)]
+pub struct McpServer {
+    config: Config,
+}
+
+impl McpServer {
+    /// Creates a server using `config`. Summaries are never printed, since
+    /// stdout carries the protocol.
+    pub fn new(mut config: Config) -> McpServer {
+        config.output = OutputFormat::Json;
+
+        McpServer { config }
+    }
+
+    pub async fn run(&self) -> Result<(), Error> {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let mut stdout = tokio::io::stdout();
+
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(response) = self.handle(&line).await {
+                stdout
+                    .write_all(format!("{}\n", response).as_bytes())
+                    .await?;
+                stdout.flush().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles one message, returning the response to write, if any.
+    /// Notifications, which have no id, are never answered.
+    pub async fn handle(&self, message: &str) -> Option<Value> {
+        let message = match serde_json::from_str::<Value>(message) {
+            Ok(message) => message,
+            Err(e) => {
+                return Some(json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": { "code": PARSE_ERROR, "message": e.to_string() },
+                }))
+            }
+        };
+
+        let id = message.get("id")?.clone();
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = message.get("params").cloned().unwrap_or(json!({}));
+
+        let response = match self.call(method, &params).await {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(RpcError(code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+
+        Some(response)
+    }
+
+    async fn call(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": params["protocolVersion"].as_str().unwrap_or(PROTOCOL_VERSION),
+                "capabilities": { "tools": {}, "resources": {} },
+                "serverInfo": { "name": "gitsum", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tools() })),
+            "tools/call" => {
+                let name = params["name"].as_str().unwrap_or_default();
+                let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+
+                // Tool failures are reported to the model rather than as
+                // protocol errors, so that it can correct itself.
+                Ok(match self.tool(name, &arguments).await {
+                    Ok(result) => json!({
+                        "content": [{ "type": "text", "text": result.to_string() }],
+                        "isError": false,
+                    }),
+                    Err(e) => json!({
+                        "content": [{ "type": "text", "text": e.to_string() }],
+                        "isError": true,
+                    }),
+                })
+            }
+            "resources/list" => Ok(json!({ "resources": self.resources() })),
+            "resources/read" => {
+                let uri = params["uri"].as_str().unwrap_or_default();
+
+                self.read_resource(uri)
+                    .map(|text| {
+                        json!({
+                            "contents": [{ "uri": uri, "mimeType": "text/markdown", "text": text }],
+                        })
+                    })
+                    .map_err(|e| RpcError(INVALID_PARAMS, e.to_string()))
+            }
+            _ => Err(RpcError(
+                METHOD_NOT_FOUND,
+                format!("unknown method {}", method),
+            )),
+        }
+    }
+
+    /// The cache of a repository, once its names are checked.
+    fn cache(&self, repo: &RepoArguments) -> Result<Cache, Error> {
+        cache::validate(&repo.username, &repo.repo, &repo.branch)?;
+
+        Ok(Cache::new(
+            &self.config.cache_dir,
+            &repo.username,
+            &repo.repo,
+            &repo.branch,
+        ))
+    }
+
+    /// A `Git` for the repository, with its cached contents, fetching and
+    /// caching them if there are none or `refresh` is set.
+    async fn git(&self, repo: &RepoArguments, refresh: bool) -> Result<Git, Error> {
+        let cache = self.cache(repo)?;
+        let mut git = Git::new(
+            &self.config.github_key().unwrap_or_default(),
+            &self.config.open_ai_key()?,
+            &repo.username,
+            &repo.repo,
+            &repo.branch,
+        )
+        .with_config(self.config.clone());
+
+        match cache.load_contents() {
+            Ok(contents) if !refresh => git.repository_content = contents,
+            _ => {
+                git.get_contents(Client::new()).await?;
+                cache.save_contents(&git.repository_content)?;
+            }
+        }
+
+        Ok(git)
+    }
+
+    async fn tool(&self, name: &str, args: &Value) -> Result<Value, Error> {
+        match name {
+            "summarize_repo" => {
+                let args = arguments::<SummarizeRepoArguments>(args)?;
+                let cache = self.cache(&args.repo)?;
+
+                if let (Ok(summaries), false) = (cache.load_summaries(), args.refresh) {
+                    return Ok(json!(summaries));
+                }
+
+                let summaries = self
+                    .git(&args.repo, args.refresh)
+                    .await?
+                    .summarize_repository()
+                    .await?;
+                cache.save_summaries(&summaries)?;
+
+                Ok(json!(summaries))
+            }
+
+            "summarize_folder" => {
+                let args = arguments::<SummarizeFolderArguments>(args)?;
+                let cache = self.cache(&args.repo)?;
+
+                let (folder_summary, file_summaries) = self
+                    .git(&args.repo, false)
+                    .await?
+                    .summarize_folder(&args.folder)
+                    .await?;
+
+                let mut summaries = cache.load_summaries_or_default();
+                summaries.insert_folder(&args.folder, folder_summary, file_summaries);
+                cache.save_summaries(&summaries)?;
+
+                Ok(json!(summaries.folders[&args.folder]))
+            }
+
+            "summarize_file" => {
+                let args = arguments::<SummarizeFileArguments>(args)?;
+                let folder = args
+                    .path
+                    .rsplit_once('/')
+                    .map(|(folder, _)| folder)
+                    .unwrap_or_default();
+
+                let summary = self
+                    .git(&args.repo, false)
+                    .await?
+                    .summarize_file(folder, &args.path)
+                    .await?;
+
+                Ok(json!(summary))
+            }
+
+            "search_repo" => {
+                let args = arguments::<SearchArguments>(args)?;
+                let cache = self.cache(&args.repo)?;
+                let provider = EmbeddingProvider::OpenAi {
+                    key: self.config.open_ai_key()?,
+                    model: String::from(DEFAULT_EMBEDDINGS_MODEL),
+                };
+
+                let contents = cache.load_contents()?;
+                let summaries = cache.load_summaries_or_default();
+
+                let index = match cache.load_index() {
+                    Ok(index) if index.is_current(provider.model(), &contents, &summaries) => index,
+                    _ => {
+                        let index = Index::build(&provider, &contents, &summaries).await?;
+                        cache.save_index(&index)?;
+
+                        index
+                    }
+                };
+
+                let results = index
+                    .search(&provider, &args.query, args.limit)
+                    .await?
+                    .into_iter()
+                    .map(|result| {
+                        json!({
+                            "path": result.chunk.path,
+                            "start_line": result.chunk.start_line,
+                            "end_line": result.chunk.end_line,
+                            "kind": result.chunk.kind,
+                            "score": result.score,
+                        })
+                    })
+                    .collect::<Vec<Value>>();
+
+                Ok(json!(results))
+            }
+
+            "get_cached_summary" => {
+                let args = arguments::<CachedSummaryArguments>(args)?;
+                let summaries = self.cache(&args.repo)?.load_summaries()?;
+
+                cached_summary(&summaries, args.path.as_deref())
+            }
+
+            _ => Err(eyre!("unknown tool {}", name)),
+        }
+    }
+
+    /// A markdown report for every cached repository.
+    fn resources(&self) -> Vec<Value> {
+        Cache::list(&self.config.cache_dir)
+            .into_iter()
+            .map(|repo| {
+                json!({
+                    "uri": format!(
+                        "{}{}/{}/{}/report",
+                        RESOURCE_SCHEME, repo.username, repo.repo, repo.branch
+                    ),
+                    "name": format!("{}/{} {} report", repo.username, repo.repo, repo.branch),
+                    "mimeType": "text/markdown",
+                })
+            })
+            .collect()
+    }
+
+    /// Renders the report named by a `gitsum://username/repo/branch/report`
+    /// uri, where the branch may contain slashes.
+    fn read_resource(&self, uri: &str) -> Result<String, Error> {
+        let (username, repo, branch) = uri
+            .strip_prefix(RESOURCE_SCHEME)
+            .and_then(|path| path.strip_suffix("/report"))
+            .and_then(|path| path.split_once('/'))
+            .and_then(|(username, path)| {
+                let (repo, branch) = path.split_once('/')?;
+
+                Some((username, repo, branch))
+            })
+            .ok_or_else(|| eyre!("unknown resource {}", uri))?;
+
+        let cache = self.cache(&RepoArguments {
+            username: username.to_string(),
+            repo: repo.to_string(),
+            branch: branch.to_string(),
+        })?;
+        let contents = cache.load_contents()?;
+        let summaries = cache.load_summaries()?;
+
+        Ok(Report::new(&format!("{}/{}", username, repo), &contents, &summaries).render())
+    }
+}
+
+/// Finds the summary of `path` in a tree: a folder, a file, or the whole
+/// tree if `path` is missing.
+fn cached_summary(summaries: &SummaryTree, path: Option<&str>) -> Result<Value, Error> {
+    let path = match path {
+        Some(path) => path.trim_matches('/'),
+        None => return Ok(json!(summaries)),
+    };
+
+    if let Some(folder) = summaries.folders.get(path) {
+        return Ok(json!(folder));
+    }
+
+    summaries
+        .folders
+        .values()
+        .flat_map(|folder| &folder.files)
+        .find(|file| file.name == path)
+        .map(|file| json!(file))
+        .ok_or_else(|| eyre!("{} has no cached summary", path))
+}
@@ -0,0 +1,129 @@
+use crate::gpt::{ChatChoice, ChatMessage, ChatRequest, ChatResponse, ChatUsage};
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+/// Answers a chat request without sending it, by filling in the JSON schema
+/// the prompt asks for. Answers are templated from a hash of the prompt, so
+/// the same request always gets the same answer.
+pub fn respond(request: &ChatRequest) -> ChatResponse {
+    let prompt = request
+        .messages
+        .last()
+        .map(|m| m.content.as_str())
+        .unwrap_or_default();
+
+    let tokens = prompt.split_whitespace().count() as i64;
+
+    let mut hasher = Sha256::new();
+    hasher.update(prompt.as_bytes());
+    let id = format!("{:x}", hasher.finalize())[..8].to_string();
+
+    // A repair prompt refers back to the schema given earlier.
+    let content = request
+        .messages
+        .iter()
+        .rev()
+        .find_map(|m| schema(&m.content))
+        .map(|schema| {
+            let listed = Listed::new(prompt);
+            fill(&schema, "", None, &listed, &id)
+        })
+        .unwrap_or_else(|| json!({ "summary": format!("Mock summary {}.", id) }));
+
+    ChatResponse {
+        id: Some(format!("mock-{}", id)),
+        object: String::from("chat.completion"),
+        created: 0,
+        choices: vec![ChatChoice {
+            index: 0,
+            message: ChatMessage::assistant(&content.to_string()),
+            finish_reason: String::from("stop"),
+        }],
+        usage: ChatUsage {
+            prompt_tokens: tokens,
+            total_tokens: tokens,
+            ..Default::default()
+        },
+    }
+}
+
+/// The JSON schema a prompt ends with, starting at the last line that opens
+/// an object.
+fn schema(prompt: &str) -> Option<Value> {
+    let start = prompt.rfind("\n{")? + 1;
+    let end = prompt.rfind('}')? + 1;
+
+    serde_json::from_str(prompt.get(start..end)?).ok()
+}
+
+/// What a prompt lists for the model to refer back to: names listed as
+/// `- name: description` and numbers listed as `- #number`.
+struct Listed {
+    names: Vec<String>,
+
+    numbers: Vec<u64>,
+}
+
+impl Listed {
+    fn new(prompt: &str) -> Listed {
+        // Lists often start on the line that introduces them.
+        let names = Regex::new(r"(?m)(?:^|: )- ([A-Za-z0-9_.@/\-]+): ").expect("pattern is valid");
+        let numbers = Regex::new(r"(?:^|\s)- #(\d+)").expect("pattern is valid");
+
+        Listed {
+            names: names
+                .captures_iter(prompt)
+                .map(|captures| captures[1].to_string())
+                .collect(),
+            numbers: numbers
+                .captures_iter(prompt)
+                .filter_map(|captures| captures[1].parse().ok())
+                .collect(),
+        }
+    }
+}
+
+/// Fills in the value of `key` described by `schema`. Objects with a `name`
+/// are given one of the listed names, and describe it. Arrays of them hold
+/// one object per listed name, arrays of integers hold the listed numbers,
+/// and other arrays a single value.
+fn fill(schema: &Value, key: &str, name: Option<&str>, listed: &Listed, id: &str) -> Value {
+    let key = key.replace('_', " ");
+
+    match schema {
+        Value::Object(fields) => {
+            let mut object = Map::new();
+            for (field, value) in fields {
+                let value = match (field.as_str(), name) {
+                    ("name", Some(name)) => json!(name),
+                    _ => fill(value, field, name, listed, id),
+                };
+                object.insert(field.clone(), value);
+            }
+
+            Value::Object(object)
+        }
+        Value::Array(items) => match items.first() {
+            Some(Value::Object(fields))
+                if fields.contains_key("name") && !listed.names.is_empty() =>
+            {
+                listed
+                    .names
+                    .iter()
+                    .map(|name| fill(&items[0], &key, Some(name), listed, id))
+                    .collect()
+            }
+            Some(Value::Number(number)) if number.is_u64() => json!(listed.numbers),
+            Some(Value::String(_)) if name.is_some() => json!([name]),
+            Some(item) => json!([fill(item, &key, name, listed, id)]),
+            None => json!([]),
+        },
+        Value::String(_) => match name {
+            Some(name) => json!(format!("Mock {} of {}.", key, name)),
+            None => json!(format!("Mock {} {}.", key, id)),
+        },
+        Value::Number(number) if number.is_f64() => json!(1.0),
+        value => value.clone(),
+    }
+}
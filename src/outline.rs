@@ -0,0 +1,385 @@
+use crate::languages::language;
+use tree_sitter::{Language, Node, Parser};
+
+/// Declarations longer than this are cut off in outlines.
+const MAX_DECLARATION_LINES: usize = 12;
+
+const INDENT: &str = "    ";
+
+/// The node kinds of a tree-sitter grammar that make up an outline.
+struct Grammar {
+    language: Language,
+
+    /// Comments, and attributes, kept when they precede an outlined item or
+    /// open the file.
+    comments: &'static [&'static str],
+
+    /// Imports and other declarations, kept in full up to `MAX_DECLARATION_LINES`.
+    declarations: &'static [&'static str],
+
+    /// Functions, kept as their signature.
+    functions: &'static [&'static str],
+
+    /// Types whose bodies are outlined in turn, such as classes and impls.
+    containers: &'static [&'static str],
+
+    /// Nodes wrapping another item, such as decorators, and the field holding it.
+    wrappers: &'static [(&'static str, &'static str)],
+
+    /// Whether bodies are delimited by braces rather than indentation.
+    braces: bool,
+}
+
+fn grammar(path: &str) -> Option<Grammar> {
+    let grammar = match language(path) {
+        "Rust" => Grammar {
+            language: tree_sitter_rust::LANGUAGE.into(),
+            comments: &["line_comment", "block_comment", "attribute_item"],
+            declarations: &[
+                "use_declaration",
+                "extern_crate_declaration",
+                "struct_item",
+                "enum_item",
+                "union_item",
+                "type_item",
+                "const_item",
+                "static_item",
+                "function_signature_item",
+                "macro_definition",
+            ],
+            functions: &["function_item"],
+            containers: &["impl_item", "trait_item", "mod_item"],
+            wrappers: &[],
+            braces: true,
+        },
+        "Python" => Grammar {
+            language: tree_sitter_python::LANGUAGE.into(),
+            comments: &["comment"],
+            declarations: &[
+                "import_statement",
+                "import_from_statement",
+                "future_import_statement",
+            ],
+            functions: &["function_definition"],
+            containers: &["class_definition"],
+            wrappers: &[("decorated_definition", "definition")],
+            braces: false,
+        },
+        "JavaScript" => Grammar {
+            language: tree_sitter_javascript::LANGUAGE.into(),
+            comments: &["comment"],
+            declarations: &[
+                "import_statement",
+                "lexical_declaration",
+                "variable_declaration",
+            ],
+            functions: &[
+                "function_declaration",
+                "generator_function_declaration",
+                "method_definition",
+            ],
+            containers: &["class_declaration"],
+            wrappers: &[("export_statement", "declaration")],
+            braces: true,
+        },
+        "TypeScript" => Grammar {
+            language: if path.ends_with(".tsx") {
+                tree_sitter_typescript::LANGUAGE_TSX.into()
+            } else {
+                tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into()
+            },
+            comments: &["comment"],
+            declarations: &[
+                "import_statement",
+                "lexical_declaration",
+                "variable_declaration",
+                "interface_declaration",
+                "type_alias_declaration",
+                "enum_declaration",
+                "function_signature",
+                "method_signature",
+                "abstract_method_signature",
+                "public_field_definition",
+            ],
+            functions: &[
+                "function_declaration",
+                "generator_function_declaration",
+                "method_definition",
+            ],
+            containers: &["class_declaration", "abstract_class_declaration"],
+            wrappers: &[("export_statement", "declaration")],
+            braces: true,
+        },
+        "Go" => Grammar {
+            language: tree_sitter_go::LANGUAGE.into(),
+            comments: &["comment"],
+            declarations: &[
+                "package_clause",
+                "import_declaration",
+                "type_declaration",
+                "const_declaration",
+                "var_declaration",
+            ],
+            functions: &["function_declaration", "method_declaration"],
+            containers: &[],
+            wrappers: &[],
+            braces: true,
+        },
+        "Java" => Grammar {
+            language: tree_sitter_java::LANGUAGE.into(),
+            comments: &["line_comment", "block_comment"],
+            declarations: &[
+                "package_declaration",
+                "import_declaration",
+                "field_declaration",
+                "constant_declaration",
+            ],
+            functions: &["method_declaration", "constructor_declaration"],
+            containers: &[
+                "class_declaration",
+                "interface_declaration",
+                "enum_declaration",
+                "record_declaration",
+            ],
+            wrappers: &[],
+            braces: true,
+        },
+        _ => return None,
+    };
+
+    Some(grammar)
+}
+
+/// The last row a node covers, not counting a trailing newline.
+fn last_row(node: &Node) -> usize {
+    let end = node.end_position();
+
+    if end.column == 0 && end.row > node.start_position().row {
+        end.row - 1
+    } else {
+        end.row
+    }
+}
+
+/// Returns the docstring opening a Python module or body, if there is one.
+fn docstring<'a>(node: &Node<'a>) -> Option<Node<'a>> {
+    let first = node.named_child(0)?;
+    let string = first.named_child(0)?;
+
+    (first.kind() == "expression_statement" && string.kind() == "string").then_some(string)
+}
+
+struct Outliner<'a> {
+    grammar: Grammar,
+
+    source: &'a [u8],
+
+    lines: Vec<String>,
+}
+
+impl<'a> Outliner<'a> {
+    /// Returns the source between two bytes, starting with the indentation
+    /// of the first line so that `push` can dedent it.
+    fn text(&self, start: usize, end: usize) -> &'a str {
+        let start = self.source[..start]
+            .iter()
+            .rposition(|b| !matches!(b, b' ' | b'\t'))
+            .map(|i| i + 1)
+            .unwrap_or_default();
+
+        std::str::from_utf8(&self.source[start..end]).unwrap_or_default()
+    }
+
+    /// Adds `text` at `depth`, dropping blank lines and its common
+    /// indentation, and cutting it off after `max_lines`.
+    fn push(&mut self, depth: usize, text: &str, max_lines: usize) {
+        let lines = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<&str>>();
+        let indent = lines
+            .iter()
+            .map(|line| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or_default();
+
+        for line in lines.iter().take(max_lines) {
+            self.lines.push(format!(
+                "{}{}",
+                INDENT.repeat(depth),
+                line.get(indent..).unwrap_or(line).trim_end()
+            ));
+        }
+
+        if lines.len() > max_lines {
+            self.lines.push(format!("{}...", INDENT.repeat(depth + 1)));
+        }
+    }
+
+    /// Outlines the named children of `node`. Comments are held back until
+    /// the next item, and dropped if it isn't outlined or is further down.
+    /// Comments and docstrings opening the node are always kept.
+    fn outline_children(&mut self, node: &Node, depth: usize) {
+        let mut cursor = node.walk();
+        let children = node.named_children(&mut cursor).collect::<Vec<Node>>();
+
+        let mut leading = true;
+        let mut comments: Vec<Node> = vec![];
+        for child in children {
+            if self.grammar.comments.contains(&child.kind()) {
+                if leading {
+                    self.push(
+                        depth,
+                        self.text(child.start_byte(), child.end_byte()),
+                        usize::MAX,
+                    );
+                    continue;
+                }
+
+                if comments
+                    .last()
+                    .is_some_and(|last| child.start_position().row > last_row(last) + 1)
+                {
+                    comments.clear();
+                }
+                comments.push(child);
+                continue;
+            }
+
+            if leading && docstring(node).is_some_and(|d| d.parent() == Some(child)) {
+                self.push(
+                    depth,
+                    self.text(child.start_byte(), child.end_byte()),
+                    usize::MAX,
+                );
+                leading = false;
+                continue;
+            }
+            leading = false;
+
+            let attached = comments
+                .last()
+                .is_some_and(|last| child.start_position().row <= last_row(last) + 1);
+            if attached && self.is_outlined(&child) {
+                for comment in &comments {
+                    self.push(
+                        depth,
+                        self.text(comment.start_byte(), comment.end_byte()),
+                        usize::MAX,
+                    );
+                }
+            }
+            comments.clear();
+
+            self.outline_node(&child, &child, depth);
+        }
+    }
+
+    fn is_outlined(&self, node: &Node) -> bool {
+        let kind = node.kind();
+
+        self.grammar.declarations.contains(&kind)
+            || self.grammar.functions.contains(&kind)
+            || self.grammar.containers.contains(&kind)
+            || self
+                .grammar
+                .wrappers
+                .iter()
+                .any(|(wrapper, _)| *wrapper == kind)
+    }
+
+    /// Outlines `item`, starting its text at `outer`, which is the item
+    /// itself or a wrapper such as a decorator or export around it.
+    fn outline_node(&mut self, outer: &Node, item: &Node, depth: usize) {
+        let kind = item.kind();
+
+        if let Some((_, field)) = self.grammar.wrappers.iter().find(|(w, _)| *w == kind) {
+            match item.child_by_field_name(field) {
+                Some(inner) => self.outline_node(outer, &inner, depth),
+                None => self.push(
+                    depth,
+                    self.text(outer.start_byte(), outer.end_byte()),
+                    MAX_DECLARATION_LINES,
+                ),
+            }
+            return;
+        }
+
+        let is_function = self.grammar.functions.contains(&kind);
+        let is_container = self.grammar.containers.contains(&kind);
+
+        if !is_function && !is_container {
+            if self.grammar.declarations.contains(&kind) {
+                self.push(
+                    depth,
+                    self.text(outer.start_byte(), outer.end_byte()),
+                    MAX_DECLARATION_LINES,
+                );
+            }
+            return;
+        }
+
+        let body = match item.child_by_field_name("body") {
+            Some(body) => body,
+            None => {
+                self.push(
+                    depth,
+                    self.text(outer.start_byte(), outer.end_byte()),
+                    MAX_DECLARATION_LINES,
+                );
+                return;
+            }
+        };
+
+        let signature = self.text(outer.start_byte(), body.start_byte()).trim_end();
+
+        if self.grammar.braces {
+            if is_container {
+                self.push(depth, &format!("{} {{", signature), usize::MAX);
+                self.outline_children(&body, depth + 1);
+                self.push(depth, "}", usize::MAX);
+            } else {
+                self.push(depth, &format!("{} {{ ... }}", signature), usize::MAX);
+            }
+        } else if is_container {
+            self.push(depth, signature, usize::MAX);
+            self.outline_children(&body, depth + 1);
+        } else {
+            match docstring(&body) {
+                Some(doc) => {
+                    self.push(depth, signature, usize::MAX);
+                    self.push(
+                        depth + 1,
+                        self.text(doc.start_byte(), doc.end_byte()),
+                        MAX_DECLARATION_LINES,
+                    );
+                }
+                None => self.push(depth, &format!("{} ...", signature), usize::MAX),
+            }
+        }
+    }
+}
+
+/// Outlines a source file: its module docs and imports, the signatures of
+/// its types and functions, and the doc comments on them. Returns `None` if
+/// the language of `path` isn't supported or the file can't be parsed.
+pub fn outline(path: &str, content: &str) -> Option<String> {
+    let grammar = grammar(path)?;
+
+    let mut parser = Parser::new();
+    parser.set_language(&grammar.language).ok()?;
+    let tree = parser.parse(content, None)?;
+
+    let mut outliner = Outliner {
+        grammar,
+        source: content.as_bytes(),
+        lines: vec![],
+    };
+    outliner.outline_children(&tree.root_node(), 0);
+
+    if outliner.lines.is_empty() {
+        return None;
+    }
+
+    Some(outliner.lines.join("\n"))
+}
@@ -0,0 +1,121 @@
+use crate::gpt;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How far a summarization has got, shared between the `Git` doing it and
+/// whatever reports on it.
+#[derive(Debug, Default)]
+pub struct Progress {
+    files_total: AtomicUsize,
+
+    files_done: AtomicUsize,
+
+    bars: Option<Bars>,
+}
+
+/// Progress bars on stderr for fetching and summarizing. Each is only
+/// shown once its phase starts.
+#[derive(Debug)]
+struct Bars {
+    multi: MultiProgress,
+
+    fetch: ProgressBar,
+
+    summarize: ProgressBar,
+}
+
+/// A snapshot of `Progress`.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ProgressView {
+    pub files_done: usize,
+
+    pub files_total: usize,
+}
+
+fn bar(prefix: &'static str) -> ProgressBar {
+    let style = ProgressStyle::with_template(
+        "{prefix:>11.green.bold} [{bar:30}] {pos}/{len} files {msg} (ETA {eta})",
+    )
+    .expect("template is valid")
+    .progress_chars("=> ");
+
+    ProgressBar::hidden().with_style(style).with_prefix(prefix)
+}
+
+impl Progress {
+    /// Progress that is drawn as bars on stderr, when it is a terminal.
+    pub fn with_bars() -> Progress {
+        Progress {
+            bars: Some(Bars {
+                multi: MultiProgress::new(),
+                fetch: bar("Fetching"),
+                summarize: bar("Summarizing"),
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Starts fetching `files` files.
+    pub fn start_fetch(&self, files: usize) {
+        if let Some(bars) = &self.bars {
+            bars.fetch.set_length(files as u64);
+            bars.multi.add(bars.fetch.clone());
+        }
+    }
+
+    pub fn file_fetched(&self) {
+        if let Some(bars) = &self.bars {
+            bars.fetch.inc(1);
+        }
+    }
+
+    pub fn finish_fetch(&self) {
+        if let Some(bars) = &self.bars {
+            bars.fetch.finish();
+        }
+    }
+
+    pub fn add_files(&self, files: usize) {
+        let total = self.files_total.fetch_add(files, Ordering::Relaxed) + files;
+
+        if let Some(bars) = &self.bars {
+            bars.summarize.set_length(total as u64);
+            bars.multi.add(bars.summarize.clone());
+        }
+    }
+
+    /// Counts a file as done, whether it was summarized or skipped.
+    pub fn file_done(&self) {
+        self.files_done.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(bars) = &self.bars {
+            bars.summarize
+                .set_message(format!("{} tokens", gpt::tokens_used()));
+            bars.summarize.inc(1);
+        }
+    }
+
+    /// Clears the bars once summarizing is over.
+    pub fn finish(&self) {
+        if let Some(bars) = &self.bars {
+            bars.summarize.finish();
+            let _ = bars.multi.clear();
+        }
+    }
+
+    /// Prints a line above the bars, so they aren't drawn over.
+    pub fn println(&self, line: &str) {
+        match &self.bars {
+            Some(bars) => bars.multi.suspend(|| println!("{}", line)),
+            None => println!("{}", line),
+        }
+    }
+
+    pub fn view(&self) -> ProgressView {
+        ProgressView {
+            files_done: self.files_done.load(Ordering::Relaxed),
+            files_total: self.files_total.load(Ordering::Relaxed),
+        }
+    }
+}
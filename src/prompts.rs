@@ -1,11 +1,83 @@
-use crate::gpt::ChatRequestBuilder;
+use crate::gpt::{ChatMessage, ChatRequestBuilder, ChatSettings};
+use clap::ValueEnum;
 use eyre::{eyre, Error, Result};
 use reqwest::Client;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::from_str;
 
+/// How many times a response that fails to parse is sent back to the model,
+/// along with the parse error, before giving up.
+pub const MAX_REPAIR_ATTEMPTS: usize = 2;
+
+/// How much of an unparsable reply is kept in the error.
+const MAX_REPLY_EXCERPT: usize = 200;
+
+pub const REPAIR_PROMPT: &str = "Your answer could not be parsed: {{error}}. 
+Reply again with only the JSON object, using the schema you were given and no other text.";
+
+/// Returns the JSON object in a response, dropping markdown code fences and
+/// any prose around it.
+pub fn extract_json(content: &str) -> &str {
+    let content = content.trim();
+
+    match (content.find('{'), content.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &content[start..=end],
+        _ => content,
+    }
+}
+
+/// Sends `messages` in JSON mode, if the model supports it, and parses the
+/// answer into `T`. Answers that fail to parse are sent back with the error
+/// up to `MAX_REPAIR_ATTEMPTS` times.
+async fn send_json<T: DeserializeOwned>(
+    key: &str,
+    settings: &ChatSettings,
+    mut messages: Vec<ChatMessage>,
+) -> Result<T, Error> {
+    let client = Client::new();
+    let mut attempt = 0;
+
+    loop {
+        let res = ChatRequestBuilder::default()
+            .conversation(messages.clone())
+            .settings(settings)
+            .json_mode(true)
+            .build()
+            .send(key, client.clone())
+            .await?;
+
+        let content = res
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content)
+            .ok_or_else(|| eyre!("gpt returned no choices"))?;
+
+        let error = match from_str::<T>(extract_json(&content)) {
+            Ok(parsed) => return Ok(parsed),
+            Err(e) => e,
+        };
+
+        if attempt == MAX_REPAIR_ATTEMPTS {
+            return Err(eyre!(
+                "failed to parse gpt response after {} attempts: {}, in: {}",
+                attempt + 1,
+                error,
+                content.chars().take(MAX_REPLY_EXCERPT).collect::<String>()
+            ));
+        }
+
+        attempt += 1;
+        messages.push(ChatMessage::assistant(&content));
+        messages.push(ChatMessage::user(
+            &REPAIR_PROMPT.replace("{{error}}", &error.to_string()),
+        ));
+    }
+}
+
 pub const REPOSITORY_SUMMARY_PROMPT: &str =
-    "Thoroughly summarize this github repository given summaries of its folders: {{summaries}}. 
+    "Thoroughly summarize this github repository given summaries of its folders: {{summaries}}. {{length}}
 Make sure to consider every file in the repository. Return a JSON object for your answer. 
 Make sure your entire answer is in the JSON object! Use the below schema for your answer.
 {
@@ -18,35 +90,18 @@ pub struct RepositorySummaryPrompt {
 }
 
 impl RepositorySummaryPrompt {
-    pub fn new(contents: &str) -> RepositorySummaryPrompt {
-        RepositorySummaryPrompt {
-            prompt: REPOSITORY_SUMMARY_PROMPT.replace("{{summaries}}", contents),
-        }
+    /// Creates the prompt from a template rendered by `Templates`.
+    pub fn new(prompt: String) -> RepositorySummaryPrompt {
+        RepositorySummaryPrompt { prompt }
     }
 
-    pub async fn send(&self, key: &str) -> Result<RepositorySummaryResponse, Error> {
-        let res = ChatRequestBuilder::default()
-            .messages(self.prompt.clone())
-            .temperature(0.7)
-            .max_tokens(2000)
-            .top_p(1.0)
-            .presence_penalty(0.0)
-            .frequency_penalty(0.0)
-            .build()
-            .send(&key, Client::new())
-            .await?;
-
-        let summary =
-            from_str::<RepositorySummaryResponse>(&res.choices[0].message.content.clone());
-
-        match summary {
-            Ok(summary) => Ok(summary),
-            Err(e) => Err(eyre!(
-                "failed to sent gpt request: {}: {}",
-                &res.choices[0].message.content.clone(),
-                e
-            )),
-        }
+    pub async fn send(
+        &self,
+        key: &str,
+        settings: &ChatSettings,
+    ) -> Result<RepositorySummaryResponse, Error> {
+        send_json::<RepositorySummaryResponse>(key, settings, vec![ChatMessage::user(&self.prompt)])
+            .await
     }
 }
 
@@ -64,7 +119,7 @@ pub struct RepositorySummaryResponse {
 }
 
 pub const FOLDER_WIDE_SUMMARY_PROMPT: &str =
-    "Thoroughly summarize this folder given summaries of the files inside it: {{files}}. 
+    "Thoroughly summarize this folder given summaries of the files inside it: {{files}}. {{length}}
 Make sure to consider every file in the repository. Return a JSON object for your answer. 
 Make sure your entire answer is in the JSON object! Use the below schema for your answer.
 {
@@ -77,35 +132,18 @@ pub struct FolderWideSummaryPrompt {
 }
 
 impl FolderWideSummaryPrompt {
-    pub fn new(contents: &str) -> FolderWideSummaryPrompt {
-        FolderWideSummaryPrompt {
-            prompt: FOLDER_WIDE_SUMMARY_PROMPT.replace("{{files}}", contents),
-        }
+    /// Creates the prompt from a template rendered by `Templates`.
+    pub fn new(prompt: String) -> FolderWideSummaryPrompt {
+        FolderWideSummaryPrompt { prompt }
     }
 
-    pub async fn send(&self, key: &str) -> Result<FolderWideSummaryResponse, Error> {
-        let res = ChatRequestBuilder::default()
-            .messages(self.prompt.clone())
-            .temperature(0.7)
-            .max_tokens(2000)
-            .top_p(1.0)
-            .presence_penalty(0.0)
-            .frequency_penalty(0.0)
-            .build()
-            .send(&key, Client::new())
-            .await?;
-
-        let summary =
-            from_str::<FolderWideSummaryResponse>(&res.choices[0].message.content.clone());
-
-        match summary {
-            Ok(summary) => Ok(summary),
-            Err(e) => Err(eyre!(
-                "failed to sent gpt request: {}: {}",
-                &res.choices[0].message.content.clone(),
-                e
-            )),
-        }
+    pub async fn send(
+        &self,
+        key: &str,
+        settings: &ChatSettings,
+    ) -> Result<FolderWideSummaryResponse, Error> {
+        send_json::<FolderWideSummaryResponse>(key, settings, vec![ChatMessage::user(&self.prompt)])
+            .await
     }
 }
 
@@ -117,18 +155,37 @@ impl Default for FolderWideSummaryPrompt {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FolderWideSummaryResponse {
     pub summary: String,
 }
 
-pub const FILE_SUMMARY_PROMPT: &str =
-    "Thoroughly summarize this code file given the contents: {{file}}. 
-Make sure to consider every file in the repository. Return a JSON object for your answer. 
+/// Appends what every file prompt asks for besides the summary, and the
+/// schema of `FileSummaryResponse`, to the instructions of a style.
+macro_rules! file_prompt {
+    ($instructions:literal) => {
+        concat!(
+            $instructions,
+            "Besides the summary, give its purpose in one sentence, its key types and functions with a one line description each, the external libraries and services it depends on, its side effects such as file, network or environment access, any TODOs or notable risks, and your confidence in the summary from 0 to 1. 
+Return a JSON object for your answer. 
 Make sure your entire answer is in the JSON object! Use the below schema for your answer.
 {
-    \"summary\": \"\"
-}";
+    \"summary\": \"\",
+    \"purpose\": \"\",
+    \"symbols\": [{\"name\": \"\", \"description\": \"\"}],
+    \"dependencies\": [\"\"],
+    \"side_effects\": [\"\"],
+    \"risks\": [\"\"],
+    \"confidence\": 0.0
+}"
+        )
+    };
+}
+
+pub const FILE_SUMMARY_PROMPT: &str = file_prompt!(
+    "Thoroughly summarize this code file given the contents: {{file}}. {{length}}
+"
+);
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileSummaryPrompt {
@@ -136,46 +193,619 @@ pub struct FileSummaryPrompt {
 }
 
 impl FileSummaryPrompt {
-    pub fn new(contents: &str) -> FileSummaryPrompt {
+    /// Creates the prompt from a template rendered by `Templates`.
+    pub fn new(prompt: String) -> FileSummaryPrompt {
+        FileSummaryPrompt { prompt }
+    }
+
+    pub async fn send(
+        &self,
+        key: &str,
+        settings: &ChatSettings,
+    ) -> Result<FileSummaryResponse, Error> {
+        send_json::<FileSummaryResponse>(key, settings, vec![ChatMessage::user(&self.prompt)]).await
+    }
+}
+
+impl Default for FileSummaryPrompt {
+    fn default() -> Self {
         FileSummaryPrompt {
-            prompt: FILE_SUMMARY_PROMPT.replace("{{file}}", contents),
+            prompt: FILE_SUMMARY_PROMPT.to_string(),
         }
     }
+}
 
-    pub async fn send(&self, key: &str) -> Result<FileSummaryResponse, Error> {
-        let res = ChatRequestBuilder::default()
-            .messages(self.prompt.clone())
-            .temperature(0.7)
-            .max_tokens(2000)
-            .top_p(1.0)
-            .presence_penalty(0.0)
-            .frequency_penalty(0.0)
-            .build()
-            .send(&key, Client::new())
-            .await?;
+/// A type, function or other item defined by a file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+
+    /// What the item does, in one line.
+    #[serde(default)]
+    pub description: String,
+}
+
+/// The summary of a file. Every field but `summary` is optional, so older
+/// cached summaries and terse answers still parse.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileSummaryResponse {
+    /// The path of the summarized file, filled in after the response is parsed.
+    #[serde(default)]
+    pub name: String,
 
-        let summary = from_str::<FileSummaryResponse>(&res.choices[0].message.content.clone());
+    pub summary: String,
+
+    /// What the file is for, in one sentence.
+    #[serde(default)]
+    pub purpose: String,
+
+    /// The key types and functions it defines.
+    #[serde(default)]
+    pub symbols: Vec<Symbol>,
+
+    /// The external libraries and services it uses.
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+
+    /// File, network and environment access, and other side effects.
+    #[serde(default)]
+    pub side_effects: Vec<String>,
+
+    /// TODOs and notable risks.
+    #[serde(default)]
+    pub risks: Vec<String>,
+
+    /// How confident the model is in the summary, from 0 to 1.
+    #[serde(default)]
+    pub confidence: Option<f64>,
+}
 
-        match summary {
-            Ok(summary) => Ok(summary),
-            Err(e) => Err(eyre!(
-                "failed to sent gpt request: {}: {}",
-                &res.choices[0].message.content.clone(),
-                e
-            )),
+impl FileSummaryResponse {
+    /// Describes the summary as labelled lines, skipping empty fields. This
+    /// is what folder prompts receive for each of their files.
+    pub fn describe(&self) -> String {
+        let mut out = format!("### {}\n", self.name);
+
+        if !self.purpose.is_empty() {
+            out.push_str(&format!("purpose: {}\n", self.purpose));
+        }
+
+        out.push_str(&format!("summary: {}\n", self.summary));
+
+        if !self.symbols.is_empty() {
+            let symbols = self
+                .symbols
+                .iter()
+                .map(|s| format!("{} ({})", s.name, s.description))
+                .collect::<Vec<String>>();
+            out.push_str(&format!("symbols: {}\n", symbols.join("; ")));
         }
+
+        for (label, values) in [
+            ("dependencies", &self.dependencies),
+            ("side effects", &self.side_effects),
+            ("risks", &self.risks),
+        ] {
+            if !values.is_empty() {
+                out.push_str(&format!("{}: {}\n", label, values.join("; ")));
+            }
+        }
+
+        out
     }
 }
 
-impl Default for FileSummaryPrompt {
+pub const ONBOARDING_FILE_PROMPT: &str = file_prompt!(
+    "Explain this code file to a developer who just joined the team: {{file}}. {{length}}
+Describe what it is responsible for, the concepts a newcomer needs to understand it and how it fits into the rest of the repository. 
+Avoid jargon that isn't explained.
+"
+);
+
+pub const ONBOARDING_FOLDER_PROMPT: &str =
+    "Explain this folder to a developer who just joined the team, given summaries of the files 
+inside it: {{files}}. {{length}}
+Describe what the folder is for, where a newcomer should start reading and how its files 
+work together. Return a JSON object for your answer. 
+Make sure your entire answer is in the JSON object! Use the below schema for your answer.
+{
+    \"summary\": \"\"
+}";
+
+pub const ONBOARDING_REPOSITORY_PROMPT: &str =
+    "Explain this github repository to a developer who just joined the team, given summaries of 
+its folders: {{summaries}}. {{length}}
+Describe what the project does, how it is organized and which folders to read first. Return a JSON object for your answer. 
+Make sure your entire answer is in the JSON object! Use the below schema for your answer.
+{
+    \"summary\": \"\"
+}";
+
+pub const EXECUTIVE_FILE_PROMPT: &str = file_prompt!(
+    "Summarize this code file for a non-technical executive in at most three sentences: {{file}}. {{length}}
+Focus on the capability it provides and why it matters to the product, not on how it works.
+"
+);
+
+pub const EXECUTIVE_FOLDER_PROMPT: &str =
+    "Summarize this folder for a non-technical executive in at most three sentences, given 
+summaries of the files inside it: {{files}}. {{length}}
+Focus on the capability it provides and why it matters to the product, not on how it works. Return a JSON object for your answer. 
+Make sure your entire answer is in the JSON object! Use the below schema for your answer.
+{
+    \"summary\": \"\"
+}";
+
+pub const EXECUTIVE_REPOSITORY_PROMPT: &str =
+    "Summarize this github repository for a non-technical executive in three sentences, given 
+summaries of its folders: {{summaries}}. {{length}}
+Focus on the problem it solves, who it is for and the business value it provides. Return a JSON object for your answer. 
+Make sure your entire answer is in the JSON object! Use the below schema for your answer.
+{
+    \"summary\": \"\"
+}";
+
+pub const MAINTAINER_FILE_PROMPT: &str = file_prompt!(
+    "Summarize this code file for its maintainers: {{file}}. {{length}}
+Describe the invariants it relies on or upholds, its extension points and any tech debt, such as TODOs, duplicated logic, missing error handling or fragile assumptions.
+"
+);
+
+pub const MAINTAINER_FOLDER_PROMPT: &str =
+    "Summarize this folder for its maintainers, given summaries of the files inside it: {{files}}. 
+{{length}}
+Describe the invariants shared by its files, where new functionality should be added and the 
+tech debt that needs attention. Return a JSON object for your answer. 
+Make sure your entire answer is in the JSON object! Use the below schema for your answer.
+{
+    \"summary\": \"\"
+}";
+
+pub const MAINTAINER_REPOSITORY_PROMPT: &str =
+    "Summarize this github repository for its maintainers, given summaries of its folders: 
+{{summaries}}. {{length}}
+Describe its architectural invariants, its extension points and the tech debt that most 
+needs attention. Return a JSON object for your answer. 
+Make sure your entire answer is in the JSON object! Use the below schema for your answer.
+{
+    \"summary\": \"\"
+}";
+
+pub const API_FILE_PROMPT: &str = file_prompt!(
+    "Summarize the public API of this code file: {{file}}. {{length}}
+Only describe the types, functions, endpoints and options it exposes to callers, with their parameters and return values. 
+Ignore private helpers and implementation details.
+"
+);
+
+pub const API_FOLDER_PROMPT: &str =
+    "Summarize the public API of this folder, given summaries of the files inside it: {{files}}. 
+{{length}}
+Only describe what it exposes to callers and ignore implementation details. Return a JSON object for your answer. 
+Make sure your entire answer is in the JSON object! Use the below schema for your answer.
+{
+    \"summary\": \"\"
+}";
+
+pub const API_REPOSITORY_PROMPT: &str =
+    "Summarize the public API of this github repository, given summaries of its folders: 
+{{summaries}}. {{length}}
+Only describe what it exposes to its users, such as libraries, commands, endpoints and 
+configuration, and ignore implementation details. Return a JSON object for your answer. 
+Make sure your entire answer is in the JSON object! Use the below schema for your answer.
+{
+    \"summary\": \"\"
+}";
+
+/// Who summaries are written for, which selects the prompts used at every level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Style {
+    /// A thorough description of what the code does
+    #[default]
+    Thorough,
+
+    /// Explains the code to a new hire
+    Onboarding,
+
+    /// At most three sentences on the business value
+    Executive,
+
+    /// Invariants, extension points and tech debt
+    Maintainer,
+
+    /// The public surface only
+    Api,
+}
+
+impl Style {
+    /// Returns the file, folder and repository prompts of this style.
+    pub fn prompts(&self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Style::Thorough => (
+                FILE_SUMMARY_PROMPT,
+                FOLDER_WIDE_SUMMARY_PROMPT,
+                REPOSITORY_SUMMARY_PROMPT,
+            ),
+            Style::Onboarding => (
+                ONBOARDING_FILE_PROMPT,
+                ONBOARDING_FOLDER_PROMPT,
+                ONBOARDING_REPOSITORY_PROMPT,
+            ),
+            Style::Executive => (
+                EXECUTIVE_FILE_PROMPT,
+                EXECUTIVE_FOLDER_PROMPT,
+                EXECUTIVE_REPOSITORY_PROMPT,
+            ),
+            Style::Maintainer => (
+                MAINTAINER_FILE_PROMPT,
+                MAINTAINER_FOLDER_PROMPT,
+                MAINTAINER_REPOSITORY_PROMPT,
+            ),
+            Style::Api => (API_FILE_PROMPT, API_FOLDER_PROMPT, API_REPOSITORY_PROMPT),
+        }
+    }
+}
+
+/// How long summaries should be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Length {
+    /// One or two sentences
+    Short,
+
+    /// A paragraph
+    Medium,
+
+    /// Several detailed paragraphs
+    Long,
+}
+
+impl Length {
+    /// The sentence added to prompts, passed to templates as `length`.
+    pub fn instruction(&self) -> &'static str {
+        match self {
+            Length::Short => "Keep the summary to one or two sentences.",
+            Length::Medium => "Keep the summary to a single paragraph.",
+            Length::Long => "Write a detailed summary of several paragraphs.",
+        }
+    }
+}
+
+pub const ASK_SYSTEM_PROMPT: &str =
+    "You answer questions about a github repository. Only use the summaries and code snippets 
+you are given as context, and say so if they do not contain the answer. Always cite the 
+paths of the files and folders you used.";
+
+pub const ASK_PROMPT: &str = "Answer this question about the repository: {{question}}
+Use the following summaries and code snippets as context: {{context}}
+Return a JSON object for your answer. Make sure your entire answer is in the JSON object! 
+List every file or folder path you used in \"sources\". Use the below schema for your answer.
+{
+    \"answer\": \"\",
+    \"sources\": [\"\"]
+}";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AskPrompt {
+    pub prompt: String,
+}
+
+impl AskPrompt {
+    pub fn new(question: &str, context: &str) -> AskPrompt {
+        AskPrompt {
+            prompt: ASK_PROMPT
+                .replace("{{question}}", question)
+                .replace("{{context}}", context),
+        }
+    }
+
+    /// Sends the prompt after the previous turns of the conversation, so
+    /// follow-up questions can refer to earlier answers.
+    pub async fn send(
+        &self,
+        key: &str,
+        settings: &ChatSettings,
+        history: &[ChatMessage],
+    ) -> Result<AskResponse, Error> {
+        let mut messages = vec![ChatMessage::system(ASK_SYSTEM_PROMPT)];
+        messages.extend(history.iter().cloned());
+        messages.push(ChatMessage::user(&self.prompt));
+
+        send_json::<AskResponse>(key, settings, messages).await
+    }
+}
+
+impl Default for AskPrompt {
     fn default() -> Self {
-        FileSummaryPrompt {
-            prompt: FILE_SUMMARY_PROMPT.to_string(),
+        AskPrompt {
+            prompt: ASK_PROMPT.to_string(),
         }
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct FileSummaryResponse {
+pub struct AskResponse {
+    pub answer: String,
+
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+pub const README_PROMPT: &str =
+    "Write a complete README.md in markdown for the github repository {{repository}}.
+Here is a summary of the repository: {{summary}}
+Here are its folders and files with their summaries: {{layout}}
+Here are its entry points and manifests: {{entry_points}}
+The README must have these sections: a description of the project's purpose, installation, 
+usage inferred from the command line interface and entry points, the project layout, and the 
+key modules. Only describe what the summaries and files support. Return a JSON object for 
+your answer. Make sure your entire answer is in the JSON object! Use the below schema for your answer.
+{
+    \"content\": \"\"
+}";
+
+pub const ARCHITECTURE_PROMPT: &str =
+    "Write an ARCHITECTURE.md in markdown for the github repository {{repository}}.
+Here is a summary of the repository: {{summary}}
+Here are its folders and files with their summaries: {{layout}}
+Here are its entry points and manifests: {{entry_points}}
+Give a high level overview of how the code fits together, then a module map listing every 
+folder and key file with its responsibility and what it depends on. Return a JSON object for 
+your answer. Make sure your entire answer is in the JSON object! Use the below schema for your answer.
+{
+    \"content\": \"\"
+}";
+
+pub const MERGE_DOCUMENT_PROMPT: &str =
+    "Merge these two versions of a markdown document. Keep everything in the existing version, 
+including its wording, and add the sections and details from the generated version that it 
+is missing. Update statements in the existing version only if the generated version shows 
+they are out of date.
+Existing version: {{existing}}
+Generated version: {{generated}}
+Return a JSON object for your answer. Make sure your entire answer is in the JSON object! 
+Use the below schema for your answer.
+{
+    \"content\": \"\"
+}";
+
+/// A prompt that produces a whole markdown document, such as a README.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentPrompt {
+    pub prompt: String,
+}
+
+impl DocumentPrompt {
+    pub fn readme(repository: &str, summary: &str, layout: &str, entry_points: &str) -> Self {
+        DocumentPrompt {
+            prompt: README_PROMPT
+                .replace("{{repository}}", repository)
+                .replace("{{summary}}", summary)
+                .replace("{{layout}}", layout)
+                .replace("{{entry_points}}", entry_points),
+        }
+    }
+
+    pub fn architecture(repository: &str, summary: &str, layout: &str, entry_points: &str) -> Self {
+        DocumentPrompt {
+            prompt: ARCHITECTURE_PROMPT
+                .replace("{{repository}}", repository)
+                .replace("{{summary}}", summary)
+                .replace("{{layout}}", layout)
+                .replace("{{entry_points}}", entry_points),
+        }
+    }
+
+    pub fn merge(existing: &str, generated: &str) -> Self {
+        DocumentPrompt {
+            prompt: MERGE_DOCUMENT_PROMPT
+                .replace("{{existing}}", existing)
+                .replace("{{generated}}", generated),
+        }
+    }
+
+    pub async fn send(
+        &self,
+        key: &str,
+        settings: &ChatSettings,
+    ) -> Result<DocumentResponse, Error> {
+        send_json::<DocumentResponse>(key, settings, vec![ChatMessage::user(&self.prompt)]).await
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentResponse {
+    pub content: String,
+}
+
+pub const DEPENDENCY_PROMPT: &str =
+    "Describe what this github repository uses each of its dependencies for, in one sentence each. 
+Here are its dependencies, with the files importing them and what those files do: {{dependencies}}
+Base each description on the files importing the dependency rather than on what the dependency 
+does in general. Return a JSON object for your answer. 
+Make sure your entire answer is in the JSON object! Use the below schema for your answer.
+{
+    \"dependencies\": [{\"name\": \"\", \"usage\": \"\"}]
+}";
+
+/// A prompt describing what the major dependencies of a repository are used for.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DependencyPrompt {
+    pub prompt: String,
+}
+
+impl DependencyPrompt {
+    pub fn new(dependencies: &str) -> DependencyPrompt {
+        DependencyPrompt {
+            prompt: DEPENDENCY_PROMPT.replace("{{dependencies}}", dependencies),
+        }
+    }
+
+    pub async fn send(
+        &self,
+        key: &str,
+        settings: &ChatSettings,
+    ) -> Result<DependencyResponse, Error> {
+        send_json::<DependencyResponse>(key, settings, vec![ChatMessage::user(&self.prompt)]).await
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DependencyUsage {
+    pub name: String,
+
+    pub usage: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DependencyResponse {
+    pub dependencies: Vec<DependencyUsage>,
+}
+
+pub const COMPARE_PROMPT: &str =
+    "Compare these two github repositories, which are being evaluated as alternatives to each other.
+Here is the first repository: {{first}}
+Here is the second repository: {{second}}
+List the functionality both provide, the aspects where they take different approaches, and signals of 
+how mature each is, such as tests, documentation, CI and the size of the codebase. Base the comparison 
+on the summaries, stats and dependencies given. Return a JSON object for your answer. 
+Make sure your entire answer is in the JSON object! Use the below schema for your answer.
+{
+    \"summary\": \"\",
+    \"overlap\": [\"\"],
+    \"differences\": [{\"aspect\": \"\", \"first\": \"\", \"second\": \"\"}],
+    \"maturity\": [{\"aspect\": \"\", \"first\": \"\", \"second\": \"\"}],
+    \"recommendation\": \"\"
+}";
+
+/// A prompt comparing two repositories from their summaries and stats.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ComparePrompt {
+    pub prompt: String,
+}
+
+impl ComparePrompt {
+    pub fn new(first: &str, second: &str) -> ComparePrompt {
+        ComparePrompt {
+            prompt: COMPARE_PROMPT
+                .replace("{{first}}", first)
+                .replace("{{second}}", second),
+        }
+    }
+
+    pub async fn send(
+        &self,
+        key: &str,
+        settings: &ChatSettings,
+    ) -> Result<ComparisonResponse, Error> {
+        send_json::<ComparisonResponse>(key, settings, vec![ChatMessage::user(&self.prompt)]).await
+    }
+}
+
+/// How the two repositories differ in one aspect.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Difference {
+    pub aspect: String,
+
+    pub first: String,
+
+    pub second: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparisonResponse {
+    pub summary: String,
+
+    /// Functionality both repositories provide.
+    #[serde(default)]
+    pub overlap: Vec<String>,
+
+    /// Aspects where the repositories take different approaches.
+    #[serde(default)]
+    pub differences: Vec<Difference>,
+
+    #[serde(default)]
+    pub maturity: Vec<Difference>,
+
+    #[serde(default)]
+    pub recommendation: String,
+}
+
+pub const ISSUES_PROMPT: &str =
+    "Here are the open and recently closed issues of a github repository, with excerpts of their comments: {{issues}}
+Here are the folders of the repository and what they contain: {{folders}}
+Group the issues into themes, and for each theme list its issues by number and the folders from the list above 
+that are most likely relevant to it. List the top pain points of users, most pressing first, and groups of issues 
+that look like duplicates of each other. Return a JSON object for your answer. 
+Make sure your entire answer is in the JSON object! Use the below schema for your answer.
+{
+    \"themes\": [{\"name\": \"\", \"summary\": \"\", \"issues\": [0], \"folders\": [\"\"]}],
+    \"pain_points\": [{\"summary\": \"\", \"issues\": [0]}],
+    \"duplicates\": [{\"issues\": [0], \"reason\": \"\"}]
+}";
+
+/// A prompt clustering the issues of a repository by theme.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IssuesPrompt {
+    pub prompt: String,
+}
+
+impl IssuesPrompt {
+    pub fn new(issues: &str, folders: &str) -> IssuesPrompt {
+        IssuesPrompt {
+            prompt: ISSUES_PROMPT
+                .replace("{{issues}}", issues)
+                .replace("{{folders}}", folders),
+        }
+    }
+
+    pub async fn send(&self, key: &str, settings: &ChatSettings) -> Result<IssuesResponse, Error> {
+        send_json::<IssuesResponse>(key, settings, vec![ChatMessage::user(&self.prompt)]).await
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssueTheme {
+    pub name: String,
+
+    #[serde(default)]
+    pub summary: String,
+
+    /// The numbers of the issues in the theme.
+    #[serde(default)]
+    pub issues: Vec<u64>,
+
+    /// The folders of the repository the theme is about.
+    #[serde(default)]
+    pub folders: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PainPoint {
     pub summary: String,
+
+    #[serde(default)]
+    pub issues: Vec<u64>,
+}
+
+/// Issues that look like they report the same thing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateIssues {
+    pub issues: Vec<u64>,
+
+    #[serde(default)]
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IssuesResponse {
+    #[serde(default)]
+    pub themes: Vec<IssueTheme>,
+
+    #[serde(default)]
+    pub pain_points: Vec<PainPoint>,
+
+    #[serde(default)]
+    pub duplicates: Vec<DuplicateIssues>,
 }
@@ -0,0 +1,179 @@
+use eyre::{eyre, Error, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The built-in patterns, as `(kind, pattern, minimum entropy)`. Patterns with
+/// a `secret` group only redact that group, and those with a minimum entropy
+/// only redact values at least that random, in bits per character.
+const BUILTIN_PATTERNS: &[(&str, &str, f64)] = &[
+    (
+        "private-key",
+        r"(?s)-----BEGIN [A-Z ]*PRIVATE KEY-----.*?-----END [A-Z ]*PRIVATE KEY-----",
+        0.0,
+    ),
+    ("aws-access-key", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b", 0.0),
+    (
+        "aws-secret-key",
+        r#"(?i)aws_?secret_?access_?key["']?\s*[:=]\s*["']?(?P<secret>[A-Za-z0-9/+=]{40})"#,
+        0.0,
+    ),
+    (
+        "github-token",
+        r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{22,})\b",
+        0.0,
+    ),
+    (
+        "secret",
+        r#"(?i)(?:secret|token|passw(?:or)?d|api_?key|access_?key|authorization)[A-Za-z0-9_.\-]*["']?\s*[:=]\s*["'](?P<secret>[^"'\s]{12,})["']"#,
+        3.0,
+    ),
+    (
+        "secret",
+        r"(?m)^\s*(?:export\s+)?[A-Z0-9_]*(?:SECRET|TOKEN|PASSWORD|PASSWD|API_KEY|ACCESS_KEY)[A-Z0-9_]*\s*=\s*(?P<secret>[^\s\x22'#$][^\s\x22'#]{7,})",
+        0.0,
+    ),
+    (
+        "high-entropy-string",
+        r#"["'](?P<secret>[A-Za-z0-9+/=_\-]{32,})["']"#,
+        4.5,
+    ),
+    (
+        "email",
+        r"\b[A-Za-z0-9._%+\-]+@[A-Za-z0-9.\-]+\.[A-Za-z]{2,}\b",
+        0.0,
+    ),
+];
+
+/// Hashes in lockfiles, such as npm's `sha512-...` integrity hashes, are
+/// random but not secret. Placeholders are skipped so that redacting twice
+/// changes nothing.
+const SKIPPED_PREFIXES: &[&str] = &["sha1-", "sha256-", "sha384-", "sha512-", "[REDACTED:"];
+
+/// A value removed from a file, recorded without the value itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Redaction {
+    pub file: String,
+
+    pub line: usize,
+
+    /// The name of the pattern that matched, e.g. `aws-access-key`.
+    pub kind: String,
+}
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    kind: String,
+
+    regex: Regex,
+
+    min_entropy: f64,
+}
+
+/// Replaces secrets and personal data in file contents with placeholders
+/// such as `[REDACTED:github-token]`, before they are sent to a model.
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    patterns: Vec<Pattern>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Redactor::new(&HashMap::new()).expect("built-in patterns are valid")
+    }
+}
+
+/// The Shannon entropy of a string, in bits per character.
+fn entropy(text: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in text.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+
+    let len = text.chars().count() as f64;
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+impl Redactor {
+    /// Creates a redactor with the built-in patterns and `custom`, a map of
+    /// kinds to the regular expressions redacting them.
+    pub fn new(custom: &HashMap<String, String>) -> Result<Redactor, Error> {
+        let mut patterns = BUILTIN_PATTERNS
+            .iter()
+            .map(|(kind, pattern, min_entropy)| Pattern {
+                kind: kind.to_string(),
+                regex: Regex::new(pattern).expect("built-in patterns are valid"),
+                min_entropy: *min_entropy,
+            })
+            .collect::<Vec<Pattern>>();
+
+        let mut kinds = custom.keys().collect::<Vec<&String>>();
+        kinds.sort();
+        for kind in kinds {
+            let pattern = &custom[kind];
+
+            patterns.push(Pattern {
+                kind: kind.clone(),
+                regex: Regex::new(pattern)
+                    .map_err(|e| eyre!("invalid redact pattern {}: {}", kind, e))?,
+                min_entropy: 0.0,
+            });
+        }
+
+        Ok(Redactor { patterns })
+    }
+
+    /// Redacts `content`, returning the redacted text and what was removed.
+    /// When matches overlap, the one starting first is kept.
+    pub fn redact(&self, file: &str, content: &str) -> (String, Vec<Redaction>) {
+        let mut matches = vec![];
+
+        for pattern in &self.patterns {
+            for captures in pattern.regex.captures_iter(content) {
+                let value = match captures.name("secret").or_else(|| captures.get(0)) {
+                    Some(value) if !value.as_str().is_empty() => value,
+                    _ => continue,
+                };
+
+                if SKIPPED_PREFIXES
+                    .iter()
+                    .any(|p| value.as_str().starts_with(p))
+                    || entropy(value.as_str()) < pattern.min_entropy
+                {
+                    continue;
+                }
+
+                matches.push((value.start(), value.end(), &pattern.kind));
+            }
+        }
+        matches.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+
+        let mut redacted = String::with_capacity(content.len());
+        let mut redactions = vec![];
+        let mut end = 0;
+        for (start, match_end, kind) in matches {
+            if start < end {
+                continue;
+            }
+
+            redacted.push_str(&content[end..start]);
+            redacted.push_str(&format!("[REDACTED:{}]", kind));
+            redactions.push(Redaction {
+                file: file.to_string(),
+                line: content[..start].matches('\n').count() + 1,
+                kind: kind.clone(),
+            });
+
+            end = match_end;
+        }
+        redacted.push_str(&content[end..]);
+
+        (redacted, redactions)
+    }
+}
@@ -0,0 +1,331 @@
+use crate::cache::{normalize, one_line, SummaryTree};
+use crate::git::RepositoryContent;
+use crate::history::{contributors, Activity, HistorySummary, HOT_SPOT_DAYS, STALE_DAYS};
+use crate::manifests::{Dependencies, DependencyKind};
+use crate::redact::Redaction;
+use crate::stats::RepositoryStats;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// How many of the files importing a dependency are named in the report.
+const MAX_IMPORTERS: usize = 3;
+
+/// Names the first `MAX_IMPORTERS` files importing a dependency, and how
+/// many more there are.
+fn importers(files: &[String]) -> String {
+    let named = files
+        .iter()
+        .take(MAX_IMPORTERS)
+        .map(String::as_str)
+        .collect::<Vec<&str>>()
+        .join(", ");
+
+    if files.len() > MAX_IMPORTERS {
+        format!("{} and {} more", named, files.len() - MAX_IMPORTERS)
+    } else {
+        named
+    }
+}
+
+/// Renders redactions as the rows of a `| File | Kind | Lines |` table,
+/// grouped by file and kind with the lines they are on.
+pub(crate) fn redaction_rows(redactions: &[Redaction]) -> String {
+    let mut groups: BTreeMap<(&str, &str), Vec<String>> = BTreeMap::new();
+    for redaction in redactions {
+        groups
+            .entry((&redaction.file, &redaction.kind))
+            .or_default()
+            .push(redaction.line.to_string());
+    }
+
+    groups
+        .into_iter()
+        .map(|((file, kind), lines)| {
+            format!(
+                "| {} | {} | {} |\n",
+                cell(file),
+                cell(kind),
+                lines.join(", ")
+            )
+        })
+        .collect()
+}
+
+/// Escapes text for a markdown table cell.
+pub(crate) fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// A markdown report of a summarized repository, combining its summaries
+/// with the facts found without asking a model.
+#[derive(Debug, Serialize)]
+pub struct Report<'a> {
+    pub repository: String,
+
+    pub stats: RepositoryStats,
+
+    /// Every dependency declared in the manifests of the repository.
+    pub dependencies: Dependencies,
+
+    pub summaries: &'a SummaryTree,
+
+    /// What was redacted before the contents were summarized.
+    pub redactions: &'a [Redaction],
+
+    /// How the repository is evolving and who owns it, if history was fetched.
+    pub history: Option<HistorySummary>,
+}
+
+impl<'a> Report<'a> {
+    pub fn new(
+        repository: &str,
+        contents: &'a RepositoryContent,
+        summaries: &'a SummaryTree,
+    ) -> Report<'a> {
+        Report {
+            repository: repository.to_string(),
+            stats: RepositoryStats::new(contents),
+            dependencies: Dependencies::new(contents),
+            summaries,
+            redactions: &contents.redactions,
+            history: Some(&contents.history)
+                .filter(|h| !h.is_empty())
+                .map(|h| h.summarize(contents)),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "# {}\n\n{}\n",
+            self.repository,
+            self.summaries.summary.trim()
+        );
+
+        self.tech_stack(&mut out);
+        self.dependencies(&mut out);
+        self.folders(&mut out);
+        self.files(&mut out);
+        self.history(&mut out);
+        self.redactions(&mut out);
+        self.failures(&mut out);
+
+        normalize(&out)
+    }
+
+    fn tech_stack(&self, out: &mut String) {
+        out.push_str("\n## Tech stack\n\n");
+
+        if !self.stats.build_systems.is_empty() {
+            out.push_str(&format!(
+                "**Build systems:** {}\n\n",
+                self.stats.build_systems.join(", ")
+            ));
+        }
+
+        if !self.stats.frameworks.is_empty() {
+            out.push_str(&format!(
+                "**Frameworks:** {}\n\n",
+                self.stats.frameworks.join(", ")
+            ));
+        }
+
+        out.push_str(
+            "| Language | Files | Code | Comments | Blank |\n| --- | --- | --- | --- | --- |\n",
+        );
+        for language in &self.stats.languages {
+            out.push_str(&format!(
+                "| {} | {} | {} | {} | {} |\n",
+                language.language, language.files, language.code, language.comments, language.blank
+            ));
+        }
+    }
+
+    fn dependencies(&self, out: &mut String) {
+        let declared = &self.dependencies.dependencies;
+
+        if declared.is_empty() {
+            return;
+        }
+
+        let count = |kind| declared.iter().filter(|d| d.kind == kind).count();
+        out.push_str(&format!(
+            "\n## Dependencies\n\n{} dependencies declared, {} for development and {} for builds.\n\n",
+            count(DependencyKind::Normal),
+            count(DependencyKind::Dev),
+            count(DependencyKind::Build)
+        ));
+
+        for workspace in &self.dependencies.workspaces {
+            out.push_str(&format!(
+                "**Workspace** `{}`: {}\n\n",
+                workspace.manifest,
+                workspace.members.join(", ")
+            ));
+        }
+
+        if self.summaries.dependencies.is_empty() {
+            return;
+        }
+
+        out.push_str(
+            "| Dependency | Version | Used for | Imported by |\n| --- | --- | --- | --- |\n",
+        );
+        for dependency in &self.summaries.dependencies {
+            out.push_str(&format!(
+                "| {} | {} | {} | {} |\n",
+                cell(&dependency.name),
+                cell(
+                    dependency
+                        .resolved
+                        .as_deref()
+                        .unwrap_or(&dependency.version)
+                ),
+                cell(&dependency.usage),
+                cell(&importers(&dependency.files))
+            ));
+        }
+    }
+
+    fn folders(&self, out: &mut String) {
+        let mut folders = self.summaries.folders.iter().collect::<Vec<_>>();
+        folders.sort_by(|a, b| a.0.cmp(b.0));
+
+        out.push_str("\n## Folders\n\n| Folder | Summary |\n| --- | --- |\n");
+        for (name, folder) in folders {
+            let name = if name.is_empty() { "." } else { name };
+
+            out.push_str(&format!(
+                "| {} | {} |\n",
+                cell(name),
+                cell(&one_line(&folder.summary))
+            ));
+        }
+    }
+
+    fn files(&self, out: &mut String) {
+        let mut files = self
+            .summaries
+            .folders
+            .values()
+            .flat_map(|folder| &folder.files)
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        out.push_str(
+            "\n## Files\n\n| File | Purpose | Side effects | Risks | Confidence |\n| --- | --- | --- | --- | --- |\n",
+        );
+        for file in files {
+            let purpose = if file.purpose.is_empty() {
+                one_line(&file.summary)
+            } else {
+                file.purpose.clone()
+            };
+
+            out.push_str(&format!(
+                "| {} | {} | {} | {} | {} |\n",
+                cell(&file.name),
+                cell(&purpose),
+                cell(&file.side_effects.join("; ")),
+                cell(&file.risks.join("; ")),
+                file.confidence
+                    .map(|c| format!("{:.2}", c))
+                    .unwrap_or_default()
+            ));
+        }
+    }
+
+    fn history(&self, out: &mut String) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let date = |activity: &Activity| {
+            activity
+                .last_modified
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|| String::from("before the history"))
+        };
+
+        if !history.hot_spots.is_empty() {
+            out.push_str(&format!(
+                "\n## Hot spots\n\nThe files changed most in the last {} days of the {} commits read.\n\n| File | Commits | Last changed | Recent changes |\n| --- | --- | --- | --- |\n",
+                HOT_SPOT_DAYS, history.commits
+            ));
+            for activity in &history.hot_spots {
+                out.push_str(&format!(
+                    "| {} | {} | {} | {} |\n",
+                    cell(&activity.path),
+                    activity.commits,
+                    date(activity),
+                    cell(&activity.recent_messages.join("; "))
+                ));
+            }
+        }
+
+        if !history.stale.is_empty() {
+            out.push_str(&format!(
+                "\n## Stale areas\n\nFolders unchanged for over {} days before the latest commit.\n\n| Folder | Last changed |\n| --- | --- |\n",
+                STALE_DAYS
+            ));
+            for activity in &history.stale {
+                out.push_str(&format!(
+                    "| {} | {} |\n",
+                    cell(&activity.path),
+                    date(activity)
+                ));
+            }
+        }
+
+        if !history.who_to_ask.is_empty() {
+            out.push_str(
+                "\n## Who to ask\n\n| Folder | Owners | Main contributors |\n| --- | --- | --- |\n",
+            );
+            for activity in &history.who_to_ask {
+                out.push_str(&format!(
+                    "| {} | {} | {} |\n",
+                    cell(&activity.path),
+                    cell(&activity.owners.join(", ")),
+                    cell(&contributors(&activity.contributors))
+                ));
+            }
+        }
+    }
+
+    fn redactions(&self, out: &mut String) {
+        if self.redactions.is_empty() {
+            return;
+        }
+
+        out.push_str(&format!(
+            "\n## Redactions\n\n{} values were redacted before the contents were summarized.\n\n| File | Kind | Lines |\n| --- | --- | --- |\n",
+            self.redactions.len()
+        ));
+        out.push_str(&redaction_rows(self.redactions));
+    }
+
+    fn failures(&self, out: &mut String) {
+        let failures = &self.summaries.failures;
+        if failures.is_empty() {
+            return;
+        }
+
+        out.push_str(&format!(
+            "\n## Failures\n\n{} summaries failed and are missing from this report.\n\n| Item | Kind | Error |\n| --- | --- | --- |\n",
+            failures.len()
+        ));
+        for failure in failures {
+            let item = if failure.path.is_empty() {
+                "."
+            } else {
+                &failure.path
+            };
+
+            out.push_str(&format!(
+                "| {} | {} | {} |\n",
+                cell(item),
+                json!(failure.kind).as_str().unwrap_or_default(),
+                cell(&failure.error)
+            ));
+        }
+    }
+}
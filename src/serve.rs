@@ -0,0 +1,367 @@
+use crate::cache::{self, Cache, CachedRepository, SummaryTree};
+use crate::config::{Config, ConfigLayer, OutputFormat};
+use crate::git::Git;
+use crate::progress::{Progress, ProgressView};
+use crate::prompts::{Length, Style};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use eyre::{eyre, Error, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+
+/// Settings a job can override, on top of the server's configuration.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobOptions {
+    pub style: Option<Style>,
+
+    pub length: Option<Length>,
+
+    pub model: Option<String>,
+
+    pub include: Option<Vec<String>>,
+
+    pub exclude: Option<Vec<String>>,
+}
+
+/// A request to summarize a repository.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobRequest {
+    /// The repository, as `username/repo`.
+    pub source: String,
+
+    /// The branch, tag or commit to summarize.
+    #[serde(rename = "ref")]
+    pub reference: String,
+
+    #[serde(default)]
+    pub options: JobOptions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+
+    Running,
+
+    Done,
+
+    Failed,
+}
+
+#[derive(Debug)]
+struct Job {
+    source: String,
+
+    reference: String,
+
+    status: JobStatus,
+
+    progress: Arc<Progress>,
+
+    error: Option<String>,
+
+    summaries: Option<SummaryTree>,
+}
+
+/// What the API returns about a job.
+#[derive(Debug, Serialize)]
+pub struct JobView {
+    pub id: u64,
+
+    pub source: String,
+
+    #[serde(rename = "ref")]
+    pub reference: String,
+
+    pub status: JobStatus,
+
+    pub progress: ProgressView,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Job {
+    fn view(&self, id: u64) -> JobView {
+        JobView {
+            id,
+            source: self.source.clone(),
+            reference: self.reference.clone(),
+            status: self.status,
+            progress: self.progress.view(),
+            error: self.error.clone(),
+        }
+    }
+}
+
+/// An error returned as `{"error": "..."}` with a status code.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+/// Runs summarization jobs submitted over HTTP, at most `concurrency` at
+/// once and with at most `queue` waiting.
+#[derive(Debug)]
+pub struct Server {
+    profile: Option<String>,
+
+    layer: ConfigLayer,
+
+    config: Config,
+
+    queue: usize,
+
+    slots: Semaphore,
+
+    jobs: Mutex<HashMap<u64, Job>>,
+
+    next_id: Mutex<u64>,
+}
+
+impl Server {
+    /// Creates a server that resolves the configuration of each job from
+    /// `profile` and `layer`, with the job's options applied on top.
+    pub fn new(
+        profile: Option<String>,
+        layer: ConfigLayer,
+        config: Config,
+        concurrency: usize,
+        queue: usize,
+    ) -> Server {
+        Server {
+            profile,
+            layer,
+            config,
+            queue,
+            slots: Semaphore::new(concurrency.max(1)),
+            jobs: Mutex::new(HashMap::new()),
+            next_id: Mutex::new(1),
+        }
+    }
+
+    /// The routes of the API.
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/jobs", post(submit).get(list_jobs))
+            .route("/jobs/:id", get(job))
+            .route("/jobs/:id/summary", get(job_summary))
+            .route("/repos", get(repos))
+            .route("/repos/:username/:repo/*branch", get(repo_summary))
+            .with_state(Arc::new(self))
+    }
+
+    pub async fn serve(self, bind: &str) -> Result<(), Error> {
+        let app = self.router();
+
+        let listener = TcpListener::bind(bind)
+            .await
+            .map_err(|e| eyre!("failed to bind {}: {}", bind, e))?;
+        println!("Listening on http://{}", listener.local_addr()?);
+
+        axum::serve(listener, app).await?;
+
+        Ok(())
+    }
+
+    /// The configuration of a job: the server's, with the job's options applied.
+    fn job_config(&self, options: &JobOptions) -> Result<Config, Error> {
+        let mut layer = self.layer.clone();
+        layer.output = Some(OutputFormat::Json);
+        layer.style = options.style.or(layer.style);
+        layer.length = options.length.or(layer.length);
+        layer.generation.model = options.model.clone().or(layer.generation.model);
+        layer.include = options.include.clone().or(layer.include);
+        layer.exclude = options.exclude.clone().or(layer.exclude);
+
+        Config::load(self.profile.as_deref(), layer)
+    }
+
+    fn update(&self, id: u64, update: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            update(job);
+        }
+    }
+
+    async fn run(&self, id: u64, git: Git, cache: Cache) {
+        let _slot = self.slots.acquire().await;
+        self.update(id, |job| job.status = JobStatus::Running);
+
+        match summarize(git, &cache).await {
+            Ok(summaries) => self.update(id, |job| {
+                job.status = JobStatus::Done;
+                job.summaries = Some(summaries);
+            }),
+            Err(e) => self.update(id, |job| {
+                job.status = JobStatus::Failed;
+                job.error = Some(e.to_string());
+            }),
+        }
+    }
+}
+
+/// Fetches and summarizes a repository, caching both like `gitsum sum` does.
+async fn summarize(mut git: Git, cache: &Cache) -> Result<SummaryTree, Error> {
+    git.get_contents(Client::new()).await?;
+    cache.save_contents(&git.repository_content)?;
+
+    let summaries = git.summarize_repository().await?;
+    cache.save_summaries(&summaries)?;
+
+    Ok(summaries)
+}
+
+async fn submit(
+    State(server): State<Arc<Server>>,
+    Json(request): Json<JobRequest>,
+) -> Result<(StatusCode, Json<JobView>), ApiError> {
+    let (username, repo) = request
+        .source
+        .split_once('/')
+        .filter(|(username, repo)| !username.is_empty() && !repo.is_empty())
+        .ok_or_else(|| {
+            ApiError(
+                StatusCode::BAD_REQUEST,
+                String::from("source must be of the form username/repo"),
+            )
+        })?;
+    cache::validate(username, repo, &request.reference)
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let config = server
+        .job_config(&request.options)
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?;
+    let open_ai_key = config
+        .open_ai_key()
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let progress = Arc::new(Progress::default());
+    let cache = Cache::new(&config.cache_dir, username, repo, &request.reference);
+    let git = Git::new(
+        &config.github_key().unwrap_or_default(),
+        &open_ai_key,
+        username,
+        repo,
+        &request.reference,
+    )
+    .with_config(config)
+    .with_progress(progress.clone());
+
+    let (id, view) = {
+        let mut jobs = server.jobs.lock().unwrap();
+
+        let queued = jobs
+            .values()
+            .filter(|job| job.status == JobStatus::Queued)
+            .count();
+        if queued >= server.queue {
+            return Err(ApiError(
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("{} jobs are already queued", queued),
+            ));
+        }
+
+        let mut next_id = server.next_id.lock().unwrap();
+        let id = *next_id;
+        *next_id += 1;
+
+        let job = Job {
+            source: request.source.clone(),
+            reference: request.reference.clone(),
+            status: JobStatus::Queued,
+            progress,
+            error: None,
+            summaries: None,
+        };
+        let view = job.view(id);
+        jobs.insert(id, job);
+
+        (id, view)
+    };
+
+    let runner = server.clone();
+    tokio::spawn(async move { runner.run(id, git, cache).await });
+
+    Ok((StatusCode::ACCEPTED, Json(view)))
+}
+
+async fn list_jobs(State(server): State<Arc<Server>>) -> ApiResult<Vec<JobView>> {
+    let jobs = server.jobs.lock().unwrap();
+
+    let mut views = jobs
+        .iter()
+        .map(|(id, job)| job.view(*id))
+        .collect::<Vec<JobView>>();
+    views.sort_by_key(|view| view.id);
+
+    Ok(Json(views))
+}
+
+fn not_found(id: u64) -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, format!("job {} not found", id))
+}
+
+async fn job(State(server): State<Arc<Server>>, Path(id): Path<u64>) -> ApiResult<JobView> {
+    let jobs = server.jobs.lock().unwrap();
+
+    jobs.get(&id)
+        .map(|job| Json(job.view(id)))
+        .ok_or_else(|| not_found(id))
+}
+
+async fn job_summary(
+    State(server): State<Arc<Server>>,
+    Path(id): Path<u64>,
+) -> ApiResult<SummaryTree> {
+    let jobs = server.jobs.lock().unwrap();
+    let job = jobs.get(&id).ok_or_else(|| not_found(id))?;
+
+    job.summaries.clone().map(Json).ok_or_else(|| {
+        ApiError(
+            StatusCode::CONFLICT,
+            format!(
+                "job {} is {}",
+                id,
+                json!(job.status).as_str().unwrap_or_default()
+            ),
+        )
+    })
+}
+
+async fn repos(State(server): State<Arc<Server>>) -> ApiResult<Vec<CachedRepository>> {
+    Ok(Json(Cache::list(&server.config.cache_dir)))
+}
+
+async fn repo_summary(
+    State(server): State<Arc<Server>>,
+    Path((username, repo, branch)): Path<(String, String, String)>,
+) -> ApiResult<SummaryTree> {
+    cache::validate(&username, &repo, &branch)
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    Cache::new(&server.config.cache_dir, &username, &repo, &branch)
+        .load_summaries()
+        .map(Json)
+        .map_err(|_| {
+            ApiError(
+                StatusCode::NOT_FOUND,
+                format!("{}/{} {} has not been summarized", username, repo, branch),
+            )
+        })
+}
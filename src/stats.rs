@@ -0,0 +1,269 @@
+use crate::git::RepositoryContent;
+use crate::languages::{comment_syntax, detect};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::LazyLock;
+
+/// Files that identify a build system, matched against file names.
+const BUILD_SYSTEMS: &[(&str, &str)] = &[
+    ("Cargo.toml", "Cargo"),
+    ("package.json", "npm"),
+    ("yarn.lock", "Yarn"),
+    ("pnpm-lock.yaml", "pnpm"),
+    ("bun.lockb", "Bun"),
+    ("pyproject.toml", "pyproject"),
+    ("poetry.lock", "Poetry"),
+    ("requirements.txt", "pip"),
+    ("setup.py", "setuptools"),
+    ("Pipfile", "Pipenv"),
+    ("go.mod", "Go modules"),
+    ("pom.xml", "Maven"),
+    ("build.gradle", "Gradle"),
+    ("build.gradle.kts", "Gradle"),
+    ("Gemfile", "Bundler"),
+    ("composer.json", "Composer"),
+    ("mix.exs", "Mix"),
+    ("CMakeLists.txt", "CMake"),
+    ("Makefile", "Make"),
+    ("Dockerfile", "Docker"),
+    ("docker-compose.yml", "Docker Compose"),
+    ("docker-compose.yaml", "Docker Compose"),
+    ("flake.nix", "Nix"),
+];
+
+/// Frameworks and notable libraries, detected from the dependencies named
+/// in the manifests they are declared in.
+const FRAMEWORKS: &[(&str, &str, &str)] = &[
+    ("Cargo.toml", "tokio", "Tokio"),
+    ("Cargo.toml", "actix-web", "Actix Web"),
+    ("Cargo.toml", "axum", "Axum"),
+    ("Cargo.toml", "rocket", "Rocket"),
+    ("Cargo.toml", "warp", "Warp"),
+    ("Cargo.toml", "clap", "clap"),
+    ("Cargo.toml", "serde", "Serde"),
+    ("Cargo.toml", "diesel", "Diesel"),
+    ("Cargo.toml", "sqlx", "SQLx"),
+    ("Cargo.toml", "bevy", "Bevy"),
+    ("Cargo.toml", "tauri", "Tauri"),
+    ("package.json", "react", "React"),
+    ("package.json", "next", "Next.js"),
+    ("package.json", "vue", "Vue"),
+    ("package.json", "nuxt", "Nuxt"),
+    ("package.json", "svelte", "Svelte"),
+    ("package.json", "@angular/core", "Angular"),
+    ("package.json", "express", "Express"),
+    ("package.json", "@nestjs/core", "NestJS"),
+    ("package.json", "electron", "Electron"),
+    ("package.json", "typescript", "TypeScript"),
+    ("package.json", "vite", "Vite"),
+    ("package.json", "webpack", "webpack"),
+    ("package.json", "jest", "Jest"),
+    ("package.json", "vitest", "Vitest"),
+    ("pyproject.toml", "django", "Django"),
+    ("pyproject.toml", "flask", "Flask"),
+    ("pyproject.toml", "fastapi", "FastAPI"),
+    ("pyproject.toml", "torch", "PyTorch"),
+    ("pyproject.toml", "tensorflow", "TensorFlow"),
+    ("pyproject.toml", "pandas", "pandas"),
+    ("pyproject.toml", "pytest", "pytest"),
+    ("requirements.txt", "django", "Django"),
+    ("requirements.txt", "flask", "Flask"),
+    ("requirements.txt", "fastapi", "FastAPI"),
+    ("requirements.txt", "torch", "PyTorch"),
+    ("requirements.txt", "tensorflow", "TensorFlow"),
+    ("requirements.txt", "pandas", "pandas"),
+    ("requirements.txt", "pytest", "pytest"),
+    ("go.mod", "github.com/gin-gonic/gin", "Gin"),
+    ("go.mod", "github.com/labstack/echo", "Echo"),
+    ("go.mod", "github.com/gofiber/fiber", "Fiber"),
+    ("go.mod", "github.com/spf13/cobra", "Cobra"),
+    ("go.mod", "google.golang.org/grpc", "gRPC"),
+    ("pom.xml", "spring-boot", "Spring Boot"),
+    ("pom.xml", "quarkus", "Quarkus"),
+    ("pom.xml", "junit", "JUnit"),
+    ("build.gradle", "spring-boot", "Spring Boot"),
+    ("build.gradle", "junit", "JUnit"),
+    ("build.gradle.kts", "spring-boot", "Spring Boot"),
+    ("build.gradle.kts", "junit", "JUnit"),
+    ("Gemfile", "rails", "Rails"),
+    ("Gemfile", "sinatra", "Sinatra"),
+    ("Gemfile", "rspec", "RSpec"),
+    ("composer.json", "laravel/framework", "Laravel"),
+    ("composer.json", "symfony/framework-bundle", "Symfony"),
+    ("mix.exs", "phoenix", "Phoenix"),
+];
+
+/// The lines of one language, counted like `cloc` does.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LanguageStats {
+    pub language: String,
+
+    pub files: usize,
+
+    pub code: usize,
+
+    pub comments: usize,
+
+    pub blank: usize,
+}
+
+/// Facts about a repository found without asking a model: the languages it
+/// is written in, its build systems and the frameworks it uses.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepositoryStats {
+    /// Ordered by lines of code, most first.
+    pub languages: Vec<LanguageStats>,
+
+    pub build_systems: Vec<String>,
+
+    pub frameworks: Vec<String>,
+}
+
+/// Counts the code, comment and blank lines of a file.
+fn count_lines(language: &str, content: &str) -> (usize, usize, usize) {
+    let (line_comments, block_comment) = comment_syntax(language);
+    let (mut code, mut comments, mut blank) = (0, 0, 0);
+    let mut in_block = false;
+
+    for line in content.lines() {
+        let line = line.trim();
+
+        if let (true, Some((_, end))) = (in_block, block_comment) {
+            comments += 1;
+            in_block = !line.contains(end);
+        } else if line.is_empty() {
+            blank += 1;
+        } else if line_comments.iter().any(|prefix| line.starts_with(prefix)) {
+            comments += 1;
+        } else if let Some((start, end)) = block_comment.filter(|(s, _)| line.starts_with(s)) {
+            comments += 1;
+            in_block = !line[start.len()..].contains(end);
+        } else {
+            code += 1;
+        }
+    }
+
+    (code, comments, blank)
+}
+
+/// For each of `FRAMEWORKS`, a pattern matching its dependency as a whole
+/// word, so that `react` doesn't match `react-dom` or `preact`.
+static FRAMEWORK_PATTERNS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    FRAMEWORKS
+        .iter()
+        .map(|(_, dependency, _)| {
+            Regex::new(&format!(
+                r"(?mi)(^|[^A-Za-z0-9_\-./@]){}($|[^A-Za-z0-9_\-])",
+                regex::escape(dependency)
+            ))
+            .unwrap()
+        })
+        .collect()
+});
+
+impl RepositoryStats {
+    pub fn new(contents: &RepositoryContent) -> RepositoryStats {
+        let mut languages: HashMap<&str, LanguageStats> = HashMap::new();
+        let mut build_systems = BTreeSet::new();
+        let mut frameworks = BTreeSet::new();
+
+        for folder in contents.folders.values() {
+            for file in folder.files.values() {
+                let language = detect(&file.name, &file.content);
+                let (code, comments, blank) = count_lines(language, &file.content);
+
+                let stats = languages.entry(language).or_insert_with(|| LanguageStats {
+                    language: language.to_string(),
+                    ..Default::default()
+                });
+                stats.files += 1;
+                stats.code += code;
+                stats.comments += comments;
+                stats.blank += blank;
+
+                let file_name = file.name.rsplit('/').next().unwrap_or_default();
+
+                for (manifest, build_system) in BUILD_SYSTEMS {
+                    if file_name == *manifest {
+                        build_systems.insert(build_system.to_string());
+                    }
+                }
+
+                for ((manifest, _, framework), pattern) in
+                    FRAMEWORKS.iter().zip(FRAMEWORK_PATTERNS.iter())
+                {
+                    if file_name == *manifest && pattern.is_match(&file.content) {
+                        frameworks.insert(framework.to_string());
+                    }
+                }
+            }
+        }
+
+        let mut languages = languages.into_values().collect::<Vec<LanguageStats>>();
+        languages.sort_by(|a, b| b.code.cmp(&a.code).then(a.language.cmp(&b.language)));
+
+        RepositoryStats {
+            languages,
+            build_systems: build_systems.into_iter().collect(),
+            frameworks: frameworks.into_iter().collect(),
+        }
+    }
+
+    /// The build systems and frameworks of the repository.
+    pub fn stack(&self) -> Vec<String> {
+        self.build_systems
+            .iter()
+            .chain(&self.frameworks)
+            .cloned()
+            .collect()
+    }
+
+    /// Describes the `limit` main languages with their share of the code.
+    pub fn main_languages(&self, limit: usize) -> String {
+        let total = self.languages.iter().map(|l| l.code).sum::<usize>().max(1);
+
+        self.languages
+            .iter()
+            .filter(|l| l.code > 0)
+            .take(limit)
+            .map(|l| format!("{} {}%", l.language, l.code * 100 / total))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Describes the stats as text, for the repository prompt.
+    pub fn describe(&self) -> String {
+        let total = self.languages.iter().map(|l| l.code).sum::<usize>().max(1);
+
+        let languages = self
+            .languages
+            .iter()
+            .filter(|l| l.code > 0)
+            .map(|l| {
+                format!(
+                    "{} ({} files, {} lines of code, {}%)",
+                    l.language,
+                    l.files,
+                    l.code,
+                    l.code * 100 / total
+                )
+            })
+            .collect::<Vec<String>>();
+
+        let mut out = format!("Languages: {}", languages.join(", "));
+
+        if !self.build_systems.is_empty() {
+            out.push_str(&format!(
+                "\nBuild systems: {}",
+                self.build_systems.join(", ")
+            ));
+        }
+
+        if !self.frameworks.is_empty() {
+            out.push_str(&format!("\nFrameworks: {}", self.frameworks.join(", ")));
+        }
+
+        out
+    }
+}
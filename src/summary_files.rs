@@ -0,0 +1,235 @@
+use crate::cache::{normalize, one_line};
+use crate::git::{Git, RepositoryContent};
+use crate::prompts::FileSummaryResponse;
+use colored::*;
+use eyre::{eyre, Error};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_SUMMARY_FILE: &str = "SUMMARY.md";
+
+/// Summary files start with this marker followed by the hash of the folder
+/// they were generated from, so unchanged folders are not summarized again.
+const MARKER: &str = "<!-- gitsum:";
+
+/// Returns the last segment of a path.
+fn base_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or_default()
+}
+
+/// Returns the direct subfolders of `folder`, in path order.
+fn children<'a>(contents: &'a RepositoryContent, folder: &str) -> Vec<&'a str> {
+    let mut children = contents
+        .folders
+        .keys()
+        .filter(|name| {
+            !name.is_empty()
+                && name
+                    .rsplit_once('/')
+                    .map(|(dir, _)| dir)
+                    .unwrap_or_default()
+                    == folder
+        })
+        .map(|name| name.as_str())
+        .collect::<Vec<&str>>();
+    children.sort();
+
+    children
+}
+
+/// Hashes the files of a folder along with the names of its subfolders,
+/// which are all that its summary file is generated from.
+pub fn folder_hash(contents: &RepositoryContent, folder: &str) -> String {
+    let mut hasher = Sha256::new();
+
+    if let Some(folder) = contents.folders.get(folder) {
+        let mut files = folder.files.values().collect::<Vec<_>>();
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for file in files {
+            hasher.update(file.name.as_bytes());
+            hasher.update([0]);
+            hasher.update(file.content.as_bytes());
+            hasher.update([0]);
+        }
+    }
+
+    for child in children(contents, folder) {
+        hasher.update(child.as_bytes());
+        hasher.update([0]);
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+/// Renders the summary file of a folder: its summary, a table of its files
+/// and links to the summary files of its subfolders.
+pub fn render(
+    title: &str,
+    hash: &str,
+    folder_summary: &str,
+    file_summaries: &[FileSummaryResponse],
+    children: &[&str],
+    file_name: &str,
+) -> String {
+    let mut out = format!("{} {} -->\n# {}\n", MARKER, hash, title);
+
+    if !folder_summary.is_empty() {
+        out.push_str(&format!("\n{}\n", folder_summary.trim()));
+    }
+
+    if !file_summaries.is_empty() {
+        let mut files = file_summaries.iter().collect::<Vec<_>>();
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        out.push_str("\n## Files\n\n| File | Summary |\n| --- | --- |\n");
+        for file in files {
+            let name = base_name(&file.name);
+            let summary = if file.purpose.is_empty() {
+                &file.summary
+            } else {
+                &file.purpose
+            };
+
+            out.push_str(&format!(
+                "| [{}]({}) | {} |\n",
+                name,
+                name,
+                one_line(summary).replace('|', "\\|")
+            ));
+        }
+    }
+
+    if !children.is_empty() {
+        out.push_str("\n## Folders\n\n");
+        for child in children {
+            let name = base_name(child);
+
+            out.push_str(&format!("- [{}]({}/{})\n", name, name, file_name));
+        }
+    }
+
+    normalize(&out)
+}
+
+/// Returns the hash recorded in an existing summary file.
+fn recorded_hash(existing: &str) -> Option<&str> {
+    existing
+        .lines()
+        .next()?
+        .strip_prefix(MARKER)?
+        .strip_suffix("-->")
+        .map(|hash| hash.trim())
+}
+
+/// Writes a summary file into every folder of a local checkout.
+#[derive(Debug)]
+pub struct SummaryFiles<'a> {
+    pub root: PathBuf,
+
+    pub file_name: String,
+
+    pub git: &'a Git,
+}
+
+impl<'a> SummaryFiles<'a> {
+    pub fn new(root: &Path, file_name: &str, git: &'a Git) -> Self {
+        SummaryFiles {
+            root: root.to_path_buf(),
+            file_name: file_name.to_string(),
+            git,
+        }
+    }
+
+    pub fn path(&self, folder: &str) -> PathBuf {
+        self.root.join(folder).join(&self.file_name)
+    }
+
+    fn title(&self, folder: &str) -> String {
+        if folder.is_empty() {
+            self.git.repository_name.clone()
+        } else {
+            folder.to_string()
+        }
+    }
+
+    /// Returns the folders whose summary file is missing or was generated
+    /// from different contents, in path order.
+    pub fn stale(&self) -> Vec<String> {
+        let contents = &self.git.repository_content;
+
+        let mut stale = contents
+            .folders
+            .keys()
+            .filter(|folder| {
+                let hash = folder_hash(contents, folder);
+                let existing = fs::read_to_string(self.path(folder)).unwrap_or_default();
+
+                recorded_hash(&existing) != Some(hash.as_str())
+            })
+            .cloned()
+            .collect::<Vec<String>>();
+        stale.sort();
+
+        stale
+    }
+
+    /// Summarizes every stale folder and writes its summary file, leaving
+    /// the others untouched. Returns the paths that were written.
+    pub async fn write(&self) -> Result<Vec<PathBuf>, Error> {
+        let contents = &self.git.repository_content;
+        let mut written = vec![];
+
+        let stale = self.stale();
+        self.git.progress.add_files(
+            stale
+                .iter()
+                .filter_map(|folder| contents.folders.get(folder))
+                .map(|folder| folder.files.len())
+                .sum(),
+        );
+
+        for folder in stale {
+            let has_files = contents
+                .folders
+                .get(&folder)
+                .map(|f| !f.files.is_empty())
+                .unwrap_or_default();
+
+            let (folder_summary, file_summaries) = if has_files {
+                let (folder_summary, file_summaries) = self.git.summarize_folder(&folder).await?;
+
+                (folder_summary.summary, file_summaries)
+            } else {
+                (String::new(), vec![])
+            };
+
+            let rendered = render(
+                &self.title(&folder),
+                &folder_hash(contents, &folder),
+                &folder_summary,
+                &file_summaries,
+                &children(contents, &folder),
+                &self.file_name,
+            );
+
+            let path = self.path(&folder);
+            fs::write(&path, rendered)
+                .map_err(|e| eyre!("failed to write {}: {}", path.display(), e))?;
+            if self.git.verbose() {
+                self.git.progress.println(&format!(
+                    "{} {}",
+                    "Wrote".green().bold(),
+                    path.display()
+                ));
+            }
+
+            written.push(path);
+        }
+
+        self.git.progress.finish();
+
+        Ok(written)
+    }
+}
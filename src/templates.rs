@@ -0,0 +1,342 @@
+use crate::cache::{FolderSummary, SummaryTree};
+use crate::history::{Activity, HistorySummary};
+use crate::manifests::{describe, Dependency, DependencyKind, Ecosystem};
+use crate::prompts::{FileSummaryResponse, Length, Style, Symbol};
+use crate::stats::RepositoryStats;
+use eyre::{eyre, Error, Result};
+use minijinja::{context, Environment, UndefinedBehavior, Value};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+pub const FILE_TEMPLATE: &str = "file";
+
+pub const FOLDER_TEMPLATE: &str = "folder";
+
+pub const REPOSITORY_TEMPLATE: &str = "repository";
+
+pub const TEMPLATE_NAMES: [&str; 3] = [FILE_TEMPLATE, FOLDER_TEMPLATE, REPOSITORY_TEMPLATE];
+
+/// The extension of template files, e.g. `file.jinja`.
+pub const TEMPLATE_EXTENSION: &str = "jinja";
+
+/// The repository being summarized, available to every template as `repository`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RepositoryMeta {
+    pub owner: String,
+
+    pub name: String,
+
+    pub branch: String,
+}
+
+/// The summary of a folder, passed to the repository template.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChildSummary {
+    pub name: String,
+
+    pub summary: String,
+}
+
+/// The prompt templates for each summary level, rendered with minijinja.
+///
+/// The file template receives `path`, `language`, `content`, `outline` and
+/// `repository`. `content` is empty if the file only fits as an outline, and
+/// `outline` is empty if its language isn't supported.
+///
+/// The folder template receives `path`, `children`, `history` and
+/// `repository`, where `children` are the structured summaries of its files
+/// and `history` is how the folder has changed, if history was fetched.
+///
+/// The repository template receives `children`, the folder summaries, along
+/// with its major `dependencies`, the `risks` of every file, the `stats` and
+/// `history` of the repository and `repository`.
+///
+/// `file`, `files` and `summaries` hold the same information as text, as
+/// used by the built-in templates, and every template receives the `length`
+/// instruction, which is empty unless a length was chosen.
+#[derive(Debug, Clone)]
+pub struct Templates {
+    env: Environment<'static>,
+
+    length: &'static str,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Templates::builtin(Style::default(), None)
+    }
+}
+
+/// Returns the folder summaries of a tree, in path order, along with the
+/// risks of its files.
+fn repository_context(tree: &SummaryTree) -> (Vec<ChildSummary>, Vec<String>) {
+    let mut names = tree.folders.keys().collect::<Vec<&String>>();
+    names.sort();
+
+    let mut children = vec![];
+    let mut risks = vec![];
+    for name in names {
+        let folder = &tree.folders[name];
+
+        children.push(ChildSummary {
+            name: if name.is_empty() {
+                String::from(".")
+            } else {
+                name.clone()
+            },
+            summary: folder.summary.clone(),
+        });
+
+        for file in &folder.files {
+            risks.extend(file.risks.iter().map(|r| format!("{}: {}", file.name, r)));
+        }
+    }
+
+    (children, risks)
+}
+
+/// Describes the folders of a repository, its dependencies, risks and stats as text.
+fn describe_repository(
+    children: &[ChildSummary],
+    dependencies: &[Dependency],
+    risks: &[String],
+    stats: &RepositoryStats,
+) -> String {
+    let mut out = children
+        .iter()
+        .map(|c| format!("- {}: {}", c.name, c.summary))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    if !dependencies.is_empty() {
+        out.push_str(&format!("\nDependencies:\n{}", describe(dependencies)));
+    }
+
+    if !risks.is_empty() {
+        out.push_str(&format!("\nNotable risks:\n- {}", risks.join("\n- ")));
+    }
+
+    out.push_str(&format!("\n{}", stats.describe()));
+
+    out
+}
+
+impl Templates {
+    pub fn new(
+        file: String,
+        folder: String,
+        repository: String,
+        length: Option<Length>,
+    ) -> Result<Templates, Error> {
+        let mut env = Environment::new();
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+
+        for (name, source) in [
+            (FILE_TEMPLATE, file),
+            (FOLDER_TEMPLATE, folder),
+            (REPOSITORY_TEMPLATE, repository),
+        ] {
+            env.add_template_owned(name, source)
+                .map_err(|e| eyre!("invalid {} template: {}", name, e))?;
+        }
+
+        let templates = Templates {
+            env,
+            length: length.map(|l| l.instruction()).unwrap_or_default(),
+        };
+        templates.validate()?;
+
+        Ok(templates)
+    }
+
+    /// The built-in templates of a style.
+    pub fn builtin(style: Style, length: Option<Length>) -> Templates {
+        let (file, folder, repository) = style.prompts();
+
+        Templates::new(
+            file.to_string(),
+            folder.to_string(),
+            repository.to_string(),
+            length,
+        )
+        .expect("built-in templates are valid")
+    }
+
+    /// Loads `file.jinja`, `folder.jinja` and `repository.jinja` from `dir`,
+    /// falling back to the built-in template of `style` for any that are missing.
+    pub fn from_dir(dir: &Path, style: Style, length: Option<Length>) -> Result<Templates, Error> {
+        let entries = fs::read_dir(dir)
+            .map_err(|e| eyre!("failed to read prompts from {}: {}", dir.display(), e))?;
+
+        for entry in entries {
+            let path = entry?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+            if !TEMPLATE_NAMES
+                .iter()
+                .any(|template| name == format!("{}.{}", template, TEMPLATE_EXTENSION))
+            {
+                return Err(eyre!(
+                    "Unknown prompt template {}, expected one of: {}",
+                    path.display(),
+                    TEMPLATE_NAMES
+                        .map(|name| format!("{}.{}", name, TEMPLATE_EXTENSION))
+                        .join(", ")
+                ));
+            }
+        }
+
+        let read = |name: &str, default: &str| -> Result<String, Error> {
+            let path = dir.join(format!("{}.{}", name, TEMPLATE_EXTENSION));
+
+            match fs::read_to_string(&path) {
+                Ok(source) => Ok(source),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(default.to_string()),
+                Err(e) => Err(eyre!("failed to read {}: {}", path.display(), e)),
+            }
+        };
+
+        let (file, folder, repository) = style.prompts();
+
+        Templates::new(
+            read(FILE_TEMPLATE, file)?,
+            read(FOLDER_TEMPLATE, folder)?,
+            read(REPOSITORY_TEMPLATE, repository)?,
+            length,
+        )
+    }
+
+    /// Renders every template with sample values, so that syntax errors and
+    /// unknown variables are reported before anything is summarized.
+    pub fn validate(&self) -> Result<(), Error> {
+        let repository = RepositoryMeta::default();
+        let files = vec![FileSummaryResponse {
+            name: String::from("src/main.rs"),
+            summary: String::from("The entry point."),
+            purpose: String::from("Starts the program."),
+            symbols: vec![Symbol {
+                name: String::from("main"),
+                description: String::from("Runs the command line interface."),
+            }],
+            dependencies: vec![String::from("tokio")],
+            side_effects: vec![String::from("Reads environment variables.")],
+            risks: vec![String::from("Errors are not retried.")],
+            confidence: Some(0.9),
+        }];
+
+        let mut tree = SummaryTree::default();
+        tree.folders.insert(
+            String::from("src"),
+            FolderSummary {
+                summary: String::from("The source code."),
+                files: files.clone(),
+            },
+        );
+        tree.dependencies.push(Dependency {
+            name: String::from("tokio"),
+            version: String::from("1"),
+            resolved: Some(String::from("1.0.0")),
+            kind: DependencyKind::Normal,
+            ecosystem: Ecosystem::Cargo,
+            manifest: String::from("Cargo.toml"),
+            files: vec![String::from("src/main.rs")],
+            usage: String::from("Runs the async runtime."),
+        });
+
+        self.file(
+            &repository,
+            "src/main.rs",
+            "Rust",
+            "fn main() {}",
+            Some("fn main() { ... }"),
+        )?;
+        // History is only given with `--history`, so try with and without it.
+        self.folder(&repository, "src", &files, Some(&Activity::default()))?;
+        self.folder(&repository, "src", &files, None)?;
+        self.repository(
+            &repository,
+            &tree,
+            &RepositoryStats::default(),
+            Some(&HistorySummary::default()),
+        )?;
+        self.repository(&repository, &tree, &RepositoryStats::default(), None)?;
+
+        Ok(())
+    }
+
+    fn render(&self, name: &str, ctx: Value) -> Result<String, Error> {
+        self.env
+            .get_template(name)
+            .and_then(|template| template.render(ctx))
+            .map_err(|e| eyre!("failed to render {} template: {}", name, e))
+    }
+
+    pub fn file(
+        &self,
+        repository: &RepositoryMeta,
+        path: &str,
+        language: &str,
+        content: &str,
+        outline: Option<&str>,
+    ) -> Result<String, Error> {
+        let file = match outline {
+            Some(outline) if content.is_empty() => format!(
+                "(the file is too large to include, this is an outline of it)\n{}",
+                outline
+            ),
+            Some(outline) => format!("{}\n\nAn outline of the file:\n{}", content, outline),
+            None => content.to_string(),
+        };
+        let outline = outline.unwrap_or_default();
+
+        self.render(
+            FILE_TEMPLATE,
+            context! { repository, path, language, content, outline, file, length => self.length },
+        )
+    }
+
+    pub fn folder(
+        &self,
+        repository: &RepositoryMeta,
+        path: &str,
+        children: &[FileSummaryResponse],
+        history: Option<&Activity>,
+    ) -> Result<String, Error> {
+        let mut files = children
+            .iter()
+            .map(|c| c.describe())
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        if let Some(history) = history {
+            files.push_str(&format!("\n{}", history.describe()));
+        }
+
+        self.render(
+            FOLDER_TEMPLATE,
+            context! { repository, path, children, history, files, length => self.length },
+        )
+    }
+
+    pub fn repository(
+        &self,
+        repository: &RepositoryMeta,
+        tree: &SummaryTree,
+        stats: &RepositoryStats,
+        history: Option<&HistorySummary>,
+    ) -> Result<String, Error> {
+        let (children, risks) = repository_context(tree);
+        let dependencies = &tree.dependencies;
+        let mut summaries = describe_repository(&children, dependencies, &risks, stats);
+
+        if let Some(history) = history {
+            summaries.push_str(&format!("\n{}", history.describe()));
+        }
+
+        self.render(
+            REPOSITORY_TEMPLATE,
+            context! { repository, children, dependencies, risks, stats, history, summaries, length => self.length },
+        )
+    }
+}
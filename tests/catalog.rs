@@ -0,0 +1,121 @@
+mod common;
+
+use common::{mock_layer, test_dir};
+use gitsum::catalog::{Batch, RepositoryFilter, RepositoryInfo, Target};
+use gitsum::config::{Config, ConfigLayer, OutputFormat};
+use std::fs;
+
+/// A batch over the recorded `octo` organization, summarizing with the mock
+/// provider into a fresh directory under `name`.
+fn batch(name: &str, filter: RepositoryFilter) -> Batch {
+    let dir = test_dir(name);
+
+    let config = Config::resolve(vec![ConfigLayer {
+        output: Some(OutputFormat::Json),
+        ..mock_layer(&dir.join("cache"))
+    }])
+    .unwrap();
+
+    Batch::new(config, filter, &dir.join("index"), 2, false)
+}
+
+fn names(targets: &[Target]) -> Vec<&str> {
+    targets
+        .iter()
+        .map(|t| t.repository.full_name.as_str())
+        .collect()
+}
+
+#[tokio::test]
+async fn lists_and_filters_organization_repositories() {
+    let targets = batch("gitsum-org", RepositoryFilter::default())
+        .org("octo")
+        .await
+        .unwrap();
+    assert_eq!(names(&targets), vec!["octo/hello", "octo/website"]);
+    assert_eq!(targets[1].branch, "gh-pages");
+
+    let cli = RepositoryFilter {
+        topics: vec![String::from("cli")],
+        ..Default::default()
+    };
+    let targets = batch("gitsum-org", cli.clone()).org("octo").await.unwrap();
+    assert_eq!(names(&targets), vec!["octo/hello"]);
+
+    let everything = RepositoryFilter {
+        include_archived: true,
+        include_forks: true,
+        ..cli
+    };
+    let targets = batch("gitsum-org", everything).org("octo").await.unwrap();
+    assert_eq!(
+        names(&targets),
+        vec!["octo/hello", "octo/hello-fork", "octo/legacy"]
+    );
+}
+
+#[tokio::test]
+async fn falls_back_to_users_only_when_there_is_no_organization() {
+    let targets = batch("gitsum-user", RepositoryFilter::default())
+        .org("alice")
+        .await
+        .unwrap();
+    assert_eq!(names(&targets), vec!["alice/dotfiles"]);
+
+    let error = batch("gitsum-user", RepositoryFilter::default())
+        .org("locked")
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("GitHub responded 401"));
+}
+
+#[tokio::test]
+async fn writes_reports_and_an_index() {
+    let batch = batch("gitsum-batch", RepositoryFilter::default());
+
+    let list = std::env::temp_dir().join("gitsum-batch-repos.txt");
+    fs::write(&list, "# Services\nocto/hello  # the greeter\n\n").unwrap();
+
+    let mut targets = batch.from_file(&list).await.unwrap();
+    targets.push(Target {
+        repository: RepositoryInfo {
+            full_name: String::from("octo/missing"),
+            ..Default::default()
+        },
+        branch: String::from("main"),
+    });
+
+    let catalog = batch.run(targets).await.unwrap();
+    assert_eq!(catalog.entries.len(), 1);
+    assert!(catalog.entries[0].summary.starts_with("Mock summary"));
+    assert_eq!(catalog.failures.len(), 1);
+    assert_eq!(catalog.failures[0].repository, "octo/missing");
+
+    let index = fs::read_to_string(batch.output_dir.join("index.md")).unwrap();
+    assert!(index.contains("| [octo/hello](octo/hello.md) |"));
+    assert!(index.contains("_Prints a greeting_"));
+    assert!(index.contains("- Topics: cli, rust"));
+    assert!(index.contains("- Tech stack: Cargo, Serde"));
+    assert!(index.contains("| octo/missing | no recorded response"));
+
+    let report = fs::read_to_string(batch.output_dir.join("octo/hello.md")).unwrap();
+    assert!(report.starts_with("# octo/hello\n"));
+}
+
+#[test]
+fn splits_concurrency_across_jobs() {
+    let config = |concurrency| {
+        Config::resolve(vec![ConfigLayer {
+            concurrency: Some(concurrency),
+            ..Default::default()
+        }])
+        .unwrap()
+    };
+    let dir = std::env::temp_dir().join("gitsum-batch-jobs");
+
+    let batch = Batch::new(config(8), RepositoryFilter::default(), &dir, 3, false);
+    assert_eq!((batch.jobs, batch.config.concurrency), (3, 2));
+
+    let batch = Batch::new(config(2), RepositoryFilter::default(), &dir, 4, false);
+    assert_eq!((batch.jobs, batch.config.concurrency), (2, 1));
+}
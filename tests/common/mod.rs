@@ -0,0 +1,42 @@
+// Each test binary uses some of these.
+#![allow(dead_code)]
+
+use gitsum::config::{Config, ConfigLayer};
+use gitsum::gpt::MOCK_PROVIDER;
+use gitsum::http::{self, Transport};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Recorded GitHub responses for `octo/hello`, a two folder Rust repository.
+pub fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/hello")
+}
+
+/// Replays GitHub responses from the fixtures instead of sending them.
+pub fn replay() {
+    http::set_transport(Transport::Replay(fixtures()));
+}
+
+/// A temporary directory for one test, removed if a previous run left it.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Replays the fixtures and returns a layer that summarizes with the mock
+/// provider, caching into `cache_dir`.
+pub fn mock_layer(cache_dir: &Path) -> ConfigLayer {
+    replay();
+
+    ConfigLayer {
+        provider: Some(String::from(MOCK_PROVIDER)),
+        cache_dir: Some(cache_dir.to_path_buf()),
+        ..Default::default()
+    }
+}
+
+/// The config of `mock_layer`.
+pub fn mock_config(cache_dir: &Path) -> Config {
+    Config::resolve(vec![mock_layer(cache_dir)]).unwrap()
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::{mock_config, test_dir};
+use gitsum::catalog::{self, summarize_cached};
+use gitsum::compare::Comparison;
+
+#[tokio::test]
+async fn compares_two_repositories() {
+    let config = mock_config(&test_dir("gitsum-compare"));
+
+    let first = catalog::lookup("", "octo/hello").await.unwrap();
+    let second = catalog::lookup("", "octo/hello@main").await.unwrap();
+    let (first_contents, first_summaries) = summarize_cached(&config, &first, false).await.unwrap();
+    let (second_contents, second_summaries) =
+        summarize_cached(&config, &second, false).await.unwrap();
+
+    let comparison = Comparison::new(
+        &config.open_ai_key().unwrap(),
+        &config.repository,
+        (&first, &first_contents, &first_summaries),
+        (&second, &second_contents, &second_summaries),
+    )
+    .await
+    .unwrap();
+
+    assert!(comparison.first.maturity.readme);
+    assert_eq!(comparison.first.maturity.dependencies, 2);
+    assert_eq!(
+        comparison.shared_dependencies,
+        vec!["pretty_assertions", "serde", "toml"]
+    );
+
+    let report = comparison.render();
+    assert!(report.starts_with("# octo/hello vs octo/hello\n\nMock summary"));
+    assert!(report.contains("| Tech stack | Cargo, Serde | Cargo, Serde |"));
+    assert!(report.contains("## Overlapping functionality\n\n- Mock overlap"));
+    assert!(report.contains("| Mock aspect "));
+    assert!(report.contains("| Mock first "));
+    assert!(report.contains("## Recommendation\n\nMock recommendation"));
+}
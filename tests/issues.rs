@@ -0,0 +1,75 @@
+mod common;
+
+use chrono::{DateTime, Utc};
+use common::{mock_config, test_dir};
+use gitsum::catalog::{self, summarize_cached};
+use gitsum::issues::{fetch_issues, parse_since, IssueDigest};
+
+#[tokio::test]
+async fn digests_issues_into_themes() {
+    let config = mock_config(&test_dir("gitsum-issues"));
+
+    let now = "2024-06-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+    let since = now - parse_since("30d").unwrap();
+
+    let issues = fetch_issues("", "octo/hello", since, 2, &config.redactor)
+        .await
+        .unwrap();
+    let numbers = issues.iter().map(|i| i.number).collect::<Vec<u64>>();
+    assert_eq!(numbers, vec![3, 1, 2, 5]);
+    assert_eq!(issues[1].discussion.len(), 4);
+    assert_eq!(issues[1].labels, vec!["bug"]);
+    assert!(issues[1]
+        .body
+        .contains("GITHUB_TOKEN=[REDACTED:github-token]"));
+    assert!(issues[2].discussion[0].body.ends_with("[REDACTED:email]."));
+    assert_eq!(
+        issues[3].title,
+        "Typo in the README, reported by [REDACTED:email]"
+    );
+
+    let target = catalog::lookup("", "octo/hello").await.unwrap();
+    let (_, summaries) = summarize_cached(&config, &target, false).await.unwrap();
+
+    let digest = IssueDigest::new(
+        &config.open_ai_key().unwrap(),
+        &config.repository,
+        "octo/hello",
+        issues.clone(),
+        Some(&summaries),
+        since,
+    )
+    .await
+    .unwrap();
+    assert_eq!((digest.open, digest.closed), (3, 1));
+    let folders = digest
+        .themes
+        .iter()
+        .map(|theme| theme.folders.clone())
+        .collect::<Vec<Vec<String>>>();
+    assert_eq!(folders, vec![vec!["."], vec!["src"]]);
+
+    let report = digest.render();
+    assert!(report.starts_with(
+        "# Issue digest for octo/hello\n\n3 open issues, and 1 closed since 2024-05-02."
+    ));
+    assert!(report.contains("1. Mock summary"));
+    assert!(report.contains("([#3](https://github.com/octo/hello/issues/3), [#1](https://github.com/octo/hello/issues/1)"));
+    assert!(report.contains("## Possible duplicates\n\n- [#3]"));
+    assert!(report.contains(
+        "| [#3](https://github.com/octo/hello/issues/3) | Support greetings in other languages | 5 | 7 | 2023-11-01 |"
+    ));
+    assert!(report.contains("## Redactions\n\n3 values were redacted"));
+    assert!(report.contains(
+        "| #1 | github-token | 1 |\n| #2 comment 1 | email | 1 |\n| #5 title | email | 1 |"
+    ));
+
+    let unlinked = IssueDigest::new("", &config.repository, "octo/hello", issues, None, since)
+        .await
+        .unwrap();
+    assert!(unlinked.themes[0].folders.is_empty());
+
+    assert!(parse_since("3x").is_err());
+    assert!(parse_since("30é").is_err());
+    assert!(parse_since("").is_err());
+}
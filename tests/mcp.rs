@@ -0,0 +1,147 @@
+mod common;
+
+use common::{mock_config, test_dir};
+use gitsum::cache::Cache;
+use gitsum::mcp::McpServer;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// An MCP server summarizing with the mock provider, replaying GitHub
+/// responses for `octo/hello` and caching into `cache_dir`.
+fn server(cache_dir: &Path) -> McpServer {
+    McpServer::new(mock_config(cache_dir))
+}
+
+async fn request(server: &McpServer, method: &str, params: Value) -> Value {
+    let message = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+
+    server.handle(&message.to_string()).await.unwrap()
+}
+
+/// Calls a tool, returning its parsed result and whether it failed.
+async fn call(server: &McpServer, name: &str, arguments: Value) -> (Value, bool) {
+    let response = request(
+        server,
+        "tools/call",
+        json!({ "name": name, "arguments": arguments }),
+    )
+    .await;
+    let result = &response["result"];
+    let text = result["content"][0]["text"].as_str().unwrap();
+
+    (
+        serde_json::from_str(text).unwrap_or(json!(text)),
+        result["isError"] == true,
+    )
+}
+
+#[tokio::test]
+async fn lists_tools() {
+    let server = server(&test_dir("gitsum-mcp-tools-test"));
+
+    let initialized = request(
+        &server,
+        "initialize",
+        json!({ "protocolVersion": "2025-03-26" }),
+    )
+    .await;
+    assert_eq!(initialized["result"]["protocolVersion"], "2025-03-26");
+
+    let tools = request(&server, "tools/list", json!({})).await;
+    let names = tools["result"]["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tool| tool["name"].as_str().unwrap())
+        .collect::<Vec<&str>>();
+    assert_eq!(
+        names,
+        vec![
+            "summarize_repo",
+            "summarize_folder",
+            "summarize_file",
+            "search_repo",
+            "get_cached_summary"
+        ]
+    );
+
+    let notification = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+    assert!(server.handle(&notification.to_string()).await.is_none());
+
+    let unknown = request(&server, "prompts/list", json!({})).await;
+    assert_eq!(unknown["error"]["code"], -32601);
+}
+
+#[tokio::test]
+async fn summarizes_and_serves_cached_summaries() {
+    let server = server(&test_dir("gitsum-mcp-summaries-test"));
+    let repo = json!({ "username": "octo", "repo": "hello", "branch": "main" });
+
+    let (missing, failed) = call(&server, "get_cached_summary", repo.clone()).await;
+    assert!(failed, "{}", missing);
+
+    let (tree, failed) = call(&server, "summarize_repo", repo.clone()).await;
+    assert!(!failed, "{}", tree);
+    assert_eq!(tree["folders"].as_object().unwrap().len(), 2);
+
+    let mut file = repo.clone();
+    file["path"] = json!("src/main.rs");
+    let (summary, failed) = call(&server, "get_cached_summary", file).await;
+    assert!(!failed, "{}", summary);
+    assert_eq!(summary["name"], "src/main.rs");
+
+    let resources = request(&server, "resources/list", json!({})).await;
+    let uri = resources["result"]["resources"][0]["uri"].clone();
+    assert_eq!(uri, "gitsum://octo/hello/main/report");
+
+    let report = request(&server, "resources/read", json!({ "uri": uri })).await;
+    let text = report["result"]["contents"][0]["text"].as_str().unwrap();
+    assert!(text.starts_with("# octo/hello"));
+
+    let mut traversal = repo.clone();
+    traversal["branch"] = json!("../../..");
+    let (_, failed) = call(&server, "get_cached_summary", traversal).await;
+    assert!(failed);
+
+    let uri = "gitsum://octo/hello/../../../report";
+    let report = request(&server, "resources/read", json!({ "uri": uri })).await;
+    assert_eq!(report["error"]["code"], -32602);
+}
+
+#[tokio::test]
+async fn serves_branches_with_slashes() {
+    let cache_dir = test_dir("gitsum-mcp-branch-test");
+    let server = server(&cache_dir);
+    let repo = json!({ "username": "octo", "repo": "hello", "branch": "main" });
+    let (_, failed) = call(&server, "summarize_repo", repo).await;
+    assert!(!failed);
+
+    let main = Cache::new(&cache_dir, "octo", "hello", "main");
+    let feature = Cache::new(&cache_dir, "octo", "hello", "feature/x");
+    feature
+        .save_contents(&main.load_contents().unwrap())
+        .unwrap();
+    feature
+        .save_summaries(&main.load_summaries().unwrap())
+        .unwrap();
+
+    let resources = request(&server, "resources/list", json!({})).await;
+    let uris = resources["result"]["resources"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|resource| resource["uri"].as_str().unwrap())
+        .collect::<Vec<&str>>();
+    assert_eq!(
+        uris,
+        vec![
+            "gitsum://octo/hello/feature/x/report",
+            "gitsum://octo/hello/main/report"
+        ]
+    );
+
+    let uri = "gitsum://octo/hello/feature/x/report";
+    let report = request(&server, "resources/read", json!({ "uri": uri })).await;
+    let text = report["result"]["contents"][0]["text"].as_str().unwrap();
+    assert!(text.starts_with("# octo/hello"));
+}
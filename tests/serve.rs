@@ -0,0 +1,147 @@
+mod common;
+
+use common::{mock_layer, test_dir};
+use gitsum::cache::{Cache, SummaryTree};
+use gitsum::config::Config;
+use gitsum::serve::Server;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Serves the API on a free port, summarizing with the mock provider into
+/// `cache_dir` and replaying GitHub responses for `octo/hello`. Returns its
+/// base url.
+async fn serve(cache_dir: &Path) -> String {
+    let layer = mock_layer(cache_dir);
+    let config = Config::resolve(vec![layer.clone()]).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let router = Server::new(None, layer, config, 1, 4).router();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    url
+}
+
+#[tokio::test]
+async fn runs_submitted_jobs() {
+    let url = serve(&test_dir("gitsum-serve-jobs")).await;
+    let client = Client::new();
+
+    let submitted = client
+        .post(format!("{}/jobs", url))
+        .json(&json!({ "source": "octo/hello", "ref": "main", "options": { "style": "api" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(submitted.status(), StatusCode::ACCEPTED);
+    let id = submitted.json::<Value>().await.unwrap()["id"].clone();
+
+    let mut job = Value::Null;
+    for _ in 0..100 {
+        job = client
+            .get(format!("{}/jobs/{}", url, id))
+            .send()
+            .await
+            .unwrap()
+            .json::<Value>()
+            .await
+            .unwrap();
+
+        if job["status"] == "done" || job["status"] == "failed" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(job["status"], "done", "{}", job);
+    assert_eq!(job["progress"]["files_done"], 5);
+    assert_eq!(job["progress"]["files_total"], 5);
+
+    let summary = client
+        .get(format!("{}/jobs/{}/summary", url, id))
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert!(summary["summary"]
+        .as_str()
+        .unwrap()
+        .starts_with("Mock summary"));
+
+    let repos = client
+        .get(format!("{}/repos", url))
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap();
+    assert!(repos
+        .as_array()
+        .unwrap()
+        .contains(&json!({ "username": "octo", "repo": "hello", "branch": "main" })));
+}
+
+#[tokio::test]
+async fn rejects_invalid_jobs() {
+    let url = serve(&test_dir("gitsum-serve-invalid")).await;
+    let client = Client::new();
+
+    let invalid = client
+        .post(format!("{}/jobs", url))
+        .json(&json!({ "source": "octo", "ref": "main" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+
+    for (source, reference) in [("octo/..", "main"), ("octo/hello", "../../etc")] {
+        let traversal = client
+            .post(format!("{}/jobs", url))
+            .json(&json!({ "source": source, "ref": reference }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(traversal.status(), StatusCode::BAD_REQUEST);
+    }
+
+    let traversal = client
+        .get(format!("{}/repos/octo/hello/..%2F..%2F..", url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(traversal.status(), StatusCode::BAD_REQUEST);
+
+    let missing = client.get(format!("{}/jobs/42", url)).send().await.unwrap();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn serves_branches_with_slashes() {
+    let dir = test_dir("gitsum-serve-branch");
+    let url = serve(&dir).await;
+
+    let tree = SummaryTree {
+        summary: String::from("A feature branch."),
+        ..Default::default()
+    };
+    Cache::new(&dir, "octo", "hello", "feature/x")
+        .save_summaries(&tree)
+        .unwrap();
+
+    let summary = Client::new()
+        .get(format!("{}/repos/octo/hello/feature/x", url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(summary.status(), StatusCode::OK);
+    assert_eq!(
+        summary.json::<Value>().await.unwrap()["summary"],
+        "A feature branch."
+    );
+}
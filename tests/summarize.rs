@@ -0,0 +1,366 @@
+mod common;
+
+use common::{replay, test_dir};
+use gitsum::cache::{Failure, FailureKind};
+use gitsum::config::{Config, ConfigLayer, OutputFormat};
+use gitsum::git::{Folder, Git, RepositoryContent};
+use gitsum::gpt::{self, ChatMessage, ChatRequestBuilder, ChatSettings, MOCK_PROVIDER};
+use gitsum::history::CodeOwners;
+use gitsum::journal::Journal;
+use gitsum::prompts::Length;
+use gitsum::report::Report;
+use reqwest::Client;
+use std::sync::Arc;
+
+/// A `Git` for `octo/hello` that summarizes with the mock provider and
+/// replays GitHub responses from the fixtures.
+async fn hello() -> Git {
+    hello_with(ConfigLayer {
+        provider: Some(String::from(MOCK_PROVIDER)),
+        ..Default::default()
+    })
+    .await
+}
+
+/// A `Git` for `octo/hello` configured with `layer`. Requests to OpenAI
+/// aren't in the fixtures, so they fail.
+async fn hello_with(layer: ConfigLayer) -> Git {
+    replay();
+
+    let config = Config::resolve(vec![ConfigLayer {
+        output: Some(OutputFormat::Json),
+        ..layer
+    }])
+    .unwrap();
+
+    let mut git = Git::new("", MOCK_PROVIDER, "octo", "hello", "main").with_config(config);
+    git.get_contents(Client::new()).await.unwrap();
+
+    git
+}
+
+#[tokio::test]
+async fn fetches_contents_from_fixtures() {
+    let git = hello().await;
+    let folders = &git.repository_content.folders;
+
+    let mut names = folders.keys().cloned().collect::<Vec<String>>();
+    names.sort();
+    assert_eq!(names, vec!["", "src"]);
+
+    assert_eq!(folders[""].files.len(), 3);
+    assert_eq!(folders["src"].files.len(), 2);
+    assert!(folders["src"].files["src/main.rs"]
+        .content
+        .contains("fn main()"));
+}
+
+#[tokio::test]
+async fn redacts_contents_when_fetched() {
+    let git = hello().await;
+    let env = &git.repository_content.folders[""].files[".env"].content;
+
+    assert!(!env.contains("ghp_"));
+    assert!(!env.contains("jane.doe@example.com"));
+    assert!(env.contains("[REDACTED:github-token]"));
+
+    let kinds = git
+        .repository_content
+        .redactions
+        .iter()
+        .map(|r| (r.file.as_str(), r.line, r.kind.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![(".env", 1, "github-token"), (".env", 2, "email")]
+    );
+}
+
+#[tokio::test]
+async fn summarizes_a_repository_offline() {
+    let git = hello().await;
+    let tree = git.summarize_repository().await.unwrap();
+
+    assert!(tree.summary.starts_with("Mock summary"));
+    assert_eq!(tree.folders.len(), 2);
+
+    let mut files = tree
+        .folders
+        .values()
+        .flat_map(|folder| &folder.files)
+        .map(|file| file.name.as_str())
+        .collect::<Vec<&str>>();
+    files.sort();
+    assert_eq!(
+        files,
+        vec![
+            ".env",
+            "Cargo.toml",
+            "README.md",
+            "src/config.rs",
+            "src/main.rs"
+        ]
+    );
+
+    let dependencies = tree
+        .dependencies
+        .iter()
+        .map(|d| (d.name.as_str(), d.usage.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        dependencies,
+        vec![
+            ("serde", "Mock usage of serde."),
+            ("toml", "Mock usage of toml.")
+        ]
+    );
+}
+
+#[tokio::test]
+async fn tracks_progress_and_tokens() {
+    let git = hello().await;
+    git.summarize_repository().await.unwrap();
+
+    let progress = git.progress.view();
+    assert_eq!(progress.files_total, 5);
+    assert_eq!(progress.files_done, 5);
+    assert!(gpt::tokens_used() > 0);
+}
+
+#[tokio::test]
+async fn keeps_going_past_failures() {
+    let git = hello_with(ConfigLayer {
+        keep_going: Some(true),
+        ..Default::default()
+    })
+    .await;
+    let tree = git.summarize_repository().await.unwrap();
+
+    let failures = tree
+        .failures
+        .iter()
+        .map(|f| (f.kind, f.path.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(failures.len(), 9);
+    assert!(failures.contains(&(FailureKind::File, "src/main.rs")));
+    assert!(failures.contains(&(FailureKind::Folder, "src")));
+    assert!(failures.contains(&(FailureKind::Repository, "")));
+    assert!(tree.failures[0].error.contains("no recorded response"));
+
+    let error = hello_with(ConfigLayer::default())
+        .await
+        .summarize_repository()
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("no recorded response"));
+
+    // A failure keeps the start of its error on one line, not a whole reply.
+    let reply = "fn main() {}\n".repeat(100);
+    let failure = Failure::new(
+        FailureKind::File,
+        "src/main.rs",
+        &eyre::eyre!("failed to parse gpt response: {}", reply),
+    );
+    assert!(failure
+        .error
+        .starts_with("failed to parse gpt response: fn main() {}"));
+    assert!(!failure.error.contains('\n'));
+    assert!(failure.error.len() < reply.len());
+}
+
+#[tokio::test]
+async fn resumes_from_the_journal() {
+    let dir = test_dir("gitsum-resume");
+    let path = dir.join("journal.jsonl");
+
+    let journal = Arc::new(Journal::open(&path, false).unwrap());
+    let (_, files) = hello()
+        .await
+        .with_journal(journal)
+        .summarize_folder("src")
+        .await
+        .unwrap();
+
+    // Summaries are marked in the journal, to tell which ones are reused.
+    let marked = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| match line.starts_with("{\"folder\"") {
+            true => line.replace("Mock summary", "Journaled summary"),
+            false => line.replace("\"confidence\":1.0", "\"confidence\":0.5"),
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    std::fs::write(&path, marked).unwrap();
+
+    let journal = Arc::new(Journal::open(&path, true).unwrap());
+    assert_eq!(journal.resumed_files(), 2);
+    let (resumed_folder, resumed_files) = hello()
+        .await
+        .with_journal(journal.clone())
+        .summarize_folder("src")
+        .await
+        .unwrap();
+
+    assert!(resumed_folder.summary.starts_with("Journaled summary"));
+    assert_eq!(resumed_files.len(), files.len());
+    assert!(resumed_files.iter().all(|f| f.confidence == Some(0.5)));
+
+    // Another length gives other prompts, so nothing is reused.
+    let journal = Arc::new(Journal::open(&path, true).unwrap());
+    let (changed_folder, changed_files) = hello_with(ConfigLayer {
+        provider: Some(String::from(MOCK_PROVIDER)),
+        length: Some(Length::Short),
+        ..Default::default()
+    })
+    .await
+    .with_journal(journal.clone())
+    .summarize_folder("src")
+    .await
+    .unwrap();
+
+    assert!(changed_folder.summary.starts_with("Mock summary"));
+    assert!(changed_files.iter().all(|f| f.confidence == Some(1.0)));
+
+    journal.remove().unwrap();
+    assert!(!path.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn reproducible_runs_give_identical_reports() {
+    let layer = || ConfigLayer {
+        provider: Some(String::from(MOCK_PROVIDER)),
+        reproducible: Some(true),
+        concurrency: Some(4),
+        ..Default::default()
+    };
+
+    let mut reports = vec![];
+    for _ in 0..2 {
+        let git = hello_with(layer()).await;
+        let tree = git.summarize_repository().await.unwrap();
+
+        reports.push((
+            serde_json::to_string(&tree).unwrap(),
+            Report::new("octo/hello", &git.repository_content, &tree).render(),
+        ));
+    }
+    assert_eq!(reports[0], reports[1]);
+    assert!(reports[0].1.ends_with("|\n"));
+    assert!(reports[0]
+        .1
+        .contains("| serde | 1 | Mock usage of serde. | src/config.rs |"));
+
+    let settings = hello_with(layer()).await.config.file;
+    assert_eq!(settings.temperature, 0.0);
+    assert_eq!(settings.seed, Some(0));
+
+    let request = ChatRequestBuilder::default().settings(&settings).build();
+    assert_eq!(serde_json::to_value(&request).unwrap()["seed"], 0);
+}
+
+#[tokio::test]
+async fn summarizes_history_and_ownership() {
+    let mut git = hello_with(ConfigLayer {
+        provider: Some(String::from(MOCK_PROVIDER)),
+        history: Some(true),
+        ..Default::default()
+    })
+    .await;
+
+    let history = &git.repository_content.history;
+    assert_eq!(history.commits.len(), 3);
+    assert_eq!(history.commits[0].author, "@alice");
+    assert_eq!(history.commits[0].message, "Document the greeting");
+    assert_eq!(
+        history.commits[1].message,
+        "Bump serde for [REDACTED:email]"
+    );
+    assert_eq!(history.commits[2].author, "Carol");
+    assert!(git
+        .repository_content
+        .redactions
+        .iter()
+        .any(|r| r.file == "commit b72d04a" && r.kind == "email"));
+
+    let src = history.activity("src");
+    assert_eq!(src.commits, 1);
+    assert_eq!(src.recent_messages, vec!["Initial commit"]);
+
+    git.repository_content.history.owners =
+        CodeOwners::parse("# Owners\n* @octo/maintainers\n/src/ @alice @octo/core\n*.toml @bob\n");
+    let owners = &git.repository_content.history.owners;
+    assert_eq!(owners.owners("src/main.rs"), vec!["@alice", "@octo/core"]);
+    assert_eq!(owners.owners("Cargo.toml"), vec!["@bob"]);
+    assert_eq!(owners.owners("README.md"), vec!["@octo/maintainers"]);
+
+    // A folder no commit read changes isn't known to be stale.
+    let mut contents = RepositoryContent::default();
+    for name in ["docs", "src"] {
+        contents.folders.insert(name.to_string(), Folder::new(name));
+    }
+    let stale = git.repository_content.history.summarize(&contents).stale;
+    assert_eq!(
+        stale.iter().map(|a| a.path.as_str()).collect::<Vec<&str>>(),
+        vec!["src"]
+    );
+
+    let tree = git.summarize_repository().await.unwrap();
+    let report = Report::new("octo/hello", &git.repository_content, &tree).render();
+
+    assert!(report.contains("## Hot spots"));
+    assert!(report.contains(
+        "| README.md | 2 | 2024-06-01 | Document the greeting; Bump serde for [REDACTED:email] |"
+    ));
+    assert!(report.contains("## Stale areas"));
+    assert!(report.contains("| src | 2023-01-10 |"));
+    assert!(report.contains("## Who to ask"));
+    assert!(report.contains("| src | @alice, @octo/core | Carol (1) |"));
+}
+
+#[tokio::test]
+async fn mock_answers_are_deterministic() {
+    let settings = ChatSettings {
+        provider: String::from(MOCK_PROVIDER),
+        ..Default::default()
+    };
+    let request = |prompt: &str| {
+        ChatRequestBuilder::default()
+            .conversation(vec![ChatMessage::user(prompt)])
+            .settings(&settings)
+            .build()
+    };
+
+    let first = request("Summarize this file")
+        .send("", Client::new())
+        .await
+        .unwrap();
+    let again = request("Summarize this file")
+        .send("", Client::new())
+        .await
+        .unwrap();
+    let other = request("Summarize that file")
+        .send("", Client::new())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        first.choices[0].message.content,
+        again.choices[0].message.content
+    );
+    assert_ne!(
+        first.choices[0].message.content,
+        other.choices[0].message.content
+    );
+}
+
+#[tokio::test]
+async fn replay_fails_for_unrecorded_requests() {
+    replay();
+
+    let mut git = Git::new("", MOCK_PROVIDER, "octo", "missing", "main");
+    let error = git.get_contents(Client::new()).await.unwrap_err();
+
+    assert!(error.to_string().contains("no recorded response"));
+}
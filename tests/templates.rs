@@ -0,0 +1,40 @@
+use gitsum::prompts::Style;
+use gitsum::templates::Templates;
+use std::fs;
+
+/// Builds templates with `folder` as the folder template and the built-in
+/// file and repository templates.
+fn with_folder(folder: &str) -> eyre::Result<Templates> {
+    let (file, _, repository) = Style::default().prompts();
+
+    Templates::new(
+        file.to_string(),
+        folder.to_string(),
+        repository.to_string(),
+        None,
+    )
+}
+
+#[test]
+fn templates_must_handle_missing_history() {
+    assert!(with_folder("{{ path }} changed {{ history.commits }} times").is_err());
+    assert!(with_folder(
+        "{{ path }}{% if history %} changed {{ history.commits }} times{% endif %}"
+    )
+    .is_ok());
+}
+
+#[test]
+fn prompt_directories_only_hold_jinja_templates() {
+    let dir = std::env::temp_dir().join(format!("gitsum-prompts-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    fs::write(dir.join("file.jinja"), "Summarize {{ path }}.").unwrap();
+    assert!(Templates::from_dir(&dir, Style::default(), None).is_ok());
+
+    fs::write(dir.join("folder.txt"), "Summarize {{ path }}.").unwrap();
+    assert!(Templates::from_dir(&dir, Style::default(), None).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}